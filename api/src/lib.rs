@@ -12,7 +12,9 @@ use actix_web::{
     App as ActixApp, HttpServer,
 };
 use cobase::{command::Command, query::Query, storage::Storage};
+use cobase_cluster::{Cluster, ClusterOptions};
 use evento::PgEngine;
+use opendal::Operator;
 use serde::Deserialize;
 use sqlx::PgPool;
 use std::time::SystemTime;
@@ -51,6 +53,7 @@ pub struct EventoOptions {
 pub struct AppOptions {
     pub zone: String,
     pub listen: String,
+    pub cluster: ClusterOptions,
     pub jwks: JwksOptions,
     pub pikav: PikavOptions,
     pub dsn: String,
@@ -64,6 +67,7 @@ pub struct AppOptions {
 pub struct AppState {
     pub cmd: Addr<Command>,
     pub query: Addr<Query>,
+    pub storage: Operator,
     pub public_folder: String,
 }
 
@@ -122,7 +126,9 @@ impl App {
             .data(pikva_client.clone())
             .data(storage.clone())
             .subscribe(cobase::room::projection::rooms())
-            .subscribe(cobase::warehouse::projection::warehouse_data());
+            .subscribe(cobase::warehouse::projection::warehouse_data())
+            .subscribe(cobase::warehouse::projection::warehouse_fields())
+            .subscribe(cobase::warehouse::projection::warehouse_exports());
 
         let producer = match evento.run(self.options.evento.delay).await {
             Ok(p) => p,
@@ -133,9 +139,22 @@ impl App {
             }
         };
 
-        let cmd = Command::new(evento, producer, storage).start();
+        let cmd = Command::new(evento, producer, storage.clone()).start();
         let query = Query::new(pool).start();
 
+        let cluster = Cluster::new(
+            self.options.cluster.clone(),
+            cmd.clone(),
+            query.clone(),
+            storage.clone(),
+        );
+
+        actix_web::rt::spawn(async move {
+            if let Err(e) = cluster.serve().await {
+                error!("{e}");
+            }
+        });
+
         let mut openapi = openapi::ApiDoc::openapi();
         openapi.servers = self.options.openapi.servers.clone();
 
@@ -153,12 +172,15 @@ impl App {
                 .app_data(web::Data::new(AppState {
                     cmd: cmd.clone(),
                     query: query.clone(),
+                    storage: storage.clone(),
                     public_folder: public_folder.to_owned(),
                 }))
                 .app_data(Data::new(jwks_client.clone()))
                 .app_data(Data::new(openapi.clone()))
                 .service(
+                    // Warehouses are nested under /rooms, so they must be matched first
                     web::scope("/api")
+                        .service(warehouse::room_scope())
                         .service(room::scope())
                         .service(warehouse::scope()),
                 )
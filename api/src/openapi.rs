@@ -6,6 +6,7 @@ use utoipa::{IntoParams, ToSchema};
 use crate::room;
 use crate::warehouse;
 
+use crate::room::Room;
 use crate::warehouse::WarehouseData;
 
 #[derive(Default, Serialize, Deserialize, Debug, IntoParams)]
@@ -22,14 +23,17 @@ pub struct QueryArgs {
 }
 
 #[derive(Default, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
-#[aliases(QueryResultWarehouseData = QueryResult<EdgeWarehouseData>)]
+#[aliases(
+    QueryResultWarehouseData = QueryResult<EdgeWarehouseData>,
+    QueryResultRoom = QueryResult<EdgeRoom>
+)]
 pub struct QueryResult<N> {
     pub edges: Vec<N>,
     pub page_info: PageInfo,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, ToSchema)]
-#[aliases(EdgeWarehouseData = Edge<WarehouseData>)]
+#[aliases(EdgeWarehouseData = Edge<WarehouseData>, EdgeRoom = Edge<Room>)]
 pub struct Edge<N> {
     pub cursor: String,
     pub node: N,
@@ -51,8 +55,65 @@ pub struct CommandResponse {
 
 #[derive(OpenApi)]
 #[openapi(
-    paths(room::create_room, room::list_rooms, warehouse::import_data, warehouse::list_warehouses_data),
-    components(schemas(room::Room, room::CreateRoomInput, warehouse::ImportDataWarehouseInput, WarehouseData, CommandResponse, QueryResultWarehouseData, PageInfo, EdgeWarehouseData)),
+    paths(
+        room::create_room,
+        room::list_rooms,
+        room::get_room,
+        room::rename_room,
+        room::archive_room,
+        room::delete_room,
+        room::list_room_members,
+        room::invite_room_member,
+        room::join_room,
+        room::remove_room_member,
+        warehouse::list_warehouses,
+        warehouse::create_warehouse,
+        warehouse::list_room_warehouses,
+        warehouse::list_warehouses_data,
+        warehouse::search_warehouse_data,
+        warehouse::get_warehouse_data,
+        warehouse::delete_warehouse_data,
+        warehouse::patch_warehouse_data,
+        warehouse::get_warehouse_schema,
+        warehouse::define_warehouse_schema,
+        warehouse::import_data,
+        warehouse::dry_run_import_data,
+        warehouse::import_csv,
+        warehouse::import_file,
+        warehouse::export_warehouse_data,
+        warehouse::get_warehouse_export,
+        warehouse::get_warehouse_import,
+    ),
+    components(schemas(
+        room::Room,
+        room::CreateRoomInput,
+        room::RenameRoomInput,
+        room::RoomMember,
+        room::InviteRoomMemberInput,
+        warehouse::Warehouse,
+        warehouse::CreateWarehouseInput,
+        warehouse::ImportDataWarehouseInput,
+        warehouse::ImportResponse,
+        warehouse::ImportDryRun,
+        warehouse::ImportRowError,
+        warehouse::ImportFileInput,
+        warehouse::PatchWarehouseDataInput,
+        warehouse::DefineWarehouseSchemaInput,
+        warehouse::WarehouseSchema,
+        warehouse::WarehouseSchemaField,
+        warehouse::WarehouseField,
+        warehouse::WarehouseExport,
+        warehouse::ExportWarehouseDataInput,
+        warehouse::WarehouseImport,
+        WarehouseData,
+        warehouse::WarehouseDataMatch,
+        CommandResponse,
+        QueryResultWarehouseData,
+        PageInfo,
+        EdgeWarehouseData,
+        QueryResultRoom,
+        EdgeRoom,
+    )),
     tags(
         (name = "Cobase", description = "Cobase api endpoints.")
     )
@@ -1,8 +1,10 @@
 use actix_jwks::JwtPayload;
-use actix_web::{get, post, web, HttpResponse, Scope};
+use actix_web::{delete, get, patch, post, web, HttpResponse, Scope};
+use chrono::{DateTime, Utc};
 use cobase::command::CommandInput;
+use cobase::query::SortOrder;
 use cobase::room;
-use evento::{CommandError, CommandResponse};
+use evento::{query::QueryArgs, CommandError, CommandResponse};
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
@@ -17,30 +19,86 @@ pub struct Room {
     pub name: String,
     #[schema(example = "a18aac51-6262-4576-8883-7fda0ca72aac")]
     pub user_id: Uuid,
+    #[schema(value_type = Option<String>, example = "2023-03-26T02:57:08.590084Z")]
+    pub archived_at: Option<DateTime<Utc>>,
+    #[schema(value_type = Option<String>, example = "2023-03-26T02:57:08.590084Z")]
+    pub updated_at: Option<DateTime<Utc>>,
+    #[schema(value_type = String, example = "2023-03-26T02:57:08.590084Z")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListRoomsArgs {
+    /// Case insensitive search on the room name
+    #[param(required = false, example = "room")]
+    pub name: Option<String>,
+    /// Sort by creation date
+    #[param(required = false, value_type = Option<String>, example = "desc")]
+    #[serde(default)]
+    pub order: SortOrder,
 }
 
 #[utoipa::path(
     tag = "cobase",
     context_path = "/api/rooms",
+    params(
+        crate::openapi::QueryArgs,
+        ListRoomsArgs
+    ),
     responses(
-        (status = 200, description = "Get rooms did not result error", body = [Room]),
+        (status = 200, description = "Get rooms did not result error", body = QueryResultRoom),
     )
 )]
 #[get("")]
 async fn list_rooms(
     state: web::Data<AppState>,
     payload: JwtPayload,
+    query_args: web::Query<QueryArgs>,
+    args: web::Query<ListRoomsArgs>,
 ) -> Result<HttpResponse, CommandError> {
+    let args = args.into_inner();
     let rooms = state
         .query
         .send(room::ListRoomsQuery {
             user_id: Uuid::parse_str(&payload.subject)?,
+            query_args: query_args.0,
+            name: args.name,
+            order: args.order,
         })
         .await??;
 
     Ok(HttpResponse::Ok().json(rooms))
 }
 
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms",
+    params(
+        ("id" = String, Path, description = "Room id")
+    ),
+    responses(
+        (status = 200, description = "Get room did not result error", body = Room),
+        (status = 404, description = "Room not found or not accessible"),
+    )
+)]
+#[get("/{id}")]
+async fn get_room(
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+    payload: JwtPayload,
+) -> Result<HttpResponse, CommandError> {
+    let room = state
+        .query
+        .send(room::GetRoomQuery {
+            user_id: Uuid::parse_str(&payload.subject)?,
+            id: id.into_inner(),
+        })
+        .await??;
+
+    Ok(HttpResponse::Ok().json(room))
+}
+
 #[derive(Deserialize, IntoParams, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateRoomInput {
@@ -74,8 +132,261 @@ async fn create_room(
     .into()
 }
 
+#[derive(Deserialize, IntoParams, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RenameRoomInput {
+    #[schema(example = "My room name 2")]
+    pub name: String,
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms",
+    params(
+        ("id" = String, Path, description = "Room id")
+    ),
+    request_body=RenameRoomInput,
+    responses(
+        (status = 200, description = "Rename room did not result error", body = CommandResponse),
+    )
+)]
+#[patch("/{id}")]
+async fn rename_room(
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+    input: web::Json<RenameRoomInput>,
+    payload: JwtPayload,
+) -> HttpResponse {
+    CommandResponse(
+        state
+            .cmd
+            .send(CommandInput {
+                user_id: payload.subject,
+                input: room::RenameCommand {
+                    id: id.into_inner(),
+                    name: input.0.name,
+                },
+            })
+            .await,
+    )
+    .into()
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms",
+    params(
+        ("id" = String, Path, description = "Room id")
+    ),
+    responses(
+        (status = 200, description = "Archive room did not result error", body = CommandResponse),
+    )
+)]
+#[post("/{id}/archive")]
+async fn archive_room(
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+    payload: JwtPayload,
+) -> HttpResponse {
+    CommandResponse(
+        state
+            .cmd
+            .send(CommandInput {
+                user_id: payload.subject,
+                input: room::ArchiveCommand {
+                    id: id.into_inner(),
+                },
+            })
+            .await,
+    )
+    .into()
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms",
+    params(
+        ("id" = String, Path, description = "Room id")
+    ),
+    responses(
+        (status = 200, description = "Delete room did not result error", body = CommandResponse),
+    )
+)]
+#[delete("/{id}")]
+async fn delete_room(
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+    payload: JwtPayload,
+) -> HttpResponse {
+    CommandResponse(
+        state
+            .cmd
+            .send(CommandInput {
+                user_id: payload.subject,
+                input: room::DeleteCommand {
+                    id: id.into_inner(),
+                },
+            })
+            .await,
+    )
+    .into()
+}
+
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct RoomMember {
+    #[schema(example = "V1StGXR8_Z5jdHi6B-myT")]
+    pub room_id: String,
+    #[schema(example = "a18aac51-6262-4576-8883-7fda0ca72aac")]
+    pub user_id: Uuid,
+    #[schema(example = "editor")]
+    pub role: String,
+    #[schema(value_type = Option<String>, example = "2023-03-26T02:57:08.590084Z")]
+    pub joined_at: Option<DateTime<Utc>>,
+    #[schema(value_type = String, example = "2023-03-26T02:57:08.590084Z")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms",
+    params(
+        ("id" = String, Path, description = "Room id")
+    ),
+    responses(
+        (status = 200, description = "Get room members did not result error", body = [RoomMember]),
+    )
+)]
+#[get("/{id}/members")]
+async fn list_room_members(
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+    payload: JwtPayload,
+) -> Result<HttpResponse, CommandError> {
+    let members = state
+        .query
+        .send(room::ListRoomMembersQuery {
+            user_id: Uuid::parse_str(&payload.subject)?,
+            room_id: id.into_inner(),
+        })
+        .await??;
+
+    Ok(HttpResponse::Ok().json(members))
+}
+
+#[derive(Deserialize, IntoParams, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteRoomMemberInput {
+    #[schema(example = "a18aac51-6262-4576-8883-7fda0ca72aac")]
+    pub user_id: String,
+    #[schema(value_type = String, example = "editor")]
+    pub role: room::Role,
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms",
+    params(
+        ("id" = String, Path, description = "Room id")
+    ),
+    request_body=InviteRoomMemberInput,
+    responses(
+        (status = 200, description = "Invite room member did not result error", body = CommandResponse),
+    )
+)]
+#[post("/{id}/members")]
+async fn invite_room_member(
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+    input: web::Json<InviteRoomMemberInput>,
+    payload: JwtPayload,
+) -> HttpResponse {
+    CommandResponse(
+        state
+            .cmd
+            .send(CommandInput {
+                user_id: payload.subject,
+                input: room::InviteMemberCommand {
+                    id: id.into_inner(),
+                    user_id: input.0.user_id,
+                    role: input.0.role,
+                },
+            })
+            .await,
+    )
+    .into()
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms",
+    params(
+        ("id" = String, Path, description = "Room id")
+    ),
+    responses(
+        (status = 200, description = "Join room did not result error", body = CommandResponse),
+    )
+)]
+#[post("/{id}/join")]
+async fn join_room(
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+    payload: JwtPayload,
+) -> HttpResponse {
+    CommandResponse(
+        state
+            .cmd
+            .send(CommandInput {
+                user_id: payload.subject,
+                input: room::JoinCommand {
+                    id: id.into_inner(),
+                },
+            })
+            .await,
+    )
+    .into()
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms",
+    params(
+        ("id" = String, Path, description = "Room id"),
+        ("user_id" = String, Path, description = "Member user id")
+    ),
+    responses(
+        (status = 200, description = "Remove room member did not result error", body = CommandResponse),
+    )
+)]
+#[delete("/{id}/members/{user_id}")]
+async fn remove_room_member(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    payload: JwtPayload,
+) -> HttpResponse {
+    let (id, user_id) = path.into_inner();
+
+    CommandResponse(
+        state
+            .cmd
+            .send(CommandInput {
+                user_id: payload.subject,
+                input: room::RemoveMemberCommand { id, user_id },
+            })
+            .await,
+    )
+    .into()
+}
+
 pub fn scope() -> Scope {
     web::scope("/rooms")
         .service(list_rooms)
         .service(create_room)
+        .service(get_room)
+        .service(rename_room)
+        .service(archive_room)
+        .service(delete_room)
+        .service(list_room_members)
+        .service(invite_room_member)
+        .service(join_room)
+        .service(remove_room_member)
 }
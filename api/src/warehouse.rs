@@ -1,13 +1,17 @@
 use std::collections::HashMap;
 
 use actix_jwks::JwtPayload;
-use actix_web::{get, post, web, HttpResponse, Scope};
+use actix_multipart::Multipart;
+use actix_web::{delete, get, http::header, patch, post, put, web, HttpResponse, Scope};
 use chrono::{DateTime, Utc};
 use cobase::command::CommandInput;
 use cobase::warehouse;
 use evento::{query::QueryArgs, CommandError, CommandResponse};
+use futures::StreamExt;
+use opendal::Operator;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tracing::error;
 use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
@@ -27,60 +31,850 @@ pub struct WarehouseData {
     pub updated_at: Option<DateTime<Utc>>,
 }
 
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct WarehouseDataMatch {
+    #[schema(example = "V1StGXR8_Z5jdHi6B-myT")]
+    pub id: String,
+    #[schema(example = "your-custom-key")]
+    pub key: String,
+    #[schema(value_type = Object, example = "{\"name\": \"My name\"}")]
+    pub data: Value,
+    #[schema(value_type = String, example = "2023-03-26T02:57:08.590084Z")]
+    pub created_at: DateTime<Utc>,
+    #[schema(value_type = Option<String>, example = "2023-03-26T02:57:08.590084Z")]
+    pub updated_at: Option<DateTime<Utc>>,
+    #[schema(example = 0.0607927)]
+    pub rank: f32,
+    #[schema(example = "<mark>My</mark> name")]
+    pub snippet: Option<String>,
+}
+
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct Warehouse {
+    #[schema(example = "V1StGXR8Z5jdHi6BmyT")]
+    pub id: String,
+    #[schema(example = "V1StGXR8_Z5jdHi6B-myT")]
+    pub room_id: Option<String>,
+    #[schema(example = "Customers")]
+    pub name: String,
+    #[schema(example = "Customers imported from the CRM")]
+    pub description: Option<String>,
+    #[schema(example = "a18aac51-6262-4576-8883-7fda0ca72aac")]
+    pub user_id: Uuid,
+    pub schema: Option<WarehouseSchema>,
+    #[schema(value_type = String, example = "2023-03-26T02:57:08.590084Z")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct WarehouseSchema {
+    pub fields: Vec<WarehouseSchemaField>,
+    /// Rejects rows with fields that are not declared
+    pub strict: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct WarehouseSchemaField {
+    /// Dot separated path of the field in the row
+    #[schema(example = "address.city")]
+    pub name: String,
+    #[serde(rename = "type")]
+    #[schema(value_type = String, example = "string")]
+    pub field_type: warehouse::FieldType,
+    pub required: bool,
+    pub nullable: bool,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct ListWarehousesArgs {
+    /// Only list the warehouses of this room
+    #[param(required = false, example = "V1StGXR8_Z5jdHi6B-myT")]
+    pub room_id: Option<String>,
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/warehouses",
+    params(
+        ListWarehousesArgs
+    ),
+    responses(
+        (status = 200, description = "Get warehouses did not result error", body = [Warehouse]),
+        (status = 404, description = "Room not found or not accessible"),
+    )
+)]
+#[get("")]
+async fn list_warehouses(
+    state: web::Data<AppState>,
+    args: web::Query<ListWarehousesArgs>,
+    payload: JwtPayload,
+) -> Result<HttpResponse, CommandError> {
+    let warehouses = state
+        .query
+        .send(warehouse::ListWarehousesQuery {
+            user_id: Uuid::parse_str(&payload.subject)?,
+            room_id: args.into_inner().room_id,
+        })
+        .await??;
+
+    Ok(HttpResponse::Ok().json(warehouses))
+}
+
+#[derive(Deserialize, IntoParams, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateWarehouseInput {
+    #[schema(example = "V1StGXR8_Z5jdHi6B-myT")]
+    pub room_id: String,
+    #[schema(example = "Customers")]
+    pub name: String,
+    #[schema(example = "Customers imported from the CRM")]
+    pub description: Option<String>,
+}
+
 #[utoipa::path(
     tag = "cobase",
     context_path = "/api/warehouses",
+    request_body=CreateWarehouseInput,
+    responses(
+        (status = 200, description = "Create warehouse did not result error", body = CommandResponse),
+    )
+)]
+#[post("")]
+async fn create_warehouse(
+    state: web::Data<AppState>,
+    input: web::Json<CreateWarehouseInput>,
+    payload: JwtPayload,
+) -> HttpResponse {
+    let input = input.into_inner();
+
+    CommandResponse(
+        state
+            .cmd
+            .send(CommandInput {
+                user_id: payload.subject,
+                input: warehouse::CreateWarehouseCommand {
+                    room_id: input.room_id,
+                    name: input.name,
+                    description: input.description,
+                },
+            })
+            .await,
+    )
+    .into()
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms/{room_id}/warehouses",
     params(
-        crate::openapi::QueryArgs
+        ("room_id" = String, Path, description = "Room id")
+    ),
+    responses(
+        (status = 200, description = "Get warehouses did not result error", body = [Warehouse]),
+        (status = 404, description = "Room not found or not accessible"),
+    )
+)]
+#[get("")]
+async fn list_room_warehouses(
+    state: web::Data<AppState>,
+    room_id: web::Path<String>,
+    payload: JwtPayload,
+) -> Result<HttpResponse, CommandError> {
+    let warehouses = state
+        .query
+        .send(warehouse::ListWarehousesQuery {
+            user_id: Uuid::parse_str(&payload.subject)?,
+            room_id: Some(room_id.into_inner()),
+        })
+        .await??;
+
+    Ok(HttpResponse::Ok().json(warehouses))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListWarehouseDataArgs {
+    /// Filter expression on the row data, operators are `=`, `!=`, `>`, `>=`, `<`, `<=`,
+    /// `in (..)`, `contains` and `exists` combined with `and`, `or`, `not` and parentheses
+    #[param(required = false, example = "status = \"active\" and price > 10")]
+    pub filter: Option<String>,
+    /// Comma separated fields of the row data to sort on, prefixed by `-` for descending
+    /// order and suffixed by `:number` or `:date` to cast values
+    #[param(required = false, example = "-price:number,address.city")]
+    pub sort: Option<String>,
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms/{room_id}/warehouses",
+    params(
+        ("room_id" = String, Path, description = "Room id"),
+        ("id" = String, Path, description = "Warehouse id"),
+        crate::openapi::QueryArgs,
+        ListWarehouseDataArgs
     ),
     responses(
         (status = 200, description = "Get warehouse data did not result error", body = QueryResultWarehouseData),
+        (status = 404, description = "Room or warehouse not found or not accessible"),
     )
 )]
-#[get("/data")]
+#[get("/{id}/data")]
 async fn list_warehouses_data(
     state: web::Data<AppState>,
+    path: web::Path<(String, String)>,
     payload: JwtPayload,
     query_args: web::Query<QueryArgs>,
+    args: web::Query<ListWarehouseDataArgs>,
 ) -> Result<HttpResponse, CommandError> {
+    let (room_id, warehouse_id) = path.into_inner();
+    let args = args.into_inner();
     let rows = state
         .query
         .send(warehouse::ListWarehouseDataQuery {
             user_id: Uuid::parse_str(&payload.subject)?,
+            room_id,
+            warehouse_id,
             query_args: query_args.0,
+            filter: args.filter,
+            sort: args.sort,
+        })
+        .await??;
+
+    Ok(HttpResponse::Ok().json(rows))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchWarehouseDataArgs {
+    /// Words to search, supports quoted phrases, `or` and `-` to exclude words
+    #[param(example = "\"john doe\" -paris")]
+    pub q: String,
+    #[param(required = false)]
+    pub limit: Option<u16>,
+    #[param(required = false)]
+    pub offset: Option<u32>,
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms/{room_id}/warehouses",
+    params(
+        ("room_id" = String, Path, description = "Room id"),
+        ("id" = String, Path, description = "Warehouse id"),
+        SearchWarehouseDataArgs
+    ),
+    responses(
+        (status = 200, description = "Search warehouse data did not result error", body = [WarehouseDataMatch]),
+        (status = 400, description = "Empty search"),
+        (status = 404, description = "Room or warehouse not found or not accessible"),
+    )
+)]
+#[get("/{id}/data/search")]
+async fn search_warehouse_data(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    payload: JwtPayload,
+    args: web::Query<SearchWarehouseDataArgs>,
+) -> Result<HttpResponse, CommandError> {
+    let (room_id, warehouse_id) = path.into_inner();
+    let args = args.into_inner();
+    let rows = state
+        .query
+        .send(warehouse::SearchWarehouseDataQuery {
+            user_id: Uuid::parse_str(&payload.subject)?,
+            room_id,
+            warehouse_id,
+            q: args.q,
+            limit: args.limit,
+            offset: args.offset,
         })
         .await??;
 
     Ok(HttpResponse::Ok().json(rows))
 }
 
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms/{room_id}/warehouses",
+    params(
+        ("room_id" = String, Path, description = "Room id"),
+        ("id" = String, Path, description = "Warehouse id"),
+        ("key" = String, Path, description = "Row _id provided at import, or its internal id")
+    ),
+    responses(
+        (status = 200, description = "Get warehouse data did not result error", body = WarehouseData),
+        (status = 404, description = "Room, warehouse or data not found or not accessible"),
+    )
+)]
+#[get("/{id}/data/{key}")]
+async fn get_warehouse_data(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+    payload: JwtPayload,
+) -> Result<HttpResponse, CommandError> {
+    let (room_id, warehouse_id, key) = path.into_inner();
+    let row = state
+        .query
+        .send(warehouse::GetWarehouseDataQuery {
+            user_id: Uuid::parse_str(&payload.subject)?,
+            room_id,
+            warehouse_id,
+            key,
+        })
+        .await??;
+
+    Ok(HttpResponse::Ok().json(row))
+}
+
 #[derive(Deserialize, IntoParams, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ImportDataWarehouseInput {
+    #[schema(example = "V1StGXR8Z5jdHi6BmyT")]
+    pub warehouse_id: String,
     #[schema(value_type = Vec<Object>, example = "[{ \"_id\": 1, \"name\": \"john doe\" }]")]
     pub data: Vec<HashMap<String, Value>>,
+    /// `insert-skip`, `insert-fail`, `upsert`, `merge` or `replace`, defaults to `upsert`
+    #[serde(default)]
+    #[schema(value_type = String, example = "upsert")]
+    pub mode: warehouse::ImportMode,
+    /// Returns what the import would do without importing the data, like
+    /// `/import-data/dry-run`
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct ImportResponse {
+    /// Warehouse id
+    #[schema(example = "V1StGXR8Z5jdHi6BmyT")]
+    pub id: String,
+    /// Import id, see `/imports/{request_id}`
+    #[schema(example = "a18aac51-6262-4576-8883-7fda0ca72aac")]
+    pub request_id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct ImportRowError {
+    /// Position of the row in the imported data
+    #[schema(example = 3)]
+    pub index: usize,
+    #[schema(example = "price")]
+    pub path: String,
+    #[schema(example = "expected number, got string")]
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct ImportDryRun {
+    #[schema(example = 40)]
+    pub inserts: usize,
+    #[schema(example = 2)]
+    pub updates: usize,
+    #[schema(example = 10)]
+    pub unchanged: usize,
+    /// Existing rows kept with the `insert-skip` mode
+    #[schema(example = 0)]
+    pub skipped: usize,
+    /// Rows that are not in the import with the `replace` mode
+    #[schema(example = 0)]
+    pub deletes: usize,
+    #[schema(example = 1)]
+    pub error_count: usize,
+    /// First 100 errors, the import would fail if any
+    pub errors: Vec<ImportRowError>,
 }
 
 #[utoipa::path(
     tag = "cobase",
-    context_path = "/api/warehouses",
+    context_path = "/api/rooms/{room_id}/warehouses",
+    params(
+        ("room_id" = String, Path, description = "Room id")
+    ),
     request_body=ImportDataWarehouseInput,
     responses(
-        (status = 200, description = "Import data to wharehouse did not result error", body = CommandResponse),
+        (status = 200, description = "Import data to wharehouse did not result error. With dryRun, the response is an ImportDryRun", body = ImportResponse),
     )
 )]
 #[post("/import-data")]
 async fn import_data(
     state: web::Data<AppState>,
+    room_id: web::Path<String>,
+    input: web::Json<ImportDataWarehouseInput>,
+    payload: JwtPayload,
+) -> Result<HttpResponse, CommandError> {
+    let input = input.into_inner();
+    let command = warehouse::ImportDataCommand {
+        room_id: room_id.into_inner(),
+        warehouse_id: input.warehouse_id.to_owned(),
+        data: input.data,
+        mode: input.mode,
+        dry_run: input.dry_run,
+    };
+
+    if command.dry_run {
+        let report = state
+            .query
+            .send(command.into_dry_run(Uuid::parse_str(&payload.subject)?))
+            .await??;
+
+        return Ok(HttpResponse::Ok().json(report));
+    }
+
+    let request_id = state
+        .cmd
+        .send(CommandInput {
+            user_id: payload.subject,
+            input: command,
+        })
+        .await??;
+
+    Ok(HttpResponse::Ok().json(ImportResponse {
+        id: input.warehouse_id,
+        request_id,
+    }))
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms/{room_id}/warehouses",
+    params(
+        ("room_id" = String, Path, description = "Room id")
+    ),
+    request_body=ImportDataWarehouseInput,
+    responses(
+        (status = 200, description = "What the import would do, the data is not imported", body = ImportDryRun),
+        (status = 404, description = "Room or warehouse not found or not accessible"),
+    )
+)]
+#[post("/import-data/dry-run")]
+async fn dry_run_import_data(
+    state: web::Data<AppState>,
+    room_id: web::Path<String>,
     input: web::Json<ImportDataWarehouseInput>,
     payload: JwtPayload,
+) -> Result<HttpResponse, CommandError> {
+    let input = input.into_inner();
+
+    let report = state
+        .query
+        .send(warehouse::DryRunImportDataQuery {
+            user_id: Uuid::parse_str(&payload.subject)?,
+            room_id: room_id.into_inner(),
+            warehouse_id: input.warehouse_id,
+            data: input.data,
+            mode: input.mode,
+        })
+        .await??;
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct ImportCsvArgs {
+    #[param(example = "V1StGXR8Z5jdHi6BmyT")]
+    pub warehouse_id: String,
+    /// Single character separating the values, defaults to `,`
+    #[param(value_type = Option<String>, example = ";")]
+    pub delimiter: Option<char>,
+    /// Whether the first record holds the column names, defaults to `true`.
+    /// Otherwise columns are named `column_1`, `column_2`...
+    pub header: Option<bool>,
+    /// Column used as the row `_id`, defaults to `_id`
+    #[param(example = "sku")]
+    pub id_column: Option<String>,
+    /// Whether values are converted to the type of the schema field, or to a
+    /// number, boolean or `null` when not declared. Defaults to `true`
+    pub coerce: Option<bool>,
+    /// `insert-skip`, `insert-fail`, `upsert`, `merge` or `replace`, defaults to `upsert`
+    #[serde(default)]
+    #[param(value_type = Option<String>, example = "upsert")]
+    pub mode: warehouse::ImportMode,
+}
+
+#[derive(ToSchema)]
+pub struct ImportFileInput {
+    #[schema(value_type = String, format = Binary)]
+    pub file: Vec<u8>,
+}
+
+/// Streams the `file` field of a multipart payload to the storage
+async fn write_upload_file(
+    storage: &Operator,
+    path: &str,
+    payload: &mut Multipart,
+) -> Result<(), CommandError> {
+    let mut writer = storage
+        .writer(path)
+        .await
+        .map_err(|e| CommandError::InternalServerErr(e.to_string()))?;
+
+    let mut has_file = false;
+
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(|e| CommandError::BadRequest(e.to_string()))?;
+        let is_file = field.name() == "file";
+        has_file = has_file || is_file;
+
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|e| CommandError::BadRequest(e.to_string()))?;
+
+            if is_file {
+                writer
+                    .append(chunk)
+                    .await
+                    .map_err(|e| CommandError::InternalServerErr(e.to_string()))?;
+            }
+        }
+    }
+
+    writer
+        .close()
+        .await
+        .map_err(|e| CommandError::InternalServerErr(e.to_string()))?;
+
+    match has_file {
+        true => Ok(()),
+        false => Err(CommandError::BadRequest(
+            "Missing multipart field file".to_owned(),
+        )),
+    }
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms/{room_id}/warehouses",
+    params(
+        ("room_id" = String, Path, description = "Room id"),
+        ImportCsvArgs
+    ),
+    request_body(content = ImportFileInput, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Import csv to wharehouse did not result error", body = ImportResponse),
+        (status = 400, description = "Invalid csv or data does not match the warehouse schema"),
+    )
+)]
+#[post("/import-csv")]
+async fn import_csv(
+    state: web::Data<AppState>,
+    room_id: web::Path<String>,
+    args: web::Query<ImportCsvArgs>,
+    payload: JwtPayload,
+    mut multipart: Multipart,
+) -> Result<HttpResponse, CommandError> {
+    let args = args.into_inner();
+    let room_id = room_id.into_inner();
+
+    state
+        .cmd
+        .send(CommandInput {
+            user_id: payload.subject.to_owned(),
+            input: warehouse::AuthorizeImportCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: args.warehouse_id.to_owned(),
+            },
+        })
+        .await??;
+
+    let storage_path = warehouse::get_import_csv_path(&payload.subject);
+
+    if let Err(e) = write_upload_file(&state.storage, &storage_path, &mut multipart).await {
+        if let Err(e) = state.storage.remove(vec![storage_path]).await {
+            error!("{e}");
+        }
+
+        return Err(e);
+    }
+
+    let default_options = warehouse::CsvOptions::default();
+
+    let request_id = state
+        .cmd
+        .send(CommandInput {
+            user_id: payload.subject,
+            input: warehouse::ImportCsvCommand {
+                room_id,
+                warehouse_id: args.warehouse_id.to_owned(),
+                storage_path,
+                options: warehouse::CsvOptions {
+                    delimiter: args.delimiter.unwrap_or(default_options.delimiter),
+                    header: args.header.unwrap_or(default_options.header),
+                    id_column: args.id_column.unwrap_or(default_options.id_column),
+                    coerce: args.coerce.unwrap_or(default_options.coerce),
+                },
+                mode: args.mode,
+            },
+        })
+        .await??;
+
+    Ok(HttpResponse::Ok().json(ImportResponse {
+        id: args.warehouse_id,
+        request_id,
+    }))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct ImportFileArgs {
+    #[param(example = "V1StGXR8Z5jdHi6BmyT")]
+    pub warehouse_id: String,
+    /// `json` for an array of rows, `ndjson` for one row per line or `parquet`
+    #[param(value_type = String, example = "ndjson")]
+    pub format: warehouse::ImportFormat,
+    /// `insert-skip`, `insert-fail`, `upsert`, `merge` or `replace`, defaults to `upsert`
+    #[serde(default)]
+    #[param(value_type = Option<String>, example = "upsert")]
+    pub mode: warehouse::ImportMode,
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms/{room_id}/warehouses",
+    params(
+        ("room_id" = String, Path, description = "Room id"),
+        ImportFileArgs
+    ),
+    request_body(content = ImportFileInput, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Import file to wharehouse did not result error", body = ImportResponse),
+        (status = 400, description = "Invalid file or data does not match the warehouse schema"),
+    )
+)]
+#[post("/import-file")]
+async fn import_file(
+    state: web::Data<AppState>,
+    room_id: web::Path<String>,
+    args: web::Query<ImportFileArgs>,
+    payload: JwtPayload,
+    mut multipart: Multipart,
+) -> Result<HttpResponse, CommandError> {
+    let args = args.into_inner();
+    let room_id = room_id.into_inner();
+
+    state
+        .cmd
+        .send(CommandInput {
+            user_id: payload.subject.to_owned(),
+            input: warehouse::AuthorizeImportCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: args.warehouse_id.to_owned(),
+            },
+        })
+        .await??;
+
+    let storage_path = warehouse::get_import_file_path(&payload.subject, args.format);
+
+    if let Err(e) = write_upload_file(&state.storage, &storage_path, &mut multipart).await {
+        if let Err(e) = state.storage.remove(vec![storage_path]).await {
+            error!("{e}");
+        }
+
+        return Err(e);
+    }
+
+    let request_id = state
+        .cmd
+        .send(CommandInput {
+            user_id: payload.subject,
+            input: warehouse::ImportDataFileCommand {
+                room_id,
+                warehouse_id: args.warehouse_id.to_owned(),
+                storage_path,
+                format: args.format,
+                mode: args.mode,
+            },
+        })
+        .await??;
+
+    Ok(HttpResponse::Ok().json(ImportResponse {
+        id: args.warehouse_id,
+        request_id,
+    }))
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms/{room_id}/warehouses",
+    params(
+        ("room_id" = String, Path, description = "Room id"),
+        ("id" = String, Path, description = "Warehouse id"),
+        ("key" = String, Path, description = "Row _id provided at import")
+    ),
+    responses(
+        (status = 200, description = "Delete warehouse data did not result error", body = CommandResponse),
+    )
+)]
+#[delete("/{id}/data/{key}")]
+async fn delete_warehouse_data(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+    payload: JwtPayload,
+) -> HttpResponse {
+    let (room_id, warehouse_id, key) = path.into_inner();
+
+    CommandResponse(
+        state
+            .cmd
+            .send(CommandInput {
+                user_id: payload.subject,
+                input: warehouse::DeleteDataCommand {
+                    room_id,
+                    warehouse_id,
+                    key,
+                },
+            })
+            .await,
+    )
+    .into()
+}
+
+#[derive(Deserialize, IntoParams, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PatchWarehouseDataInput {
+    /// JSON merge patch, `null` values remove fields
+    #[schema(value_type = Object, example = "{ \"name\": \"john smith\", \"phone\": null }")]
+    pub data: HashMap<String, Value>,
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms/{room_id}/warehouses",
+    params(
+        ("room_id" = String, Path, description = "Room id"),
+        ("id" = String, Path, description = "Warehouse id"),
+        ("key" = String, Path, description = "Row _id provided at import")
+    ),
+    request_body=PatchWarehouseDataInput,
+    responses(
+        (status = 200, description = "Patch warehouse data did not result error", body = CommandResponse),
+    )
+)]
+#[patch("/{id}/data/{key}")]
+async fn patch_warehouse_data(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+    input: web::Json<PatchWarehouseDataInput>,
+    payload: JwtPayload,
 ) -> HttpResponse {
+    let (room_id, warehouse_id, key) = path.into_inner();
+
     CommandResponse(
         state
             .cmd
             .send(CommandInput {
                 user_id: payload.subject,
-                input: warehouse::ImportDataCommand { data: input.0.data },
+                input: warehouse::PatchDataCommand {
+                    room_id,
+                    warehouse_id,
+                    key,
+                    data: input.into_inner().data,
+                },
+            })
+            .await,
+    )
+    .into()
+}
+
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct WarehouseField {
+    #[schema(example = "V1StGXR8Z5jdHi6BmyT")]
+    pub warehouse_id: String,
+    /// Dot separated path of the field in the rows
+    #[schema(example = "address.city")]
+    pub name: String,
+    /// Type that every value matches, none when values have incompatible types
+    #[schema(example = "string")]
+    pub field_type: Option<String>,
+    #[schema(example = json!(["string"]))]
+    pub types: Vec<String>,
+    pub nullable: bool,
+    /// Distinct values of the import or patch with the most distinct values, capped at 1000
+    #[schema(example = 42)]
+    pub max_cardinality: i64,
+    #[schema(value_type = String, example = "2023-03-26T02:57:08.590084Z")]
+    pub updated_at: DateTime<Utc>,
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms/{room_id}/warehouses",
+    params(
+        ("room_id" = String, Path, description = "Room id"),
+        ("id" = String, Path, description = "Warehouse id")
+    ),
+    responses(
+        (status = 200, description = "Fields detected in the imported data, the declared schema is defined with PUT", body = [WarehouseField]),
+        (status = 404, description = "Room or warehouse not found or not accessible"),
+    )
+)]
+#[get("/{id}/schema")]
+async fn get_warehouse_schema(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    payload: JwtPayload,
+) -> Result<HttpResponse, CommandError> {
+    let (room_id, warehouse_id) = path.into_inner();
+    let fields = state
+        .query
+        .send(warehouse::ListWarehouseFieldsQuery {
+            user_id: Uuid::parse_str(&payload.subject)?,
+            room_id,
+            warehouse_id,
+        })
+        .await??;
+
+    Ok(HttpResponse::Ok().json(fields))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct DefineWarehouseSchemaInput {
+    /// Replaces the fields of the schema, an empty list removes the schema
+    #[schema(value_type = Vec<WarehouseSchemaField>)]
+    pub fields: Vec<warehouse::SchemaField>,
+    /// Rejects rows with fields that are not declared
+    #[serde(default)]
+    pub strict: bool,
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms/{room_id}/warehouses",
+    params(
+        ("room_id" = String, Path, description = "Room id"),
+        ("id" = String, Path, description = "Warehouse id")
+    ),
+    request_body=DefineWarehouseSchemaInput,
+    responses(
+        (status = 200, description = "Define warehouse schema did not result error", body = CommandResponse),
+    )
+)]
+#[put("/{id}/schema")]
+async fn define_warehouse_schema(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    input: web::Json<DefineWarehouseSchemaInput>,
+    payload: JwtPayload,
+) -> HttpResponse {
+    let (room_id, warehouse_id) = path.into_inner();
+    let input = input.into_inner();
+
+    CommandResponse(
+        state
+            .cmd
+            .send(CommandInput {
+                user_id: payload.subject,
+                input: warehouse::DefineSchemaCommand {
+                    room_id,
+                    warehouse_id,
+                    schema: warehouse::Schema {
+                        fields: input.fields,
+                        strict: input.strict,
+                    },
+                },
             })
             .await,
     )
@@ -89,6 +883,240 @@ async fn import_data(
 
 pub fn scope() -> Scope {
     web::scope("/warehouses")
+        .service(list_warehouses)
+        .service(create_warehouse)
+}
+
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct WarehouseExport {
+    #[schema(example = "V1StGXR8_Z5jdHi6B-myT")]
+    pub id: String,
+    #[schema(example = "V1StGXR8_Z5jdHi6B-myT")]
+    pub room_id: String,
+    #[schema(example = "V1StGXR8Z5jdHi6BmyT")]
+    pub warehouse_id: String,
+    #[schema(example = "a18aac51-6262-4576-8883-7fda0ca72aac")]
+    pub user_id: Uuid,
+    #[schema(example = "csv")]
+    pub format: String,
+    #[schema(example = "status = \"active\"")]
+    pub filter: Option<String>,
+    /// `running`, `completed` or `failed`
+    #[schema(example = "completed")]
+    pub status: String,
+    pub storage_path: Option<String>,
+    #[schema(example = 42)]
+    pub row_count: Option<i64>,
+    pub error: Option<String>,
+    #[schema(value_type = String, example = "2023-03-26T02:57:08.590084Z")]
+    pub created_at: DateTime<Utc>,
+    #[schema(value_type = Option<String>, example = "2023-03-26T02:57:08.590084Z")]
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportWarehouseDataInput {
+    #[schema(example = "V1StGXR8Z5jdHi6BmyT")]
+    pub warehouse_id: String,
+    /// `csv`, `ndjson` or `parquet`, defaults to `csv`
+    #[serde(default)]
+    #[schema(value_type = String, example = "csv")]
+    pub format: warehouse::ExportFormat,
+    /// Filter expression on the row data, see `GET /{id}/data`
+    #[schema(example = "status = \"active\"")]
+    pub filter: Option<String>,
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms/{room_id}/warehouses",
+    params(
+        ("room_id" = String, Path, description = "Room id")
+    ),
+    request_body = ExportWarehouseDataInput,
+    responses(
+        (status = 200, description = "Export started, the response id is the export id", body = CommandResponse),
+    )
+)]
+#[post("/exports")]
+async fn export_warehouse_data(
+    state: web::Data<AppState>,
+    room_id: web::Path<String>,
+    input: web::Json<ExportWarehouseDataInput>,
+    payload: JwtPayload,
+) -> HttpResponse {
+    let input = input.into_inner();
+
+    CommandResponse(
+        state
+            .cmd
+            .send(CommandInput {
+                user_id: payload.subject,
+                input: warehouse::ExportDataCommand {
+                    room_id: room_id.into_inner(),
+                    warehouse_id: input.warehouse_id,
+                    format: input.format,
+                    filter: input.filter,
+                },
+            })
+            .await,
+    )
+    .into()
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GetWarehouseExportArgs {
+    /// Returns the exported file instead of the export once completed
+    pub download: Option<bool>,
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms/{room_id}/warehouses",
+    params(
+        ("room_id" = String, Path, description = "Room id"),
+        ("id" = String, Path, description = "Export id"),
+        GetWarehouseExportArgs
+    ),
+    responses(
+        (status = 200, description = "Export status, or the exported file with download", body = WarehouseExport),
+        (status = 400, description = "Download of an export that is not completed"),
+        (status = 404, description = "Room or export not found or not accessible"),
+    )
+)]
+#[get("/exports/{id}")]
+async fn get_warehouse_export(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    payload: JwtPayload,
+    args: web::Query<GetWarehouseExportArgs>,
+) -> Result<HttpResponse, CommandError> {
+    let (room_id, id) = path.into_inner();
+    let export = state
+        .query
+        .send(warehouse::GetWarehouseExportQuery {
+            user_id: Uuid::parse_str(&payload.subject)?,
+            room_id,
+            id,
+        })
+        .await??;
+
+    if !args.download.unwrap_or_default() {
+        return Ok(HttpResponse::Ok().json(export));
+    }
+
+    let (storage_path, format) = match (
+        &export.storage_path,
+        export.format.parse::<warehouse::ExportFormat>(),
+    ) {
+        (Some(storage_path), Ok(format)) if export.status == "completed" => (storage_path, format),
+        _ => {
+            return Err(CommandError::BadRequest(format!(
+                "export {} is {}",
+                export.id, export.status
+            )))
+        }
+    };
+
+    let reader = state
+        .storage
+        .reader(storage_path)
+        .await
+        .map_err(|e| CommandError::InternalServerErr(e.to_string()))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.{format}\"", export.warehouse_id),
+        ))
+        .streaming(reader))
+}
+
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct WarehouseImport {
+    #[schema(example = "a18aac51-6262-4576-8883-7fda0ca72aac")]
+    pub request_id: String,
+    #[schema(example = "V1StGXR8Z5jdHi6BmyT")]
+    pub warehouse_id: String,
+    #[schema(example = "a18aac51-6262-4576-8883-7fda0ca72aac")]
+    pub user_id: Uuid,
+    #[schema(example = "import-data/V1StGXR8_Z5jdHi6B-myT.tid")]
+    pub storage_path: String,
+    #[schema(example = "json")]
+    pub format: String,
+    #[schema(example = "upsert")]
+    pub mode: String,
+    /// `running`, `completed` or `failed`
+    #[schema(example = "completed")]
+    pub state: String,
+    /// Rows read from the file so far, counters are reset when the import fails
+    #[schema(example = 42)]
+    pub row_count: i64,
+    #[schema(example = 40)]
+    pub rows_inserted: i64,
+    #[schema(example = 2)]
+    pub rows_updated: i64,
+    /// Rows that were not in the import with the `replace` mode
+    #[schema(example = 0)]
+    pub rows_deleted: i64,
+    pub error: Option<String>,
+    #[schema(value_type = String, example = "2023-03-26T02:57:08.590084Z")]
+    pub created_at: DateTime<Utc>,
+    #[schema(value_type = Option<String>, example = "2023-03-26T02:57:08.590084Z")]
+    pub updated_at: Option<DateTime<Utc>>,
+    #[schema(value_type = Option<String>, example = "2023-03-26T02:57:08.590084Z")]
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[utoipa::path(
+    tag = "cobase",
+    context_path = "/api/rooms/{room_id}/warehouses",
+    params(
+        ("room_id" = String, Path, description = "Room id"),
+        ("request_id" = String, Path, description = "Import id returned by the import endpoints"),
+    ),
+    responses(
+        (status = 200, description = "Import progress", body = WarehouseImport),
+        (status = 404, description = "Room or import not found or not accessible"),
+    )
+)]
+#[get("/imports/{request_id}")]
+async fn get_warehouse_import(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    payload: JwtPayload,
+) -> Result<HttpResponse, CommandError> {
+    let (room_id, request_id) = path.into_inner();
+    let import = state
+        .query
+        .send(warehouse::GetWarehouseImportQuery {
+            user_id: Uuid::parse_str(&payload.subject)?,
+            room_id,
+            request_id,
+        })
+        .await??;
+
+    Ok(HttpResponse::Ok().json(import))
+}
+
+pub fn room_scope() -> Scope {
+    web::scope("/rooms/{room_id}/warehouses")
+        .service(list_room_warehouses)
+        .service(export_warehouse_data)
+        .service(get_warehouse_export)
+        .service(get_warehouse_import)
         .service(list_warehouses_data)
+        .service(search_warehouse_data)
+        .service(get_warehouse_data)
+        .service(delete_warehouse_data)
+        .service(patch_warehouse_data)
+        .service(get_warehouse_schema)
+        .service(define_warehouse_schema)
         .service(import_data)
+        .service(dry_run_import_data)
+        .service(import_csv)
+        .service(import_file)
 }
@@ -1,46 +1,507 @@
+use std::collections::HashMap;
+
+use actix::{Addr, MailboxError};
+use cobase::{
+    command::{Command, CommandInput},
+    query::{Query, SortOrder},
+    room, warehouse,
+};
+use evento::{
+    query::{PageInfo, QueryArgs},
+    CommandError,
+};
+use opendal::Operator;
+use serde_json::Value;
 use timada_cobase_client::timada::{
-    cobase_server::CobaseServer, CreateRoomReply, CreateRoomRequest, ImportDataWarehouseReply,
-    ImportDataWarehouseRequest,
+    self, cobase_server::CobaseServer, CreateRoomReply, CreateRoomRequest, CreateWarehouseReply,
+    CreateWarehouseRequest, GetWarehouseDataRequest, ImportDataWarehouseReply,
+    ImportDataWarehouseRequest, ListRoomsReply, ListRoomsRequest, ListWarehouseDataReply,
+    ListWarehouseDataRequest, ListWarehousesReply, ListWarehousesRequest, RoomEdge,
+    SearchWarehouseDataReply, SearchWarehouseDataRequest, WarehouseDataEdge, WarehouseDataMatch,
 };
-use tonic::{transport::Server, Request, Response, Status};
-use tracing::info;
+use tonic::{transport::Server, Request, Response, Status, Streaming};
+use tracing::{error, info};
+use uuid::Uuid;
+
+pub struct Cobase {
+    cmd: Addr<Command>,
+    query: Addr<Query>,
+    storage: Operator,
+}
+
+impl Cobase {
+    async fn write_import_data_stream(
+        &self,
+        stream: &mut Streaming<ImportDataWarehouseRequest>,
+        writer: &mut Option<warehouse::ImportDataWriter>,
+    ) -> Result<(Uuid, String, String), Status> {
+        let mut target = None;
+
+        while let Some(request) = stream.message().await? {
+            let request_target = (
+                parse_user_id(&request.user_id)?,
+                request.room_id,
+                request.warehouse_id,
+            );
+
+            if matches!(&target, Some(target) if target != &request_target) {
+                return Err(Status::invalid_argument(
+                    "user_id, room_id and warehouse_id must be the same for every message",
+                ));
+            }
+
+            if target.is_none() {
+                self.cmd
+                    .send(CommandInput {
+                        user_id: request_target.0.to_string(),
+                        input: warehouse::AuthorizeImportCommand {
+                            room_id: request_target.1.to_owned(),
+                            warehouse_id: request_target.2.to_owned(),
+                        },
+                    })
+                    .await
+                    .map_err(mailbox_status)?
+                    .map_err(command_status)?;
+
+                // The data file is bound to the user, it is only created once authorized
+                *writer = Some(
+                    warehouse::ImportDataWriter::new(&self.storage, &request_target.0.to_string())
+                        .await
+                        .map_err(|e| Status::internal(e.to_string()))?,
+                );
+            }
+
+            target = Some(request_target);
 
-#[derive(Default)]
-pub struct Cobase {}
+            let Some(writer) = writer.as_mut() else {
+                return Err(Status::internal("import data writer not created"));
+            };
+
+            let data = serde_json::from_str::<Vec<HashMap<String, Value>>>(&request.data)
+                .map_err(|e| Status::invalid_argument(format!("data: {e}")))?;
+
+            warehouse::check_import_data(&data, writer.len()).map_err(command_status)?;
+
+            writer
+                .append(&data)
+                .await
+                .map_err(|e| Status::internal(e.to_string()))?;
+        }
+
+        match target {
+            Some(target) if writer.as_ref().map_or(false, |w| !w.is_empty()) => Ok(target),
+            _ => Err(Status::invalid_argument(
+                "stream must contain at least one row",
+            )),
+        }
+    }
+}
 
 #[tonic::async_trait]
 impl timada_cobase_client::timada::cobase_server::Cobase for Cobase {
     async fn create_room(
         &self,
-        _request: Request<CreateRoomRequest>,
+        request: Request<CreateRoomRequest>,
     ) -> Result<Response<CreateRoomReply>, Status> {
-        Ok(Response::new(CreateRoomReply { success: true }))
+        let request = request.into_inner();
+
+        let id = self
+            .cmd
+            .send(CommandInput {
+                user_id: parse_user_id(&request.user_id)?.to_string(),
+                input: room::CreateCommand { name: request.name },
+            })
+            .await
+            .map_err(mailbox_status)?
+            .map_err(command_status)?;
+
+        Ok(Response::new(CreateRoomReply { id }))
+    }
+
+    async fn create_warehouse(
+        &self,
+        request: Request<CreateWarehouseRequest>,
+    ) -> Result<Response<CreateWarehouseReply>, Status> {
+        let request = request.into_inner();
+
+        let id = self
+            .cmd
+            .send(CommandInput {
+                user_id: parse_user_id(&request.user_id)?.to_string(),
+                input: warehouse::CreateWarehouseCommand {
+                    room_id: request.room_id,
+                    name: request.name,
+                    description: request.description,
+                },
+            })
+            .await
+            .map_err(mailbox_status)?
+            .map_err(command_status)?;
+
+        Ok(Response::new(CreateWarehouseReply { id }))
     }
 
     async fn import_data(
         &self,
-        _request: Request<ImportDataWarehouseRequest>,
+        request: Request<ImportDataWarehouseRequest>,
+    ) -> Result<Response<ImportDataWarehouseReply>, Status> {
+        let request = request.into_inner();
+        let data = serde_json::from_str::<Vec<HashMap<String, Value>>>(&request.data)
+            .map_err(|e| Status::invalid_argument(format!("data: {e}")))?;
+
+        let request_id = self
+            .cmd
+            .send(CommandInput {
+                user_id: parse_user_id(&request.user_id)?.to_string(),
+                input: warehouse::ImportDataCommand {
+                    room_id: request.room_id,
+                    warehouse_id: request.warehouse_id.to_owned(),
+                    data,
+                    mode: warehouse::ImportMode::Upsert,
+                    dry_run: false,
+                },
+            })
+            .await
+            .map_err(mailbox_status)?
+            .map_err(command_status)?;
+
+        Ok(Response::new(ImportDataWarehouseReply {
+            id: request.warehouse_id,
+            request_id,
+        }))
+    }
+
+    async fn import_data_stream(
+        &self,
+        request: Request<Streaming<ImportDataWarehouseRequest>>,
     ) -> Result<Response<ImportDataWarehouseReply>, Status> {
-        Ok(Response::new(ImportDataWarehouseReply { success: true }))
+        let mut stream = request.into_inner();
+        let mut writer = None;
+
+        let res = self
+            .write_import_data_stream(&mut stream, &mut writer)
+            .await;
+
+        let storage_path = match writer {
+            Some(writer) => Some(
+                writer
+                    .close()
+                    .await
+                    .map_err(|e| Status::internal(e.to_string()))?,
+            ),
+            None => None,
+        };
+
+        let (user_id, room_id, warehouse_id, storage_path) = match (res, storage_path) {
+            (Ok((user_id, room_id, warehouse_id)), Some(storage_path)) => {
+                (user_id, room_id, warehouse_id, storage_path)
+            }
+            (res, storage_path) => {
+                if let Some(storage_path) = storage_path {
+                    if let Err(e) = self.storage.remove(vec![storage_path]).await {
+                        error!("{e}");
+                    }
+                }
+
+                return Err(res.err().unwrap_or_else(|| {
+                    Status::invalid_argument("stream must contain at least one row")
+                }));
+            }
+        };
+
+        let request_id = self
+            .cmd
+            .send(CommandInput {
+                user_id: user_id.to_string(),
+                input: warehouse::ImportDataFileCommand {
+                    room_id,
+                    warehouse_id: warehouse_id.to_owned(),
+                    storage_path,
+                    format: warehouse::ImportFormat::Ndjson,
+                    mode: warehouse::ImportMode::Upsert,
+                },
+            })
+            .await
+            .map_err(mailbox_status)?
+            .map_err(command_status)?;
+
+        Ok(Response::new(ImportDataWarehouseReply {
+            id: warehouse_id,
+            request_id,
+        }))
+    }
+
+    async fn list_rooms(
+        &self,
+        request: Request<ListRoomsRequest>,
+    ) -> Result<Response<ListRoomsReply>, Status> {
+        let request = request.into_inner();
+
+        let order = match request.order() {
+            timada::SortOrder::Asc => SortOrder::Asc,
+            timada::SortOrder::Desc => SortOrder::Desc,
+        };
+
+        let res = self
+            .query
+            .send(room::ListRoomsQuery {
+                user_id: parse_user_id(&request.user_id)?,
+                query_args: QueryArgs {
+                    first: parse_limit("first", request.first)?,
+                    after: request.after,
+                    last: parse_limit("last", request.last)?,
+                    before: request.before,
+                },
+                name: request.name,
+                order,
+            })
+            .await
+            .map_err(mailbox_status)?
+            .map_err(command_status)?;
+
+        let edges = res
+            .edges
+            .into_iter()
+            .map(|edge| RoomEdge {
+                cursor: edge.cursor,
+                node: Some(to_room(edge.node)),
+            })
+            .collect();
+
+        Ok(Response::new(ListRoomsReply {
+            edges,
+            page_info: Some(to_page_info(res.page_info)),
+        }))
+    }
+
+    async fn list_warehouses(
+        &self,
+        request: Request<ListWarehousesRequest>,
+    ) -> Result<Response<ListWarehousesReply>, Status> {
+        let request = request.into_inner();
+
+        let warehouses = self
+            .query
+            .send(warehouse::ListWarehousesQuery {
+                user_id: parse_user_id(&request.user_id)?,
+                room_id: request.room_id,
+            })
+            .await
+            .map_err(mailbox_status)?
+            .map_err(command_status)?;
+
+        Ok(Response::new(ListWarehousesReply {
+            warehouses: warehouses
+                .into_iter()
+                .map(to_warehouse)
+                .collect::<Result<Vec<_>, Status>>()?,
+        }))
+    }
+
+    async fn list_warehouse_data(
+        &self,
+        request: Request<ListWarehouseDataRequest>,
+    ) -> Result<Response<ListWarehouseDataReply>, Status> {
+        let request = request.into_inner();
+
+        let res = self
+            .query
+            .send(warehouse::ListWarehouseDataQuery {
+                user_id: parse_user_id(&request.user_id)?,
+                room_id: request.room_id,
+                warehouse_id: request.warehouse_id,
+                query_args: QueryArgs {
+                    first: parse_limit("first", request.first)?,
+                    after: request.after,
+                    last: parse_limit("last", request.last)?,
+                    before: request.before,
+                },
+                filter: request.filter,
+                sort: request.sort,
+            })
+            .await
+            .map_err(mailbox_status)?
+            .map_err(command_status)?;
+
+        let edges = res
+            .edges
+            .into_iter()
+            .map(|edge| {
+                Ok(WarehouseDataEdge {
+                    cursor: edge.cursor,
+                    node: Some(to_warehouse_data(edge.node)?),
+                })
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+
+        Ok(Response::new(ListWarehouseDataReply {
+            edges,
+            page_info: Some(to_page_info(res.page_info)),
+        }))
+    }
+
+    async fn get_warehouse_data(
+        &self,
+        request: Request<GetWarehouseDataRequest>,
+    ) -> Result<Response<timada::WarehouseData>, Status> {
+        let request = request.into_inner();
+
+        let data = self
+            .query
+            .send(warehouse::GetWarehouseDataQuery {
+                user_id: parse_user_id(&request.user_id)?,
+                room_id: request.room_id,
+                warehouse_id: request.warehouse_id,
+                key: request.key,
+            })
+            .await
+            .map_err(mailbox_status)?
+            .map_err(command_status)?;
+
+        Ok(Response::new(to_warehouse_data(data)?))
+    }
+
+    async fn search_warehouse_data(
+        &self,
+        request: Request<SearchWarehouseDataRequest>,
+    ) -> Result<Response<SearchWarehouseDataReply>, Status> {
+        let request = request.into_inner();
+
+        let rows = self
+            .query
+            .send(warehouse::SearchWarehouseDataQuery {
+                user_id: parse_user_id(&request.user_id)?,
+                room_id: request.room_id,
+                warehouse_id: request.warehouse_id,
+                q: request.q,
+                limit: parse_limit("limit", request.limit)?,
+                offset: request.offset,
+            })
+            .await
+            .map_err(mailbox_status)?
+            .map_err(command_status)?;
+
+        let matches = rows
+            .into_iter()
+            .map(|row| {
+                Ok(WarehouseDataMatch {
+                    node: Some(to_warehouse_data(row.data)?),
+                    rank: row.rank,
+                    snippet: row.snippet,
+                })
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+
+        Ok(Response::new(SearchWarehouseDataReply { matches }))
+    }
+}
+
+fn to_room(room: room::Room) -> timada::Room {
+    timada::Room {
+        id: room.id,
+        name: room.name,
+        user_id: room.user_id.to_string(),
+        created_at: room.created_at.to_rfc3339(),
+        archived_at: room.archived_at.map(|v| v.to_rfc3339()),
+        updated_at: room.updated_at.map(|v| v.to_rfc3339()),
+    }
+}
+
+fn to_warehouse(warehouse: warehouse::Warehouse) -> Result<timada::Warehouse, Status> {
+    let schema = warehouse
+        .schema
+        .map(|schema| serde_json::to_string(&schema.0))
+        .transpose()
+        .map_err(|e| Status::internal(e.to_string()))?;
+
+    Ok(timada::Warehouse {
+        id: warehouse.id,
+        room_id: warehouse.room_id,
+        name: warehouse.name,
+        description: warehouse.description,
+        user_id: warehouse.user_id.to_string(),
+        schema,
+        created_at: warehouse.created_at.to_rfc3339(),
+    })
+}
+
+fn to_warehouse_data(data: warehouse::WarehouseData) -> Result<timada::WarehouseData, Status> {
+    Ok(timada::WarehouseData {
+        id: data.id,
+        key: data.key,
+        data: serde_json::to_string(&data.data).map_err(|e| Status::internal(e.to_string()))?,
+        created_at: data.created_at.to_rfc3339(),
+        updated_at: data.updated_at.map(|v| v.to_rfc3339()),
+    })
+}
+
+fn to_page_info(page_info: PageInfo) -> timada::PageInfo {
+    timada::PageInfo {
+        has_previous_page: page_info.has_previous_page,
+        has_next_page: page_info.has_next_page,
+        start_cursor: page_info.start_cursor,
+        end_cursor: page_info.end_cursor,
+    }
+}
+
+fn parse_user_id(user_id: &str) -> Result<Uuid, Status> {
+    Uuid::parse_str(user_id).map_err(|e| Status::invalid_argument(format!("user_id: {e}")))
+}
+
+fn parse_limit(name: &str, value: Option<u32>) -> Result<Option<u16>, Status> {
+    value
+        .map(u16::try_from)
+        .transpose()
+        .map_err(|e| Status::invalid_argument(format!("{name}: {e}")))
+}
+
+fn mailbox_status(e: MailboxError) -> Status {
+    Status::unavailable(e.to_string())
+}
+
+fn command_status(e: CommandError) -> Status {
+    match e {
+        CommandError::BadRequest(msg) => Status::invalid_argument(msg),
+        CommandError::NotFound(msg) => Status::not_found(msg),
+        CommandError::Forbidden(msg) => Status::permission_denied(msg),
+        e => Status::internal(e.to_string()),
     }
 }
 
+#[derive(Clone)]
 pub struct ClusterOptions {
     pub addr: String,
 }
 
 pub struct Cluster {
     pub options: ClusterOptions,
+    pub cmd: Addr<Command>,
+    pub query: Addr<Query>,
+    pub storage: Operator,
 }
 
 impl Cluster {
-    pub fn new(options: ClusterOptions) -> Self {
-        Self { options }
+    pub fn new(
+        options: ClusterOptions,
+        cmd: Addr<Command>,
+        query: Addr<Query>,
+        storage: Operator,
+    ) -> Self {
+        Self {
+            options,
+            cmd,
+            query,
+            storage,
+        }
     }
 
     pub async fn serve(&self) -> Result<(), tonic::transport::Error> {
         let addr = self.options.addr.parse().unwrap();
-        let cobase = Cobase {};
+        let cobase = Cobase {
+            cmd: self.cmd.clone(),
+            query: self.query.clone(),
+            storage: self.storage.clone(),
+        };
 
         info!("Cobase grpc listening on {addr}");
 
@@ -1,11 +1,16 @@
 use std::path::Path;
 
+use cobase::warehouse;
 use config::{Config, ConfigError, Environment, File};
 use serde::Deserialize;
 use sqlx::{
     migrate::{MigrateDatabase, Migrator},
     Any, PgPool,
 };
+use tracing::{info, Level};
+use tracing_subscriber::{
+    filter, prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt,
+};
 
 #[derive(Deserialize)]
 pub struct Migrate {
@@ -23,6 +28,11 @@ impl Migrate {
     }
 
     pub async fn run(&self) -> Result<(), std::io::Error> {
+        tracing_subscriber::registry()
+            .with(tracing_subscriber::fmt::layer())
+            .with(filter::Targets::new().with_target(env!("CARGO_CRATE_NAME"), Level::INFO))
+            .init();
+
         let dsn = self.dsn.replace("cockroach", "postgres");
         let exists = crate::retry_connect_errors(&dsn, Any::database_exists)
             .await
@@ -42,6 +52,15 @@ impl Migrate {
             .await
             .unwrap();
 
+        // Warehouse data tables are created by the projection, not by the migrations
+        let tables = warehouse::projection::migrate_data_tables(&pool)
+            .await
+            .unwrap();
+
+        for table in tables {
+            info!("successfully migrated {table} data");
+        }
+
         Ok(())
     }
 }
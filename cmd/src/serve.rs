@@ -4,7 +4,7 @@ use cobase::storage::Storage;
 use cobase_api::{
     App, AppOptions, EventoOptions, JwksOptions, OpenApiOptions, PikavOptions, SwaggerUIOptions,
 };
-use cobase_cluster::{Cluster, ClusterOptions};
+use cobase_cluster::ClusterOptions;
 use config::{Config, ConfigError, Environment, File};
 use serde::Deserialize;
 use tracing::Level;
@@ -60,13 +60,12 @@ impl Serve {
             .with(filter)
             .init();
 
-        let cluster = Cluster::new(ClusterOptions {
-            addr: self.addr.cluster.to_owned(),
-        });
-
         let app = App::new(AppOptions {
             zone: self.zone.to_owned(),
             listen: self.addr.api.to_owned(),
+            cluster: ClusterOptions {
+                addr: self.addr.cluster.to_owned(),
+            },
             dsn: self.dsn.to_owned(),
             jwks: self.jwks.clone(),
             pikav: self.pikav.clone(),
@@ -77,8 +76,6 @@ impl Serve {
             storage: self.storage.clone(),
         });
 
-        actix_rt::spawn(async move { cluster.serve().await });
-
         app.run().await
     }
 }
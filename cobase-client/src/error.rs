@@ -1,7 +1,58 @@
 use thiserror::Error as ThisError;
+use tonic::{Code, Status};
 
 #[derive(ThisError, Debug, Clone)]
 pub enum ClientError {
+    #[error("invalid url: {0}")]
+    InvalidUrl(String),
+
+    #[error("invalid metadata: {0}")]
+    InvalidMetadata(String),
+
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("not found: {0}")]
+    NotFound(String),
+
+    #[error("unauthenticated: {0}")]
+    Unauthenticated(String),
+
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+
+    #[error("unavailable: {0}")]
+    Unavailable(String),
+
+    #[error("timeout: {0}")]
+    Timeout(String),
+
+    #[error("internal: {0}")]
+    Internal(String),
+
     #[error("{0}")]
     Unknown(String),
 }
+
+impl From<Status> for ClientError {
+    fn from(status: Status) -> Self {
+        let message = status.message().to_owned();
+
+        match status.code() {
+            Code::InvalidArgument => ClientError::InvalidArgument(message),
+            Code::NotFound => ClientError::NotFound(message),
+            Code::Unauthenticated => ClientError::Unauthenticated(message),
+            Code::PermissionDenied => ClientError::PermissionDenied(message),
+            Code::Unavailable => ClientError::Unavailable(message),
+            Code::DeadlineExceeded => ClientError::Timeout(message),
+            Code::Internal => ClientError::Internal(message),
+            _ => ClientError::Unknown(message),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ClientError {
+    fn from(e: serde_json::Error) -> Self {
+        ClientError::InvalidArgument(e.to_string())
+    }
+}
@@ -1,9 +1,20 @@
-use error::ClientError;
-use serde::Deserialize;
-use timada::{cobase_client::CobaseClient, CreateRoomReply};
-use tonic::transport::Channel;
+use std::{future::Future, time::Duration};
 
-pub use timada::CreateRoomRequest;
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use timada::cobase_client::CobaseClient;
+use tonic::{metadata::AsciiMetadataValue, transport::Channel, Code};
+
+pub use error::ClientError;
+pub use timada::{
+    CreateRoomReply, CreateRoomRequest, CreateWarehouseReply, CreateWarehouseRequest,
+    GetWarehouseDataRequest, ImportDataWarehouseReply, ImportDataWarehouseRequest, ListRoomsReply,
+    ListRoomsRequest, ListWarehouseDataReply, ListWarehouseDataRequest, ListWarehousesReply,
+    ListWarehousesRequest, PageInfo, Room, RoomEdge, SearchWarehouseDataReply,
+    SearchWarehouseDataRequest, SortOrder, Warehouse, WarehouseData, WarehouseDataEdge,
+    WarehouseDataMatch,
+};
 pub use tonic::Status;
 
 mod error;
@@ -12,40 +23,277 @@ pub mod timada {
     tonic::include_proto!("timada");
 }
 
+const NAMESPACE_KEY: &str = "x-cobase-namespace";
+const AUTHORIZATION_KEY: &str = "authorization";
+const DEFAULT_RETRIES: usize = 3;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct ClientOptions<N: Into<String>> {
     pub url: String,
     pub namespace: N,
+    /// Sent as a bearer token in the `authorization` metadata of every call
+    pub token: Option<String>,
+    /// Per-call timeout in milliseconds
+    pub timeout: Option<u64>,
+    /// Number of retries of the read calls when the server is `Unavailable`, default to 3.
+    /// Calls that create rooms, warehouses or imports are never retried.
+    pub retries: Option<usize>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ClientInstanceOptions {
     pub url: String,
     pub namespace: Option<String>,
+    pub token: Option<String>,
+    pub timeout: Option<u64>,
+    pub retries: Option<usize>,
 }
 
 #[derive(Clone)]
 pub struct Client {
     channel: Channel,
+    namespace: Option<AsciiMetadataValue>,
+    authorization: Option<AsciiMetadataValue>,
+    timeout: Option<Duration>,
+    retries: usize,
 }
 
 impl Client {
-    pub fn new<N: Into<String>>(url: N) -> Result<Self, ClientError> {
-        let channel = Channel::from_shared(url.into())
-            .map_err(|e| ClientError::Unknown(e.to_string()))?
+    pub fn new<N: Into<String>>(options: ClientOptions<N>) -> Result<Self, ClientError> {
+        Self::from_instance(ClientInstanceOptions {
+            url: options.url,
+            namespace: Some(options.namespace.into()),
+            token: options.token,
+            timeout: options.timeout,
+            retries: options.retries,
+        })
+    }
+
+    pub fn from_instance(options: ClientInstanceOptions) -> Result<Self, ClientError> {
+        let channel = Channel::from_shared(options.url)
+            .map_err(|e| ClientError::InvalidUrl(e.to_string()))?
             .connect_lazy();
 
-        Ok(Self { channel })
+        let namespace = options
+            .namespace
+            .map(|namespace| parse_metadata(&namespace))
+            .transpose()?;
+
+        let authorization = options
+            .token
+            .map(|token| parse_metadata(&format!("Bearer {token}")))
+            .transpose()?;
+
+        Ok(Self {
+            channel,
+            namespace,
+            authorization,
+            timeout: options.timeout.map(Duration::from_millis),
+            retries: options.retries.unwrap_or(DEFAULT_RETRIES),
+        })
+    }
+
+    /// Returns a copy of the client using `timeout` for its calls
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self.clone()
+        }
     }
 
     pub async fn create_room(
         &self,
         message: CreateRoomRequest,
-    ) -> Result<tonic::Response<CreateRoomReply>, Status> {
+    ) -> Result<CreateRoomReply, ClientError> {
+        self.call_once(message, |mut client, request| async move {
+            client.create_room(request).await
+        })
+        .await
+    }
+
+    pub async fn import_data(
+        &self,
+        message: ImportDataWarehouseRequest,
+    ) -> Result<ImportDataWarehouseReply, ClientError> {
+        self.call_once(message, |mut client, request| async move {
+            client.import_data(request).await
+        })
+        .await
+    }
+
+    /// Uploads every batch of the stream as a single import, the stream can
+    /// only be consumed once so the call is never retried.
+    pub async fn import_data_stream<S>(
+        &self,
+        stream: S,
+    ) -> Result<ImportDataWarehouseReply, ClientError>
+    where
+        S: Stream<Item = ImportDataWarehouseRequest> + Send + 'static,
+    {
         let mut client = CobaseClient::new(self.channel.clone());
+        let res = self
+            .timeout_call(client.import_data_stream(self.request(stream)))
+            .await?;
+
+        Ok(res.into_inner())
+    }
+
+    pub async fn list_rooms(
+        &self,
+        message: ListRoomsRequest,
+    ) -> Result<ListRoomsReply, ClientError> {
+        self.call(message, |mut client, request| async move {
+            client.list_rooms(request).await
+        })
+        .await
+    }
+
+    pub async fn create_warehouse(
+        &self,
+        message: CreateWarehouseRequest,
+    ) -> Result<CreateWarehouseReply, ClientError> {
+        self.call_once(message, |mut client, request| async move {
+            client.create_warehouse(request).await
+        })
+        .await
+    }
+
+    pub async fn list_warehouses(
+        &self,
+        message: ListWarehousesRequest,
+    ) -> Result<ListWarehousesReply, ClientError> {
+        self.call(message, |mut client, request| async move {
+            client.list_warehouses(request).await
+        })
+        .await
+    }
+
+    pub async fn list_warehouse_data(
+        &self,
+        message: ListWarehouseDataRequest,
+    ) -> Result<ListWarehouseDataReply, ClientError> {
+        self.call(message, |mut client, request| async move {
+            client.list_warehouse_data(request).await
+        })
+        .await
+    }
 
-        let request = tonic::Request::new(message);
+    pub async fn get_warehouse_data(
+        &self,
+        message: GetWarehouseDataRequest,
+    ) -> Result<WarehouseData, ClientError> {
+        self.call(message, |mut client, request| async move {
+            client.get_warehouse_data(request).await
+        })
+        .await
+    }
+
+    pub async fn search_warehouse_data(
+        &self,
+        message: SearchWarehouseDataRequest,
+    ) -> Result<SearchWarehouseDataReply, ClientError> {
+        self.call(message, |mut client, request| async move {
+            client.search_warehouse_data(request).await
+        })
+        .await
+    }
+
+    /// Calls that are not idempotent, a retry after `Unavailable` could apply them twice
+    async fn call_once<M, R, F, Fut>(&self, message: M, f: F) -> Result<R, ClientError>
+    where
+        F: FnOnce(CobaseClient<Channel>, tonic::Request<M>) -> Fut,
+        Fut: Future<Output = Result<tonic::Response<R>, Status>>,
+    {
+        let client = CobaseClient::new(self.channel.clone());
+        let res = self.timeout_call(f(client, self.request(message))).await?;
+
+        Ok(res.into_inner())
+    }
+
+    async fn call<M, R, F, Fut>(&self, message: M, f: F) -> Result<R, ClientError>
+    where
+        M: Clone,
+        F: Fn(CobaseClient<Channel>, tonic::Request<M>) -> Fut,
+        Fut: Future<Output = Result<tonic::Response<R>, Status>>,
+    {
+        let mut attempt = 0;
+
+        loop {
+            let client = CobaseClient::new(self.channel.clone());
+            let fut = f(client, self.request(message.clone()));
+
+            match self.timeout_call(fut).await {
+                Ok(res) => return Ok(res.into_inner()),
+                Err(status) if status.code() == Code::Unavailable && attempt < self.retries => {
+                    tokio::time::sleep(Duration::from_millis(100 * 2u64.pow(attempt as u32))).await;
+
+                    attempt += 1;
+                }
+                Err(status) => return Err(status.into()),
+            }
+        }
+    }
+
+    async fn timeout_call<R, Fut>(&self, fut: Fut) -> Result<R, Status>
+    where
+        Fut: Future<Output = Result<R, Status>>,
+    {
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, fut)
+                .await
+                .map_err(|e| Status::deadline_exceeded(e.to_string()))
+                .and_then(|res| res),
+            _ => fut.await,
+        }
+    }
+
+    fn request<M>(&self, message: M) -> tonic::Request<M> {
+        let mut request = tonic::Request::new(message);
+
+        if let Some(timeout) = self.timeout {
+            request.set_timeout(timeout);
+        }
+
+        if let Some(namespace) = &self.namespace {
+            request
+                .metadata_mut()
+                .insert(NAMESPACE_KEY, namespace.clone());
+        }
+
+        if let Some(authorization) = &self.authorization {
+            request
+                .metadata_mut()
+                .insert(AUTHORIZATION_KEY, authorization.clone());
+        }
+
+        request
+    }
+}
+
+fn parse_metadata(value: &str) -> Result<AsciiMetadataValue, ClientError> {
+    value
+        .parse()
+        .map_err(|_| ClientError::InvalidMetadata(value.to_owned()))
+}
+
+impl ImportDataWarehouseRequest {
+    pub fn from_rows<U: Into<String>, R: Into<String>, W: Into<String>, T: Serialize>(
+        user_id: U,
+        room_id: R,
+        warehouse_id: W,
+        rows: &[T],
+    ) -> Result<Self, ClientError> {
+        Ok(Self {
+            user_id: user_id.into(),
+            data: serde_json::to_string(rows)?,
+            room_id: room_id.into(),
+            warehouse_id: warehouse_id.into(),
+        })
+    }
+}
 
-        client.create_room(request).await
+impl WarehouseData {
+    pub fn to_value(&self) -> Result<Value, ClientError> {
+        Ok(serde_json::from_str(&self.data)?)
     }
 }
@@ -62,7 +62,9 @@ mod tests {
             .data(pikav_client.clone())
             .data(storage.clone())
             .subscribe(crate::room::projection::rooms())
-            .subscribe(crate::warehouse::projection::warehouse_data());
+            .subscribe(crate::warehouse::projection::warehouse_data())
+            .subscribe(crate::warehouse::projection::warehouse_fields())
+            .subscribe(crate::warehouse::projection::warehouse_exports());
         let producer = evento.run(0).await.unwrap();
         let command = Command::new(evento.clone(), producer, storage.clone()).start();
         let query = Query::new(pool.clone()).start();
@@ -1,6 +1,11 @@
 use actix::{Actor, Context};
+use evento::query::{PageInfo, QueryArgs, QueryResult};
+use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 
+/// Page size used when a query does not specify `first` or `last`
+pub(crate) const DEFAULT_LIMIT: u16 = 40;
+
 pub struct Query {
     pub pool: PgPool,
 }
@@ -14,3 +19,52 @@ impl Query {
 impl Actor for Query {
     type Context = Context<Self>;
 }
+
+#[derive(Default, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    /// Cursors only paginate in ascending order, a descending page is the
+    /// reversed ascending page going the other way, see `SortOrder::reverse`.
+    pub fn args(self, args: QueryArgs) -> QueryArgs {
+        if self == SortOrder::Asc {
+            return args;
+        }
+
+        let last = match (args.first, args.last) {
+            (None, None) => Some(DEFAULT_LIMIT),
+            (first, _) => first,
+        };
+
+        QueryArgs {
+            first: args.last,
+            after: args.before,
+            last,
+            before: args.after,
+        }
+    }
+
+    pub fn reverse<N>(self, res: QueryResult<N>) -> QueryResult<N> {
+        if self == SortOrder::Asc {
+            return res;
+        }
+
+        let mut edges = res.edges;
+        edges.reverse();
+
+        QueryResult {
+            edges,
+            page_info: PageInfo {
+                has_previous_page: res.page_info.has_next_page,
+                has_next_page: res.page_info.has_previous_page,
+                start_cursor: res.page_info.end_cursor,
+                end_cursor: res.page_info.start_cursor,
+            },
+        }
+    }
+}
@@ -1,12 +1,41 @@
-use evento::Aggregate;
+use std::collections::HashMap;
+
+use evento::{Aggregate, CommandError};
 use serde::{Deserialize, Serialize};
 
-use super::event::{Created, RoomEvent};
+use crate::command::CommandMetadata;
+
+use super::event::{Created, MemberInvited, MemberJoined, MemberRemoved, Renamed, Role, RoomEvent};
+
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Member {
+    pub role: Role,
+    pub joined: bool,
+}
 
 #[derive(Default, Serialize, Deserialize)]
 pub struct Room {
     pub id: String,
     pub name: String,
+    pub user_id: String,
+    pub archived: bool,
+    pub deleted: bool,
+    pub members: HashMap<String, Member>,
+}
+
+impl Room {
+    /// Returns an error unless `user_id` joined the room with at least `role`, the room
+    /// is not found for users that did not join it like in queries and forbidden for
+    /// members with a lower role
+    pub fn check_role(&self, id: &str, user_id: &str, role: Role) -> Result<(), CommandError> {
+        match self.members.get(user_id) {
+            Some(member) if member.joined && member.role >= role => Ok(()),
+            Some(member) if member.joined => Err(CommandError::Forbidden(format!(
+                "user {user_id} requires {role} role on room {id}"
+            ))),
+            _ => Err(CommandError::NotFound(format!("room {id} not found"))),
+        }
+    }
 }
 
 impl Aggregate for Room {
@@ -16,7 +45,46 @@ impl Aggregate for Room {
         match room_event {
             RoomEvent::Created => {
                 let data: Created = event.to_data().unwrap();
+                let metadata: CommandMetadata = event.to_metadata().unwrap();
                 self.name = data.name;
+                self.user_id = metadata.request_by.to_owned();
+                self.members.insert(
+                    metadata.request_by,
+                    Member {
+                        role: Role::Owner,
+                        joined: true,
+                    },
+                );
+            }
+            RoomEvent::Renamed => {
+                let data: Renamed = event.to_data().unwrap();
+                self.name = data.name;
+            }
+            RoomEvent::Archived => {
+                self.archived = true;
+            }
+            RoomEvent::Deleted => {
+                self.deleted = true;
+            }
+            RoomEvent::MemberInvited => {
+                let data: MemberInvited = event.to_data().unwrap();
+                self.members.insert(
+                    data.user_id,
+                    Member {
+                        role: data.role,
+                        joined: false,
+                    },
+                );
+            }
+            RoomEvent::MemberJoined => {
+                let data: MemberJoined = event.to_data().unwrap();
+                if let Some(member) = self.members.get_mut(&data.user_id) {
+                    member.joined = true;
+                }
+            }
+            RoomEvent::MemberRemoved => {
+                let data: MemberRemoved = event.to_data().unwrap();
+                self.members.remove(&data.user_id);
             }
         }
     }
@@ -1,19 +1,32 @@
 use actix::{ActorFutureExt, Context, Handler, ResponseActFuture, WrapFuture};
-use evento::{CommandResult, Event};
+use evento::{CommandError, CommandResult, Event, PgEvento};
 use nanoid::nanoid;
 use serde::Deserialize;
 use uuid::Uuid;
+use validator::Validate;
 
 use crate::command::{Command, CommandInput, CommandMetadata};
 
 use super::{
     aggregate::Room,
-    event::{Created, RoomEvent},
+    event::{
+        Archived, Created, Deleted, MemberInvited, MemberJoined, MemberRemoved, Renamed, Role,
+        RoomEvent,
+    },
 };
 
-#[derive(Deserialize)]
+/// Loads a room that is not deleted, returning it with its last event.
+pub(crate) async fn load_room(evento: &PgEvento, id: &str) -> Result<(Room, Event), CommandError> {
+    match evento.load::<Room, _>(id).await? {
+        Some((room, event)) if !room.deleted => Ok((room, event)),
+        _ => Err(CommandError::NotFound(format!("room {id} not found"))),
+    }
+}
+
+#[derive(Deserialize, Validate)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateCommand {
+    #[validate(length(min = 1, max = 50))]
     pub name: String,
 }
 
@@ -28,6 +41,10 @@ impl Handler<CommandInput<CreateCommand>> for Command {
         let producer = self.producer.clone();
 
         async move {
+            msg.input
+                .validate()
+                .map_err(|e| CommandError::BadRequest(e.to_string()))?;
+
             let id = nanoid!();
             let request_id = Uuid::new_v4();
 
@@ -52,3 +69,349 @@ impl Handler<CommandInput<CreateCommand>> for Command {
         .boxed_local()
     }
 }
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct RenameCommand {
+    pub id: String,
+    #[validate(length(min = 1, max = 50))]
+    pub name: String,
+}
+
+impl Handler<CommandInput<RenameCommand>> for Command {
+    type Result = ResponseActFuture<Self, CommandResult>;
+
+    fn handle(
+        &mut self,
+        msg: CommandInput<RenameCommand>,
+        _ctx: &mut Context<Self>,
+    ) -> Self::Result {
+        let evento = self.evento.clone();
+        let producer = self.producer.clone();
+
+        async move {
+            msg.input
+                .validate()
+                .map_err(|e| CommandError::BadRequest(e.to_string()))?;
+
+            let (room, event) = load_room(&evento, &msg.input.id).await?;
+            room.check_role(&msg.input.id, &msg.user_id, Role::Editor)?;
+
+            if room.archived {
+                return Err(CommandError::BadRequest(format!(
+                    "room {} is archived",
+                    msg.input.id
+                )));
+            }
+
+            let request_id = Uuid::new_v4();
+
+            producer
+                .publish::<Room, _>(
+                    &msg.input.id,
+                    vec![Event::new(RoomEvent::Renamed)
+                        .data(Renamed {
+                            name: msg.input.name,
+                        })?
+                        .metadata(CommandMetadata {
+                            request_by: msg.user_id,
+                            request_id: request_id.to_string(),
+                        })?],
+                    event.version,
+                )
+                .await?;
+
+            Ok(msg.input.id)
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveCommand {
+    pub id: String,
+}
+
+impl Handler<CommandInput<ArchiveCommand>> for Command {
+    type Result = ResponseActFuture<Self, CommandResult>;
+
+    fn handle(
+        &mut self,
+        msg: CommandInput<ArchiveCommand>,
+        _ctx: &mut Context<Self>,
+    ) -> Self::Result {
+        let evento = self.evento.clone();
+        let producer = self.producer.clone();
+
+        async move {
+            let (room, event) = load_room(&evento, &msg.input.id).await?;
+            room.check_role(&msg.input.id, &msg.user_id, Role::Owner)?;
+
+            if room.archived {
+                return Err(CommandError::BadRequest(format!(
+                    "room {} is already archived",
+                    msg.input.id
+                )));
+            }
+
+            let request_id = Uuid::new_v4();
+
+            producer
+                .publish::<Room, _>(
+                    &msg.input.id,
+                    vec![Event::new(RoomEvent::Archived)
+                        .data(Archived {})?
+                        .metadata(CommandMetadata {
+                            request_by: msg.user_id,
+                            request_id: request_id.to_string(),
+                        })?],
+                    event.version,
+                )
+                .await?;
+
+            Ok(msg.input.id)
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteCommand {
+    pub id: String,
+}
+
+impl Handler<CommandInput<DeleteCommand>> for Command {
+    type Result = ResponseActFuture<Self, CommandResult>;
+
+    fn handle(
+        &mut self,
+        msg: CommandInput<DeleteCommand>,
+        _ctx: &mut Context<Self>,
+    ) -> Self::Result {
+        let evento = self.evento.clone();
+        let producer = self.producer.clone();
+
+        async move {
+            let (room, event) = load_room(&evento, &msg.input.id).await?;
+            room.check_role(&msg.input.id, &msg.user_id, Role::Owner)?;
+
+            let request_id = Uuid::new_v4();
+
+            producer
+                .publish::<Room, _>(
+                    &msg.input.id,
+                    vec![Event::new(RoomEvent::Deleted).data(Deleted {})?.metadata(
+                        CommandMetadata {
+                            request_by: msg.user_id,
+                            request_id: request_id.to_string(),
+                        },
+                    )?],
+                    event.version,
+                )
+                .await?;
+
+            Ok(msg.input.id)
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteMemberCommand {
+    pub id: String,
+    pub user_id: String,
+    pub role: Role,
+}
+
+impl Handler<CommandInput<InviteMemberCommand>> for Command {
+    type Result = ResponseActFuture<Self, CommandResult>;
+
+    fn handle(
+        &mut self,
+        msg: CommandInput<InviteMemberCommand>,
+        _ctx: &mut Context<Self>,
+    ) -> Self::Result {
+        let evento = self.evento.clone();
+        let producer = self.producer.clone();
+
+        async move {
+            let (room, event) = load_room(&evento, &msg.input.id).await?;
+            room.check_role(&msg.input.id, &msg.user_id, Role::Owner)?;
+
+            if room.archived {
+                return Err(CommandError::BadRequest(format!(
+                    "room {} is archived",
+                    msg.input.id
+                )));
+            }
+
+            let user_id = Uuid::parse_str(&msg.input.user_id)
+                .map_err(|e| CommandError::BadRequest(format!("user_id: {e}")))?
+                .to_string();
+
+            if msg.input.role == Role::Owner {
+                return Err(CommandError::BadRequest(format!(
+                    "room {} can only have one owner",
+                    msg.input.id
+                )));
+            }
+
+            if matches!(room.members.get(&user_id), Some(member) if member.joined) {
+                return Err(CommandError::BadRequest(format!(
+                    "user {user_id} is already a member of room {}",
+                    msg.input.id
+                )));
+            }
+
+            let request_id = Uuid::new_v4();
+
+            producer
+                .publish::<Room, _>(
+                    &msg.input.id,
+                    vec![Event::new(RoomEvent::MemberInvited)
+                        .data(MemberInvited {
+                            user_id,
+                            role: msg.input.role,
+                        })?
+                        .metadata(CommandMetadata {
+                            request_by: msg.user_id,
+                            request_id: request_id.to_string(),
+                        })?],
+                    event.version,
+                )
+                .await?;
+
+            Ok(msg.input.id)
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JoinCommand {
+    pub id: String,
+}
+
+impl Handler<CommandInput<JoinCommand>> for Command {
+    type Result = ResponseActFuture<Self, CommandResult>;
+
+    fn handle(&mut self, msg: CommandInput<JoinCommand>, _ctx: &mut Context<Self>) -> Self::Result {
+        let evento = self.evento.clone();
+        let producer = self.producer.clone();
+
+        async move {
+            let (room, event) = load_room(&evento, &msg.input.id).await?;
+
+            match room.members.get(&msg.user_id) {
+                Some(member) if member.joined => {
+                    return Err(CommandError::BadRequest(format!(
+                        "user {} is already a member of room {}",
+                        msg.user_id, msg.input.id
+                    )))
+                }
+                Some(_) => {}
+                None => {
+                    return Err(CommandError::NotFound(format!(
+                        "invitation to room {} not found",
+                        msg.input.id
+                    )))
+                }
+            };
+
+            let request_id = Uuid::new_v4();
+
+            producer
+                .publish::<Room, _>(
+                    &msg.input.id,
+                    vec![Event::new(RoomEvent::MemberJoined)
+                        .data(MemberJoined {
+                            user_id: msg.user_id.to_owned(),
+                        })?
+                        .metadata(CommandMetadata {
+                            request_by: msg.user_id,
+                            request_id: request_id.to_string(),
+                        })?],
+                    event.version,
+                )
+                .await?;
+
+            Ok(msg.input.id)
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveMemberCommand {
+    pub id: String,
+    pub user_id: String,
+}
+
+impl Handler<CommandInput<RemoveMemberCommand>> for Command {
+    type Result = ResponseActFuture<Self, CommandResult>;
+
+    fn handle(
+        &mut self,
+        msg: CommandInput<RemoveMemberCommand>,
+        _ctx: &mut Context<Self>,
+    ) -> Self::Result {
+        let evento = self.evento.clone();
+        let producer = self.producer.clone();
+
+        async move {
+            let (room, event) = load_room(&evento, &msg.input.id).await?;
+
+            // Any member can leave a room, only the owner can remove others
+            if msg.input.user_id != msg.user_id {
+                room.check_role(&msg.input.id, &msg.user_id, Role::Owner)?;
+            }
+
+            match room.members.get(&msg.input.user_id) {
+                Some(member) if member.role == Role::Owner => {
+                    return Err(CommandError::BadRequest(format!(
+                        "owner of room {} cannot be removed",
+                        msg.input.id
+                    )))
+                }
+                Some(_) => {}
+                None => {
+                    return Err(CommandError::NotFound(format!(
+                        "member {} of room {} not found",
+                        msg.input.user_id, msg.input.id
+                    )))
+                }
+            };
+
+            let request_id = Uuid::new_v4();
+
+            producer
+                .publish::<Room, _>(
+                    &msg.input.id,
+                    vec![Event::new(RoomEvent::MemberRemoved)
+                        .data(MemberRemoved {
+                            user_id: msg.input.user_id,
+                        })?
+                        .metadata(CommandMetadata {
+                            request_by: msg.user_id,
+                            request_id: request_id.to_string(),
+                        })?],
+                    event.version,
+                )
+                .await?;
+
+            Ok(msg.input.id)
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
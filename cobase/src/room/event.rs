@@ -5,6 +5,12 @@ use serde::{Deserialize, Serialize};
 #[display(style = "kebab-case")]
 pub enum RoomEvent {
     Created,
+    Renamed,
+    Archived,
+    Deleted,
+    MemberInvited,
+    MemberJoined,
+    MemberRemoved,
 }
 
 impl From<RoomEvent> for String {
@@ -13,7 +19,58 @@ impl From<RoomEvent> for String {
     }
 }
 
+/// Role levels of a room member, ordered from the least to the most privileged
+#[derive(
+    Display,
+    FromStr,
+    Default,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+)]
+#[display(style = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    Viewer,
+    Editor,
+    Owner,
+}
+
 #[derive(Default, Serialize, Deserialize)]
 pub struct Created {
     pub name: String,
 }
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Renamed {
+    pub name: String,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Archived {}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Deleted {}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct MemberInvited {
+    pub user_id: String,
+    pub role: Role,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct MemberJoined {
+    pub user_id: String,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct MemberRemoved {
+    pub user_id: String,
+}
@@ -6,7 +6,9 @@ mod query;
 pub mod projection;
 
 pub use command::*;
-pub use projection::Room;
+pub(crate) use command::load_room;
+pub use event::Role;
+pub use projection::{Room, RoomMember};
 pub use query::*;
 
 #[cfg(test)]
@@ -15,13 +17,38 @@ mod tests {
     use tokio::time::{sleep, Duration};
     use uuid::Uuid;
 
+    use evento::CommandError;
+
+    use evento::query::QueryArgs;
+
     use crate::{
-        command::Command,
-        query::Query,
-        room::{projection, CreateCommand, ListRoomsQuery},
+        command::{Command, CommandInput},
+        query::{Query, SortOrder},
+        room::{
+            projection, ArchiveCommand, CreateCommand, DeleteCommand, GetRoomQuery,
+            InviteMemberCommand, JoinCommand, ListRoomMembersQuery, ListRoomsQuery,
+            RemoveMemberCommand, RenameCommand, Role,
+        },
         tests::create_context,
     };
 
+    async fn list_rooms(query: &Addr<Query>, user_id: Uuid) -> Vec<projection::Room> {
+        query
+            .send(ListRoomsQuery {
+                user_id,
+                query_args: QueryArgs::default(),
+                name: None,
+                order: SortOrder::Asc,
+            })
+            .await
+            .unwrap()
+            .unwrap()
+            .edges
+            .into_iter()
+            .map(|edge| edge.node)
+            .collect()
+    }
+
     #[actix::test]
     async fn success_create_room() {
         let ctx = create_context("success_create_room").await;
@@ -42,13 +69,7 @@ mod tests {
 
         sleep(Duration::from_millis(300)).await;
 
-        let rooms = query
-            .send(ListRoomsQuery {
-                user_id: user_id.to_owned(),
-            })
-            .await
-            .unwrap()
-            .unwrap();
+        let rooms = list_rooms(query, user_id).await;
 
         assert_eq!(
             rooms,
@@ -56,8 +77,450 @@ mod tests {
                 id,
                 name: "Central park".to_owned(),
                 user_id,
+                archived_at: None,
+                updated_at: None,
                 created_at: rooms[0].created_at.to_owned()
             }]
         );
     }
+
+    #[actix::test]
+    async fn fail_invalid_room_name() {
+        let ctx = create_context("fail_invalid_room_name").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let user_id = Uuid::new_v4();
+
+        for name in [String::new(), "a".repeat(51)] {
+            let err = cmd
+                .send(CommandInput {
+                    user_id: user_id.to_string(),
+                    input: CreateCommand { name },
+                })
+                .await
+                .unwrap()
+                .unwrap_err();
+
+            assert!(matches!(err, CommandError::BadRequest(_)), "{err:?}");
+        }
+
+        let id = cmd
+            .send(CommandInput {
+                user_id: user_id.to_string(),
+                input: CreateCommand {
+                    name: "Central park".to_owned(),
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let err = cmd
+            .send(CommandInput {
+                user_id: user_id.to_string(),
+                input: RenameCommand {
+                    id,
+                    name: String::new(),
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert!(matches!(err, CommandError::BadRequest(_)), "{err:?}");
+    }
+
+    #[actix::test]
+    async fn success_rename_archive_delete_room() {
+        let ctx = create_context("success_rename_archive_delete_room").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let user_id = Uuid::new_v4();
+        let other_user_id = Uuid::new_v4();
+
+        let id = cmd
+            .send(CommandInput {
+                user_id: user_id.to_string(),
+                input: CreateCommand {
+                    name: "Central park".to_owned(),
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let err = cmd
+            .send(CommandInput {
+                user_id: other_user_id.to_string(),
+                input: RenameCommand {
+                    id: id.to_owned(),
+                    name: "Not my room".to_owned(),
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::NotFound(format!("room {id} not found"))
+        );
+
+        cmd.send(CommandInput {
+            user_id: user_id.to_string(),
+            input: RenameCommand {
+                id: id.to_owned(),
+                name: "Hyde park".to_owned(),
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        cmd.send(CommandInput {
+            user_id: user_id.to_string(),
+            input: ArchiveCommand { id: id.to_owned() },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        let err = cmd
+            .send(CommandInput {
+                user_id: user_id.to_string(),
+                input: RenameCommand {
+                    id: id.to_owned(),
+                    name: "Regent's park".to_owned(),
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::BadRequest(format!("room {id} is archived"))
+        );
+
+        let err = cmd
+            .send(CommandInput {
+                user_id: user_id.to_string(),
+                input: InviteMemberCommand {
+                    id: id.to_owned(),
+                    user_id: Uuid::new_v4().to_string(),
+                    role: Role::Editor,
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::BadRequest(format!("room {id} is archived"))
+        );
+
+        sleep(Duration::from_millis(300)).await;
+
+        let rooms = list_rooms(query, user_id).await;
+
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(rooms[0].name, "Hyde park");
+        assert!(rooms[0].archived_at.is_some());
+
+        cmd.send(CommandInput {
+            user_id: user_id.to_string(),
+            input: DeleteCommand { id: id.to_owned() },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        let err = cmd
+            .send(CommandInput {
+                user_id: user_id.to_string(),
+                input: DeleteCommand { id: id.to_owned() },
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(err, CommandError::NotFound(format!("room {id} not found")));
+
+        sleep(Duration::from_millis(300)).await;
+
+        let rooms = list_rooms(query, user_id).await;
+
+        assert!(rooms.is_empty());
+
+        let err = query
+            .send(ListRoomMembersQuery {
+                user_id,
+                room_id: id.to_owned(),
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(err, CommandError::NotFound(format!("room {id} not found")));
+    }
+
+    #[actix::test]
+    async fn success_share_room() {
+        let ctx = create_context("success_share_room").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let owner_id = Uuid::new_v4();
+        let viewer_id = Uuid::new_v4();
+
+        let id = cmd
+            .send(CommandInput {
+                user_id: owner_id.to_string(),
+                input: CreateCommand {
+                    name: "Central park".to_owned(),
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        cmd.send(CommandInput {
+            user_id: owner_id.to_string(),
+            input: InviteMemberCommand {
+                id: id.to_owned(),
+                user_id: viewer_id.to_string(),
+                role: Role::Viewer,
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let rooms = list_rooms(query, viewer_id).await;
+
+        assert!(rooms.is_empty());
+
+        cmd.send(CommandInput {
+            user_id: viewer_id.to_string(),
+            input: JoinCommand { id: id.to_owned() },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        let err = cmd
+            .send(CommandInput {
+                user_id: viewer_id.to_string(),
+                input: RenameCommand {
+                    id: id.to_owned(),
+                    name: "Hyde park".to_owned(),
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::Forbidden(format!(
+                "user {viewer_id} requires editor role on room {id}"
+            ))
+        );
+
+        sleep(Duration::from_millis(300)).await;
+
+        let rooms = list_rooms(query, viewer_id).await;
+
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(rooms[0].id, id);
+
+        let members = query
+            .send(ListRoomMembersQuery {
+                user_id: viewer_id,
+                room_id: id.to_owned(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            members
+                .iter()
+                .map(|member| (member.user_id, member.role.to_owned()))
+                .collect::<Vec<_>>(),
+            vec![
+                (owner_id, "owner".to_owned()),
+                (viewer_id, "viewer".to_owned())
+            ]
+        );
+
+        cmd.send(CommandInput {
+            user_id: owner_id.to_string(),
+            input: RemoveMemberCommand {
+                id: id.to_owned(),
+                user_id: viewer_id.to_string(),
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let rooms = list_rooms(query, viewer_id).await;
+
+        assert!(rooms.is_empty());
+    }
+
+    #[actix::test]
+    async fn success_get_room() {
+        let ctx = create_context("success_get_room").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let user_id = Uuid::new_v4();
+        let other_user_id = Uuid::new_v4();
+
+        let id = cmd
+            .send(CommandInput {
+                user_id: user_id.to_string(),
+                input: CreateCommand {
+                    name: "Central park".to_owned(),
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let room = query
+            .send(GetRoomQuery {
+                user_id,
+                id: id.to_owned(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(room.id, id);
+        assert_eq!(room.name, "Central park");
+
+        let err = query
+            .send(GetRoomQuery {
+                user_id: other_user_id,
+                id: id.to_owned(),
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(err, CommandError::NotFound(format!("room {id} not found")));
+    }
+
+    #[actix::test]
+    async fn success_paginate_rooms() {
+        let ctx = create_context("success_paginate_rooms").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let user_id = Uuid::new_v4();
+
+        for name in [
+            "Central park",
+            "Hyde park",
+            "Regent's park",
+            "Bois de Boulogne",
+        ] {
+            cmd.send(CommandInput {
+                user_id: user_id.to_string(),
+                input: CreateCommand {
+                    name: name.to_owned(),
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+            sleep(Duration::from_millis(10)).await;
+        }
+
+        sleep(Duration::from_millis(300)).await;
+
+        let res = query
+            .send(ListRoomsQuery {
+                user_id,
+                query_args: QueryArgs {
+                    first: Some(2),
+                    ..Default::default()
+                },
+                name: None,
+                order: SortOrder::Asc,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            res.edges.iter().map(|e| &e.node.name).collect::<Vec<_>>(),
+            vec!["Central park", "Hyde park"]
+        );
+        assert!(res.page_info.has_next_page);
+
+        let res = query
+            .send(ListRoomsQuery {
+                user_id,
+                query_args: QueryArgs {
+                    first: Some(2),
+                    after: res.page_info.end_cursor,
+                    ..Default::default()
+                },
+                name: None,
+                order: SortOrder::Asc,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            res.edges.iter().map(|e| &e.node.name).collect::<Vec<_>>(),
+            vec!["Regent's park", "Bois de Boulogne"]
+        );
+        assert!(!res.page_info.has_next_page);
+
+        let res = query
+            .send(ListRoomsQuery {
+                user_id,
+                query_args: QueryArgs {
+                    first: Some(2),
+                    ..Default::default()
+                },
+                name: None,
+                order: SortOrder::Desc,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            res.edges.iter().map(|e| &e.node.name).collect::<Vec<_>>(),
+            vec!["Bois de Boulogne", "Regent's park"]
+        );
+        assert!(res.page_info.has_next_page);
+
+        let res = query
+            .send(ListRoomsQuery {
+                user_id,
+                query_args: QueryArgs::default(),
+                name: Some("PARK".to_owned()),
+                order: SortOrder::Desc,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            res.edges.iter().map(|e| &e.node.name).collect::<Vec<_>>(),
+            vec!["Regent's park", "Hyde park", "Central park"]
+        );
+    }
 }
@@ -1,15 +1,15 @@
 use chrono::{DateTime, Utc};
-use evento::{Aggregate, Subscriber};
+use evento::{query::Cursor, Aggregate, Subscriber};
 use futures::FutureExt;
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool};
+use sqlx::{FromRow, PgPool, Postgres};
 use uuid::Uuid;
 
 use crate::{command::CommandMetadata, room::event::RoomEvent};
 
 use super::{
     aggregate::{self},
-    event::Created,
+    event::{Created, MemberInvited, MemberJoined, MemberRemoved, Renamed, Role},
 };
 
 #[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, FromRow)]
@@ -17,9 +17,85 @@ pub struct Room {
     pub id: String,
     pub name: String,
     pub user_id: Uuid,
+    pub archived_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
 }
 
+impl Cursor for Room {
+    fn keys() -> Vec<&'static str> {
+        vec!["created_at", "id"]
+    }
+
+    fn bind<'q, O>(
+        self,
+        query: sqlx::query::QueryAs<Postgres, O, sqlx::postgres::PgArguments>,
+    ) -> sqlx::query::QueryAs<Postgres, O, sqlx::postgres::PgArguments>
+    where
+        O: for<'r> FromRow<'r, <sqlx::Postgres as sqlx::Database>::Row>,
+        O: 'q + std::marker::Send,
+        O: 'q + Unpin,
+        O: 'q + Cursor,
+    {
+        query.bind(self.created_at).bind(self.id)
+    }
+
+    fn serialize(&self) -> Vec<String> {
+        vec![Self::serialize_utc(self.created_at), self.id.to_owned()]
+    }
+
+    fn deserialize(values: Vec<&str>) -> Result<Self, evento::query::CursorError> {
+        let mut values = values.iter();
+        let created_at = Self::deserialize_as_utc("created_at", values.next())?;
+        let id = Self::deserialize_as("id", values.next())?;
+
+        Ok(Room {
+            id,
+            created_at,
+            ..Default::default()
+        })
+    }
+}
+
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, FromRow)]
+pub struct RoomMember {
+    pub room_id: String,
+    pub user_id: Uuid,
+    pub role: String,
+    pub joined_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Publishes an event on `rooms/{id}` to every member of the room, invited or joined
+async fn publish_to_members(
+    db: &PgPool,
+    pikav: &pikav_client::Client,
+    room_id: &str,
+    name: &str,
+    data: serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    let user_ids =
+        sqlx::query_as::<_, (Uuid,)>("SELECT user_id FROM room_members WHERE room_id = $1")
+            .bind(room_id)
+            .fetch_all(db)
+            .await?;
+
+    pikav.publish(
+        user_ids
+            .into_iter()
+            .map(|(user_id,)| pikav_client::Event {
+                user_id: user_id.to_string(),
+                topic: format!("rooms/{room_id}"),
+                name: name.to_owned(),
+                data: Some(data.clone().into()),
+                metadata: None,
+            })
+            .collect(),
+    );
+
+    Ok(())
+}
+
 pub fn rooms() -> Subscriber {
     Subscriber::new("rooms")
         .filter("room/#")
@@ -30,16 +106,18 @@ pub fn rooms() -> Subscriber {
             async move {
                 let room_event: RoomEvent = event.name.parse()?;
                 let metadata = event.to_metadata::<CommandMetadata>()?;
+                let id = aggregate::Room::to_id(event.aggregate_id.to_owned());
 
                 match room_event {
                     RoomEvent::Created => {
                         let data: Created = event.to_data()?;
 
                         let room = Room {
-                            id: aggregate::Room::to_id(event.aggregate_id),
+                            id,
                             name: data.name,
                             user_id: Uuid::parse_str(&metadata.request_by)?,
                             created_at: event.created_at,
+                            ..Default::default()
                         };
 
                         sqlx::query::<_>(
@@ -52,6 +130,16 @@ pub fn rooms() -> Subscriber {
                         .execute(&db)
                         .await?;
 
+                        sqlx::query::<_>(
+                            "INSERT INTO room_members (room_id, user_id, role, joined_at, created_at) VALUES ($1, $2, $3, $4, $4)",
+                        )
+                        .bind(&room.id)
+                        .bind(room.user_id)
+                        .bind(Role::Owner.to_string())
+                        .bind(room.created_at)
+                        .execute(&db)
+                        .await?;
+
                         pikav.publish(vec![pikav_client::Event {
                             user_id: metadata.request_by,
                             topic: format!("rooms/{}", room.id),
@@ -60,6 +148,131 @@ pub fn rooms() -> Subscriber {
                             metadata: None,
                         }]);
                     }
+                    RoomEvent::Renamed => {
+                        let data: Renamed = event.to_data()?;
+
+                        let room = sqlx::query_as::<_, Room>(
+                            "UPDATE rooms SET name = $2, updated_at = $3 WHERE id = $1 RETURNING *",
+                        )
+                        .bind(&id)
+                        .bind(&data.name)
+                        .bind(event.created_at)
+                        .fetch_one(&db)
+                        .await?;
+
+                        publish_to_members(
+                            &db,
+                            &pikav,
+                            &id,
+                            "renamed",
+                            serde_json::to_value(room)?,
+                        )
+                        .await?;
+                    }
+                    RoomEvent::Archived => {
+                        let room = sqlx::query_as::<_, Room>(
+                            "UPDATE rooms SET archived_at = $2, updated_at = $2 WHERE id = $1 RETURNING *",
+                        )
+                        .bind(&id)
+                        .bind(event.created_at)
+                        .fetch_one(&db)
+                        .await?;
+
+                        publish_to_members(
+                            &db,
+                            &pikav,
+                            &id,
+                            "archived",
+                            serde_json::to_value(room)?,
+                        )
+                        .await?;
+                    }
+                    RoomEvent::Deleted => {
+                        sqlx::query::<_>(
+                            "UPDATE rooms SET deleted_at = $2, updated_at = $2 WHERE id = $1",
+                        )
+                        .bind(&id)
+                        .bind(event.created_at)
+                        .execute(&db)
+                        .await?;
+
+                        publish_to_members(
+                            &db,
+                            &pikav,
+                            &id,
+                            "deleted",
+                            serde_json::json!({ "id": id }),
+                        )
+                        .await?;
+                    }
+                    RoomEvent::MemberInvited => {
+                        let data: MemberInvited = event.to_data()?;
+
+                        let member = sqlx::query_as::<_, RoomMember>(
+                            r#"
+                            INSERT INTO room_members (room_id, user_id, role, created_at) VALUES ($1, $2, $3, $4)
+                            ON CONFLICT (room_id, user_id) DO UPDATE SET role = EXCLUDED.role
+                            RETURNING *
+                            "#,
+                        )
+                        .bind(&id)
+                        .bind(Uuid::parse_str(&data.user_id)?)
+                        .bind(data.role.to_string())
+                        .bind(event.created_at)
+                        .fetch_one(&db)
+                        .await?;
+
+                        publish_to_members(
+                            &db,
+                            &pikav,
+                            &id,
+                            "member-invited",
+                            serde_json::to_value(member)?,
+                        )
+                        .await?;
+                    }
+                    RoomEvent::MemberJoined => {
+                        let data: MemberJoined = event.to_data()?;
+
+                        let member = sqlx::query_as::<_, RoomMember>(
+                            "UPDATE room_members SET joined_at = $3 WHERE room_id = $1 AND user_id = $2 RETURNING *",
+                        )
+                        .bind(&id)
+                        .bind(Uuid::parse_str(&data.user_id)?)
+                        .bind(event.created_at)
+                        .fetch_one(&db)
+                        .await?;
+
+                        publish_to_members(
+                            &db,
+                            &pikav,
+                            &id,
+                            "member-joined",
+                            serde_json::to_value(member)?,
+                        )
+                        .await?;
+                    }
+                    RoomEvent::MemberRemoved => {
+                        let data: MemberRemoved = event.to_data()?;
+
+                        // Notify before deleting so the removed member is told too
+                        publish_to_members(
+                            &db,
+                            &pikav,
+                            &id,
+                            "member-removed",
+                            serde_json::json!({ "user_id": data.user_id }),
+                        )
+                        .await?;
+
+                        sqlx::query::<_>(
+                            "DELETE FROM room_members WHERE room_id = $1 AND user_id = $2",
+                        )
+                        .bind(&id)
+                        .bind(Uuid::parse_str(&data.user_id)?)
+                        .execute(&db)
+                        .await?;
+                    }
                 };
 
                 Ok(())
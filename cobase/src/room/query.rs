@@ -1,32 +1,147 @@
 use actix::{ActorFutureExt, Context, Handler, Message, ResponseActFuture, WrapFuture};
-use evento::CommandError;
+use evento::{
+    query::{Query as QueryAs, QueryArgs, QueryResult},
+    CommandError,
+};
 use serde::Deserialize;
 use uuid::Uuid;
 
-use crate::query::Query;
+use crate::query::{Query, SortOrder};
 
-use super::projection::Room;
+use super::projection::{Room, RoomMember};
 
 #[derive(Message, Deserialize)]
 #[serde(rename_all = "camelCase")]
-#[rtype(result = "Result<Vec<Room>, CommandError>")]
+#[rtype(result = "Result<QueryResult<Room>, CommandError>")]
 pub struct ListRoomsQuery {
     pub user_id: Uuid,
+    pub query_args: QueryArgs,
+    /// Case insensitive search on the room name
+    pub name: Option<String>,
+    /// Sort by creation date
+    pub order: SortOrder,
 }
 
 impl Handler<ListRoomsQuery> for Query {
-    type Result = ResponseActFuture<Self, Result<Vec<Room>, CommandError>>;
+    type Result = ResponseActFuture<Self, Result<QueryResult<Room>, CommandError>>;
 
     fn handle(&mut self, msg: ListRoomsQuery, _ctx: &mut Context<Self>) -> Self::Result {
         let pool = self.pool.clone();
 
         async move {
-            let rooms = sqlx::query_as::<_, Room>("SELECT * FROM rooms WHERE user_id = $1")
-                .bind(msg.user_id)
+            let name_filter = match msg.name {
+                Some(_) => "AND name ILIKE $2",
+                None => "",
+            };
+
+            // Filtering is done in a subquery so that the cursor conditions apply on rooms only
+            let sql = format!(
+                r#"
+                SELECT * FROM (
+                    SELECT * FROM rooms
+                    WHERE id IN (
+                        SELECT room_id FROM room_members WHERE user_id = $1 AND joined_at IS NOT NULL
+                    )
+                    AND deleted_at IS NULL {name_filter}
+                ) AS rooms
+                "#
+            );
+
+            let mut query = QueryAs::<Room>::new(&sql).bind(msg.user_id);
+
+            if let Some(name) = msg.name {
+                let name = name.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+                query = query.bind(format!("%{name}%"));
+            }
+
+            let res = query
+                .build(msg.order.args(msg.query_args))
                 .fetch_all(&pool)
                 .await?;
 
-            Ok(rooms)
+            Ok(msg.order.reverse(res))
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+#[derive(Message, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[rtype(result = "Result<Room, CommandError>")]
+pub struct GetRoomQuery {
+    pub user_id: Uuid,
+    pub id: String,
+}
+
+impl Handler<GetRoomQuery> for Query {
+    type Result = ResponseActFuture<Self, Result<Room, CommandError>>;
+
+    fn handle(&mut self, msg: GetRoomQuery, _ctx: &mut Context<Self>) -> Self::Result {
+        let pool = self.pool.clone();
+
+        async move {
+            let room = sqlx::query_as::<_, Room>(
+                r#"
+                SELECT rooms.* FROM rooms
+                JOIN room_members ON room_members.room_id = rooms.id
+                WHERE rooms.id = $1
+                    AND room_members.user_id = $2
+                    AND room_members.joined_at IS NOT NULL
+                    AND rooms.deleted_at IS NULL
+                "#,
+            )
+            .bind(&msg.id)
+            .bind(msg.user_id)
+            .fetch_optional(&pool)
+            .await?;
+
+            room.ok_or_else(|| CommandError::NotFound(format!("room {} not found", msg.id)))
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+#[derive(Message, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[rtype(result = "Result<Vec<RoomMember>, CommandError>")]
+pub struct ListRoomMembersQuery {
+    pub user_id: Uuid,
+    pub room_id: String,
+}
+
+impl Handler<ListRoomMembersQuery> for Query {
+    type Result = ResponseActFuture<Self, Result<Vec<RoomMember>, CommandError>>;
+
+    fn handle(&mut self, msg: ListRoomMembersQuery, _ctx: &mut Context<Self>) -> Self::Result {
+        let pool = self.pool.clone();
+
+        async move {
+            let members = sqlx::query_as::<_, RoomMember>(
+                r#"
+                SELECT room_members.* FROM room_members
+                JOIN rooms ON rooms.id = room_members.room_id
+                WHERE room_members.room_id = $1 AND rooms.deleted_at IS NULL
+                ORDER BY room_members.created_at
+                "#,
+            )
+            .bind(&msg.room_id)
+            .fetch_all(&pool)
+            .await?;
+
+            let is_member = members
+                .iter()
+                .any(|member| member.user_id == msg.user_id && member.joined_at.is_some());
+
+            if !is_member {
+                return Err(CommandError::NotFound(format!(
+                    "room {} not found",
+                    msg.room_id
+                )));
+            }
+
+            Ok(members)
         }
         .into_actor(self)
         .boxed_local()
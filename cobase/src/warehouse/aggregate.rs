@@ -1,11 +1,19 @@
 use evento::Aggregate;
 use serde::{Deserialize, Serialize};
 
-use super::event::{DataImported, WarehouseEvent};
+use super::{
+    event::{Created, DataImported, ExportEvent, ExportRequested, SchemaDefined, WarehouseEvent},
+    export::ExportFormat,
+    schema::Schema,
+};
 
 #[derive(Default, Serialize, Deserialize, PartialEq, Debug)]
 pub struct Warehouse {
+    pub room_id: String,
+    pub name: String,
+    pub description: Option<String>,
     pub storage_paths: Vec<String>,
+    pub schema: Option<Schema>,
 }
 
 impl Aggregate for Warehouse {
@@ -13,10 +21,21 @@ impl Aggregate for Warehouse {
         let warehouse_event: WarehouseEvent = event.name.parse().unwrap();
 
         match warehouse_event {
+            WarehouseEvent::Created => {
+                let data: Created = event.to_data().unwrap();
+                self.room_id = data.room_id;
+                self.name = data.name;
+                self.description = data.description;
+            }
             WarehouseEvent::DataImported => {
                 let data: DataImported = event.to_data().unwrap();
                 self.storage_paths.push(data.storage_path);
             }
+            WarehouseEvent::SchemaDefined => {
+                let data: SchemaDefined = event.to_data().unwrap();
+                self.schema = Some(data.schema).filter(|schema| !schema.fields.is_empty());
+            }
+            WarehouseEvent::DataDeleted | WarehouseEvent::DataPatched => {}
         }
     }
 
@@ -24,3 +43,31 @@ impl Aggregate for Warehouse {
         "warehouse"
     }
 }
+
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug)]
+pub struct Export {
+    pub room_id: String,
+    pub warehouse_id: String,
+    pub format: ExportFormat,
+    pub filter: Option<String>,
+}
+
+impl Aggregate for Export {
+    fn apply(&mut self, event: &evento::Event) {
+        let export_event: ExportEvent = event.name.parse().unwrap();
+
+        match export_event {
+            ExportEvent::Requested => {
+                let data: ExportRequested = event.to_data().unwrap();
+                self.room_id = data.room_id;
+                self.warehouse_id = data.warehouse_id;
+                self.format = data.format;
+                self.filter = data.filter;
+            }
+        }
+    }
+
+    fn aggregate_type<'a>() -> &'a str {
+        "warehouse-export"
+    }
+}
@@ -1,23 +1,245 @@
 use std::collections::HashMap;
 
 use actix::{ActorFutureExt, Context, Handler, ResponseActFuture, WrapFuture};
-use evento::{CommandError, CommandResult, Event};
+use evento::{CommandError, CommandResult, Event, PgEvento, PgProducer};
+use nanoid::nanoid;
+use opendal::Operator;
 use serde::Deserialize;
 use serde_json::Value;
 use uuid::Uuid;
+use validator::Validate;
 
-use crate::command::{Command, CommandInput, CommandMetadata};
+use crate::{
+    command::{Command, CommandInput, CommandMetadata},
+    room::{load_room, Role},
+};
 
 use super::{
-    aggregate::Warehouse,
-    event::{DataImported, WarehouseEvent},
-    service::{get_import_data_path, write_import_data},
+    aggregate::{Export, Warehouse},
+    csv_import::{convert_csv, CsvOptions},
+    event::{
+        Created, DataDeleted, DataImported, DataPatched, ExportEvent, ExportRequested,
+        SchemaDefined, WarehouseEvent,
+    },
+    export::ExportFormat,
+    filter::Filter,
+    query::DryRunImportDataQuery,
+    schema::Schema,
+    service::{
+        check_import_path, get_import_data_path, write_import_data, ImportDataReader, ImportFormat,
+        ImportMode,
+    },
 };
 
+/// Maximum length of the `_id` of a row, the key column of the data tables
+const MAX_ID_LEN: usize = 50;
+
+/// Checks that every row has an `_id` field that is a string or a number of at most
+/// `MAX_ID_LEN` characters, `offset` is added to the reported index when rows are sent
+/// in batches.
+pub fn check_import_data(
+    data: &[HashMap<String, Value>],
+    offset: usize,
+) -> Result<(), CommandError> {
+    for (pos, row) in data.iter().enumerate() {
+        let len = match row.get("_id") {
+            Some(Value::Number(id)) => id.to_string().chars().count(),
+            Some(Value::String(id)) => id.chars().count(),
+            _ => {
+                return Err(CommandError::BadRequest(format!(
+                    "Missing field _id or not (string | number) at index {}",
+                    offset + pos
+                )))
+            }
+        };
+
+        if len > MAX_ID_LEN {
+            return Err(CommandError::BadRequest(format!(
+                "Field _id is longer than {MAX_ID_LEN} characters at index {}",
+                offset + pos
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Validates the rows of an import data file batch by batch, against the schema of
+/// the warehouse if any
+async fn check_import_data_file(
+    storage: &Operator,
+    storage_path: &str,
+    format: ImportFormat,
+    schema: Option<&Schema>,
+) -> Result<(), CommandError> {
+    let mut reader = ImportDataReader::new(storage, storage_path, format, 1000).await?;
+    let mut offset = 0;
+
+    while let Some(data) = reader.next().await? {
+        check_import_data(&data, offset)?;
+
+        if let Some(schema) = schema {
+            schema.check_import_data(&data, offset)?;
+        }
+
+        offset += data.len();
+    }
+
+    if offset == 0 {
+        return Err(CommandError::BadRequest(
+            "Import data must contain at least one row".to_owned(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Loads a room that is not archived and checks that `user_id` can edit it
+async fn check_room(evento: &PgEvento, user_id: &str, room_id: &str) -> Result<(), CommandError> {
+    let (room, _) = load_room(evento, room_id).await?;
+    room.check_role(room_id, user_id, Role::Editor)?;
+
+    if room.archived {
+        return Err(CommandError::BadRequest(format!(
+            "room {room_id} is archived"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Returns a warehouse of the room that `user_id` can edit with its version
+async fn load_warehouse(
+    evento: &PgEvento,
+    user_id: &str,
+    room_id: &str,
+    id: &str,
+) -> Result<(Warehouse, i32), CommandError> {
+    check_room(evento, user_id, room_id).await?;
+
+    match evento.load::<Warehouse, _>(id).await? {
+        Some((warehouse, event)) if warehouse.room_id == room_id => Ok((warehouse, event.version)),
+        _ => Err(CommandError::NotFound(format!("warehouse {id} not found"))),
+    }
+}
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateWarehouseCommand {
+    pub room_id: String,
+    #[validate(length(min = 1, max = 50))]
+    pub name: String,
+    #[validate(length(max = 255))]
+    pub description: Option<String>,
+}
+
+impl Handler<CommandInput<CreateWarehouseCommand>> for Command {
+    type Result = ResponseActFuture<Self, CommandResult>;
+
+    fn handle(
+        &mut self,
+        msg: CommandInput<CreateWarehouseCommand>,
+        _ctx: &mut Context<Self>,
+    ) -> Self::Result {
+        let evento = self.evento.clone();
+        let producer = self.producer.clone();
+
+        async move {
+            msg.input
+                .validate()
+                .map_err(|e| CommandError::BadRequest(e.to_string()))?;
+
+            check_room(&evento, &msg.user_id, &msg.input.room_id).await?;
+
+            // The id is used in the warehouse data table name
+            let id = nanoid!().replace('-', "").replace('_', "");
+            let request_id = Uuid::new_v4();
+
+            producer
+                .publish::<Warehouse, _>(
+                    &id,
+                    vec![Event::new(WarehouseEvent::Created)
+                        .data(Created {
+                            room_id: msg.input.room_id,
+                            name: msg.input.name,
+                            description: msg.input.description,
+                        })?
+                        .metadata(CommandMetadata {
+                            request_by: msg.user_id,
+                            request_id: request_id.to_string(),
+                        })?],
+                    0,
+                )
+                .await?;
+
+            Ok(id)
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+/// Returns the request id that identifies the import, see `GetWarehouseImportQuery`
+async fn publish_data_imported(
+    producer: &PgProducer,
+    storage: &Operator,
+    user_id: &str,
+    id: String,
+    version: i32,
+    data: DataImported,
+) -> CommandResult {
+    let request_id = Uuid::new_v4();
+    let storage_path = data.storage_path.to_owned();
+
+    let res = producer
+        .publish::<Warehouse, _>(
+            &id,
+            vec![Event::new(WarehouseEvent::DataImported)
+                .data(data)?
+                .metadata(CommandMetadata {
+                    request_by: user_id.to_owned(),
+                    request_id: request_id.to_string(),
+                })?],
+            version,
+        )
+        .await;
+
+    if res.is_err() {
+        storage
+            .remove(vec![storage_path])
+            .await
+            .map_err(|e| CommandError::InternalServerErr(e.to_string()))?;
+    }
+
+    res?;
+
+    Ok(request_id.to_string())
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ImportDataCommand {
+    pub room_id: String,
+    pub warehouse_id: String,
     pub data: Vec<HashMap<String, Value>>,
+    #[serde(default)]
+    pub mode: ImportMode,
+    /// Dry runs read the warehouse data and are answered by `DryRunImportDataQuery`,
+    /// see `ImportDataCommand::into_dry_run`. The command never imports them.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+impl ImportDataCommand {
+    pub fn into_dry_run(self, user_id: Uuid) -> DryRunImportDataQuery {
+        DryRunImportDataQuery {
+            user_id,
+            room_id: self.room_id,
+            warehouse_id: self.warehouse_id,
+            data: self.data,
+            mode: self.mode,
+        }
+    }
 }
 
 impl Handler<CommandInput<ImportDataCommand>> for Command {
@@ -33,25 +255,33 @@ impl Handler<CommandInput<ImportDataCommand>> for Command {
         let storage = self.storage.clone();
 
         async move {
-            let error_pos = msg.input.data.iter().position(|o| match o.get("_id") {
-                Some(id) => !matches!(id, Value::Number(_) | Value::String(_)),
-                _ => true,
-            });
+            if msg.input.dry_run {
+                return Err(CommandError::BadRequest(
+                    "Dry runs are answered by DryRunImportDataQuery".to_owned(),
+                ));
+            }
 
-            if let Some(pos) = error_pos {
-                return Err(CommandError::BadRequest(format!(
-                    "Missing field _id or not (string | number) at index {pos}"
-                )));
+            if msg.input.data.is_empty() {
+                return Err(CommandError::BadRequest(
+                    "Import data must contain at least one row".to_owned(),
+                ));
             }
 
-            let request_id = Uuid::new_v4();
-            let version = evento
-                .load::<Warehouse, _>(&msg.user_id)
-                .await?
-                .map(|(_, e)| e.version)
-                .unwrap_or(0);
+            check_import_data(&msg.input.data, 0)?;
+
+            let (warehouse, version) = load_warehouse(
+                &evento,
+                &msg.user_id,
+                &msg.input.room_id,
+                &msg.input.warehouse_id,
+            )
+            .await?;
 
-            let storage_path = get_import_data_path();
+            if let Some(schema) = &warehouse.schema {
+                schema.check_import_data(&msg.input.data, 0)?;
+            }
+
+            let storage_path = get_import_data_path(&msg.user_id);
             let import_data_exists = storage
                 .is_exist(&storage_path)
                 .await
@@ -67,31 +297,475 @@ impl Handler<CommandInput<ImportDataCommand>> for Command {
                 .await
                 .map_err(|e| CommandError::InternalServerErr(e.to_string()))?;
 
-            let res = producer
+            publish_data_imported(
+                &producer,
+                &storage,
+                &msg.user_id,
+                msg.input.warehouse_id,
+                version,
+                DataImported {
+                    storage_path,
+                    format: ImportFormat::Json,
+                    mode: msg.input.mode,
+                },
+            )
+            .await
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+/// Checks that the user can import data to the warehouse, it is sent before writing an
+/// uploaded file to the storage and returns the warehouse id
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizeImportCommand {
+    pub room_id: String,
+    pub warehouse_id: String,
+}
+
+impl Handler<CommandInput<AuthorizeImportCommand>> for Command {
+    type Result = ResponseActFuture<Self, CommandResult>;
+
+    fn handle(
+        &mut self,
+        msg: CommandInput<AuthorizeImportCommand>,
+        _ctx: &mut Context<Self>,
+    ) -> Self::Result {
+        let evento = self.evento.clone();
+
+        async move {
+            load_warehouse(
+                &evento,
+                &msg.user_id,
+                &msg.input.room_id,
+                &msg.input.warehouse_id,
+            )
+            .await?;
+
+            Ok(msg.input.warehouse_id)
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+/// Imports a data file already written to the storage by the user, see
+/// `ImportDataWriter` and `get_import_file_path`. The file is removed when it is
+/// rejected.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportDataFileCommand {
+    pub room_id: String,
+    pub warehouse_id: String,
+    pub storage_path: String,
+    #[serde(default)]
+    pub format: ImportFormat,
+    #[serde(default)]
+    pub mode: ImportMode,
+}
+
+impl Handler<CommandInput<ImportDataFileCommand>> for Command {
+    type Result = ResponseActFuture<Self, CommandResult>;
+
+    fn handle(
+        &mut self,
+        msg: CommandInput<ImportDataFileCommand>,
+        _ctx: &mut Context<Self>,
+    ) -> Self::Result {
+        let evento = self.evento.clone();
+        let producer = self.producer.clone();
+        let storage = self.storage.clone();
+
+        async move {
+            let storage_path = msg.input.storage_path;
+
+            check_import_path(&msg.user_id, &storage_path)?;
+
+            let (warehouse, version) = load_warehouse(
+                &evento,
+                &msg.user_id,
+                &msg.input.room_id,
+                &msg.input.warehouse_id,
+            )
+            .await?;
+
+            let import_data_exists = storage
+                .is_exist(&storage_path)
+                .await
+                .map_err(|e| CommandError::InternalServerErr(e.to_string()))?;
+
+            if !import_data_exists {
+                return Err(CommandError::NotFound(format!(
+                    "Import data {storage_path} not found"
+                )));
+            }
+
+            if let Err(e) = check_import_data_file(
+                &storage,
+                &storage_path,
+                msg.input.format,
+                warehouse.schema.as_ref(),
+            )
+            .await
+            {
+                storage
+                    .remove(vec![storage_path])
+                    .await
+                    .map_err(|e| CommandError::InternalServerErr(e.to_string()))?;
+
+                return Err(e);
+            }
+
+            publish_data_imported(
+                &producer,
+                &storage,
+                &msg.user_id,
+                msg.input.warehouse_id,
+                version,
+                DataImported {
+                    storage_path,
+                    format: msg.input.format,
+                    mode: msg.input.mode,
+                },
+            )
+            .await
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+/// Imports a CSV file already written to the storage by the user, it is converted to
+/// an import data file and removed.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportCsvCommand {
+    pub room_id: String,
+    pub warehouse_id: String,
+    pub storage_path: String,
+    #[serde(default)]
+    pub options: CsvOptions,
+    #[serde(default)]
+    pub mode: ImportMode,
+}
+
+impl Handler<CommandInput<ImportCsvCommand>> for Command {
+    type Result = ResponseActFuture<Self, CommandResult>;
+
+    fn handle(
+        &mut self,
+        msg: CommandInput<ImportCsvCommand>,
+        _ctx: &mut Context<Self>,
+    ) -> Self::Result {
+        let evento = self.evento.clone();
+        let producer = self.producer.clone();
+        let storage = self.storage.clone();
+
+        async move {
+            let csv_path = msg.input.storage_path;
+
+            check_import_path(&msg.user_id, &csv_path)?;
+
+            let (warehouse, version) = load_warehouse(
+                &evento,
+                &msg.user_id,
+                &msg.input.room_id,
+                &msg.input.warehouse_id,
+            )
+            .await?;
+
+            let csv_exists = storage
+                .is_exist(&csv_path)
+                .await
+                .map_err(|e| CommandError::InternalServerErr(e.to_string()))?;
+
+            if !csv_exists {
+                return Err(CommandError::NotFound(format!(
+                    "Import data {csv_path} not found"
+                )));
+            }
+
+            // The CSV is not needed anymore once converted or rejected
+            let res = convert_csv(
+                &storage,
+                &msg.user_id,
+                &csv_path,
+                &msg.input.options,
+                warehouse.schema.as_ref(),
+            )
+            .await;
+
+            storage
+                .remove(vec![csv_path])
+                .await
+                .map_err(|e| CommandError::InternalServerErr(e.to_string()))?;
+
+            let storage_path = res?;
+
+            publish_data_imported(
+                &producer,
+                &storage,
+                &msg.user_id,
+                msg.input.warehouse_id,
+                version,
+                DataImported {
+                    storage_path,
+                    format: ImportFormat::Ndjson,
+                    mode: msg.input.mode,
+                },
+            )
+            .await
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+/// Deletes a row by key, deleting a key that does not exist succeeds and leaves the
+/// data unchanged so that retries of the command are idempotent
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteDataCommand {
+    pub room_id: String,
+    pub warehouse_id: String,
+    pub key: String,
+}
+
+impl Handler<CommandInput<DeleteDataCommand>> for Command {
+    type Result = ResponseActFuture<Self, CommandResult>;
+
+    fn handle(
+        &mut self,
+        msg: CommandInput<DeleteDataCommand>,
+        _ctx: &mut Context<Self>,
+    ) -> Self::Result {
+        let evento = self.evento.clone();
+        let producer = self.producer.clone();
+
+        async move {
+            let (_, version) = load_warehouse(
+                &evento,
+                &msg.user_id,
+                &msg.input.room_id,
+                &msg.input.warehouse_id,
+            )
+            .await?;
+
+            let request_id = Uuid::new_v4();
+
+            producer
+                .publish::<Warehouse, _>(
+                    &msg.input.warehouse_id,
+                    vec![Event::new(WarehouseEvent::DataDeleted)
+                        .data(DataDeleted { key: msg.input.key })?
+                        .metadata(CommandMetadata {
+                            request_by: msg.user_id,
+                            request_id: request_id.to_string(),
+                        })?],
+                    version,
+                )
+                .await?;
+
+            Ok(msg.input.warehouse_id)
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+/// Applies a JSON merge patch to a row by key, patching a key that does not exist
+/// succeeds and does not create the row, use an import to insert rows
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PatchDataCommand {
+    pub room_id: String,
+    pub warehouse_id: String,
+    pub key: String,
+    pub data: HashMap<String, Value>,
+}
+
+impl Handler<CommandInput<PatchDataCommand>> for Command {
+    type Result = ResponseActFuture<Self, CommandResult>;
+
+    fn handle(
+        &mut self,
+        msg: CommandInput<PatchDataCommand>,
+        _ctx: &mut Context<Self>,
+    ) -> Self::Result {
+        let evento = self.evento.clone();
+        let producer = self.producer.clone();
+
+        async move {
+            let key = match msg.input.data.get("_id") {
+                Some(Value::Number(v)) => Some(v.to_string()),
+                Some(Value::String(v)) => Some(v.to_owned()),
+                Some(_) => None,
+                None => Some(msg.input.key.to_owned()),
+            };
+
+            if key.as_ref() != Some(&msg.input.key) {
+                return Err(CommandError::BadRequest(
+                    "Field _id cannot be patched".to_owned(),
+                ));
+            }
+
+            let (warehouse, version) = load_warehouse(
+                &evento,
+                &msg.user_id,
+                &msg.input.room_id,
+                &msg.input.warehouse_id,
+            )
+            .await?;
+
+            if let Some(schema) = &warehouse.schema {
+                schema.check_patch(&msg.input.data)?;
+            }
+
+            let request_id = Uuid::new_v4();
+
+            producer
                 .publish::<Warehouse, _>(
-                    &msg.user_id,
-                    vec![Event::new(WarehouseEvent::DataImported)
-                        .data(DataImported {
-                            storage_path: storage_path.to_owned(),
+                    &msg.input.warehouse_id,
+                    vec![Event::new(WarehouseEvent::DataPatched)
+                        .data(DataPatched {
+                            key: msg.input.key,
+                            data: msg.input.data,
                         })?
                         .metadata(CommandMetadata {
-                            request_by: msg.user_id.to_owned(),
+                            request_by: msg.user_id,
                             request_id: request_id.to_string(),
                         })?],
                     version,
                 )
-                .await;
+                .await?;
 
-            if res.is_err() {
-                storage
-                    .remove(vec![storage_path])
-                    .await
-                    .map_err(|e| CommandError::InternalServerErr(e.to_string()))?;
+            Ok(msg.input.warehouse_id)
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DefineSchemaCommand {
+    pub room_id: String,
+    pub warehouse_id: String,
+    pub schema: Schema,
+}
+
+impl Handler<CommandInput<DefineSchemaCommand>> for Command {
+    type Result = ResponseActFuture<Self, CommandResult>;
+
+    fn handle(
+        &mut self,
+        msg: CommandInput<DefineSchemaCommand>,
+        _ctx: &mut Context<Self>,
+    ) -> Self::Result {
+        let evento = self.evento.clone();
+        let producer = self.producer.clone();
+
+        async move {
+            msg.input.schema.check()?;
+
+            let (_, version) = load_warehouse(
+                &evento,
+                &msg.user_id,
+                &msg.input.room_id,
+                &msg.input.warehouse_id,
+            )
+            .await?;
+
+            let request_id = Uuid::new_v4();
+
+            producer
+                .publish::<Warehouse, _>(
+                    &msg.input.warehouse_id,
+                    vec![Event::new(WarehouseEvent::SchemaDefined)
+                        .data(SchemaDefined {
+                            schema: msg.input.schema,
+                        })?
+                        .metadata(CommandMetadata {
+                            request_by: msg.user_id,
+                            request_id: request_id.to_string(),
+                        })?],
+                    version,
+                )
+                .await?;
+
+            Ok(msg.input.warehouse_id)
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+/// Starts an export of the warehouse rows, the export is written to the storage by
+/// the `warehouse_exports` projection. Room viewers can export.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportDataCommand {
+    pub room_id: String,
+    pub warehouse_id: String,
+    #[serde(default)]
+    pub format: ExportFormat,
+    /// Filter expression on the row data, see `Filter`
+    pub filter: Option<String>,
+}
+
+impl Handler<CommandInput<ExportDataCommand>> for Command {
+    type Result = ResponseActFuture<Self, CommandResult>;
+
+    fn handle(
+        &mut self,
+        msg: CommandInput<ExportDataCommand>,
+        _ctx: &mut Context<Self>,
+    ) -> Self::Result {
+        let evento = self.evento.clone();
+        let producer = self.producer.clone();
+
+        async move {
+            if let Some(filter) = &msg.input.filter {
+                Filter::parse(filter)?;
             }
 
-            res?;
+            let (room, _) = load_room(&evento, &msg.input.room_id).await?;
+            room.check_role(&msg.input.room_id, &msg.user_id, Role::Viewer)?;
+
+            match evento.load::<Warehouse, _>(&msg.input.warehouse_id).await? {
+                Some((warehouse, _)) if warehouse.room_id == msg.input.room_id => {}
+                _ => {
+                    return Err(CommandError::NotFound(format!(
+                        "warehouse {} not found",
+                        msg.input.warehouse_id
+                    )))
+                }
+            };
+
+            let id = nanoid!();
+            let request_id = Uuid::new_v4();
+
+            producer
+                .publish::<Export, _>(
+                    &id,
+                    vec![Event::new(ExportEvent::Requested)
+                        .data(ExportRequested {
+                            room_id: msg.input.room_id,
+                            warehouse_id: msg.input.warehouse_id,
+                            format: msg.input.format,
+                            filter: msg.input.filter,
+                        })?
+                        .metadata(CommandMetadata {
+                            request_by: msg.user_id,
+                            request_id: request_id.to_string(),
+                        })?],
+                    0,
+                )
+                .await?;
 
-            Ok(msg.user_id)
+            Ok(id)
         }
         .into_actor(self)
         .boxed_local()
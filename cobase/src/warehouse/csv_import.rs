@@ -0,0 +1,314 @@
+use std::collections::{HashMap, HashSet};
+
+use evento::CommandError;
+use futures::StreamExt;
+use opendal::{Operator, Reader};
+use serde::Deserialize;
+use serde_json::{Map, Number, Value};
+
+use super::{
+    command::check_import_data,
+    schema::{FieldType, Schema},
+    service::ImportDataWriter,
+};
+
+/// Number of rows converted before they are validated and written to the import data
+const BATCH_SIZE: usize = 1000;
+
+/// How a CSV file is converted to rows, columns with a dot separated name are
+/// converted to nested objects.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    /// The first record holds the column names, otherwise columns are named
+    /// `column_1`, `column_2`...
+    pub header: bool,
+    /// Column used as the `_id` of the rows
+    pub id_column: String,
+    /// Converts values to the type of the schema field, or to a number, boolean or
+    /// `null` when the field is not declared. Values are kept as strings otherwise.
+    pub coerce: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            header: true,
+            id_column: "_id".to_owned(),
+            coerce: true,
+        }
+    }
+}
+
+/// Converts the CSV file at `path` to a new `Ndjson` import data file and returns its path,
+/// the file is read and converted batch by batch and written under `user_id`
+pub async fn convert_csv(
+    op: &Operator,
+    user_id: &str,
+    path: &str,
+    options: &CsvOptions,
+    schema: Option<&Schema>,
+) -> Result<String, CommandError> {
+    let delimiter = match options.delimiter.is_ascii() {
+        true => options.delimiter as u8,
+        false => {
+            return Err(CommandError::BadRequest(format!(
+                "Invalid CSV delimiter {}",
+                options.delimiter
+            )))
+        }
+    };
+
+    let reader = op.reader(path).await.map_err(internal_err)?;
+    let mut writer = ImportDataWriter::new(op, user_id)
+        .await
+        .map_err(internal_err)?;
+    let res = write_rows(reader, delimiter, options, schema, &mut writer).await;
+    let storage_path = writer.close().await.map_err(internal_err)?;
+
+    if let Err(e) = res {
+        op.remove(vec![storage_path]).await.map_err(internal_err)?;
+
+        return Err(e);
+    }
+
+    Ok(storage_path)
+}
+
+async fn write_rows(
+    reader: Reader,
+    delimiter: u8,
+    options: &CsvOptions,
+    schema: Option<&Schema>,
+    writer: &mut ImportDataWriter,
+) -> Result<(), CommandError> {
+    let mut reader = csv_async::AsyncReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(options.header)
+        .create_reader(reader);
+
+    // Without header, the first record gives the number of columns
+    let headers = reader.headers().await.map_err(csv_err)?;
+    let columns = match options.header {
+        true => Columns::new(headers.iter(), options, schema)?,
+        false => Columns::new(
+            (1..=headers.len()).map(|i| format!("column_{i}")),
+            options,
+            schema,
+        )?,
+    };
+
+    let mut batch = Vec::new();
+    let mut records = reader.records();
+
+    while let Some(record) = records.next().await {
+        let record = record.map_err(csv_err)?;
+        batch.push(columns.row(record.iter(), options.coerce)?);
+
+        if batch.len() == BATCH_SIZE {
+            write_batch(writer, &batch, schema).await?;
+            batch.clear();
+        }
+    }
+
+    if !batch.is_empty() {
+        write_batch(writer, &batch, schema).await?;
+    }
+
+    if writer.is_empty() {
+        return Err(CommandError::BadRequest(
+            "CSV must contain at least one row".to_owned(),
+        ));
+    }
+
+    Ok(())
+}
+
+async fn write_batch(
+    writer: &mut ImportDataWriter,
+    batch: &[HashMap<String, Value>],
+    schema: Option<&Schema>,
+) -> Result<(), CommandError> {
+    check_import_data(batch, writer.len())?;
+
+    if let Some(schema) = schema {
+        schema.check_import_data(batch, writer.len())?;
+    }
+
+    writer.append(batch).await.map_err(internal_err)
+}
+
+struct Column {
+    path: Vec<String>,
+    field_type: Option<FieldType>,
+}
+
+struct Columns {
+    columns: Vec<Column>,
+    id: usize,
+    id_type: Option<FieldType>,
+}
+
+impl Columns {
+    fn new<S: AsRef<str>>(
+        names: impl IntoIterator<Item = S>,
+        options: &CsvOptions,
+        schema: Option<&Schema>,
+    ) -> Result<Self, CommandError> {
+        let field_type = |name: &str| {
+            schema.and_then(|schema| {
+                schema
+                    .fields
+                    .iter()
+                    .find(|field| field.name == name)
+                    .map(|field| field.field_type)
+            })
+        };
+
+        let mut names_seen = HashSet::new();
+        let mut columns = Vec::new();
+
+        for name in names {
+            let name = name.as_ref().trim();
+            let path = name.split('.').map(|v| v.to_owned()).collect::<Vec<_>>();
+
+            if path.iter().any(|v| v.is_empty()) {
+                return Err(CommandError::BadRequest(format!(
+                    "Invalid CSV column \"{name}\""
+                )));
+            }
+
+            if !names_seen.insert(name.to_owned()) {
+                return Err(CommandError::BadRequest(format!(
+                    "CSV column {name} is declared twice"
+                )));
+            }
+
+            columns.push(Column {
+                field_type: field_type(name),
+                path,
+            });
+        }
+
+        let id = columns
+            .iter()
+            .position(|column| column.path.join(".") == options.id_column)
+            .ok_or_else(|| {
+                CommandError::BadRequest(format!("CSV column {} not found", options.id_column))
+            })?;
+
+        Ok(Self {
+            columns,
+            id,
+            id_type: field_type("_id"),
+        })
+    }
+
+    fn row<'a>(
+        &self,
+        values: impl Iterator<Item = &'a str>,
+        coerce: bool,
+    ) -> Result<HashMap<String, Value>, CommandError> {
+        let mut row = Map::new();
+        let mut id = Value::Null;
+
+        for (i, (column, value)) in self.columns.iter().zip(values).enumerate() {
+            if i == self.id {
+                id = convert(value, self.id_type, coerce);
+            }
+
+            insert_path(
+                &mut row,
+                &column.path,
+                convert(value, column.field_type, coerce),
+            )
+            .map_err(|path| {
+                CommandError::BadRequest(format!(
+                    "CSV column {} conflicts with column {path}",
+                    column.path.join(".")
+                ))
+            })?;
+        }
+
+        let mut row = row.into_iter().collect::<HashMap<_, _>>();
+        row.insert("_id".to_owned(), id);
+
+        Ok(row)
+    }
+}
+
+/// Inserts a value at a dot separated path, the error is the path of the value
+/// that is not an object.
+fn insert_path(row: &mut Map<String, Value>, path: &[String], value: Value) -> Result<(), String> {
+    let (name, parents) = path.split_last().expect("path is not empty");
+    let mut object = row;
+
+    for (i, parent) in parents.iter().enumerate() {
+        object = match object
+            .entry(parent.to_owned())
+            .or_insert_with(|| Value::Object(Map::new()))
+        {
+            Value::Object(object) => object,
+            _ => return Err(path[..=i].join(".")),
+        };
+    }
+
+    if matches!(object.get(name), Some(Value::Object(_))) {
+        return Err(path.join("."));
+    }
+
+    object.insert(name.to_owned(), value);
+
+    Ok(())
+}
+
+/// Converts a CSV value, values that don't match `field_type` are kept as strings
+/// so that the schema validation reports them.
+fn convert(value: &str, field_type: Option<FieldType>, coerce: bool) -> Value {
+    if !coerce {
+        return Value::String(value.to_owned());
+    }
+
+    if value.is_empty() {
+        return Value::Null;
+    }
+
+    let coerced = match field_type {
+        Some(FieldType::String | FieldType::Date) => None,
+        Some(FieldType::Number | FieldType::Integer) => parse_number(value),
+        Some(FieldType::Boolean) => parse_bool(value),
+        Some(FieldType::Object | FieldType::Array) => serde_json::from_str(value).ok(),
+        None => parse_number(value).or_else(|| parse_bool(value)),
+    };
+
+    coerced.unwrap_or_else(|| Value::String(value.to_owned()))
+}
+
+/// Parses a JSON number, values like `007` or `+1` are not numbers
+fn parse_number(value: &str) -> Option<Value> {
+    if value.trim() != value {
+        return None;
+    }
+
+    serde_json::from_str::<Number>(value)
+        .ok()
+        .map(Value::Number)
+}
+
+fn parse_bool(value: &str) -> Option<Value> {
+    match value.to_lowercase().as_str() {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ => None,
+    }
+}
+
+fn csv_err(e: csv_async::Error) -> CommandError {
+    CommandError::BadRequest(format!("Invalid CSV: {e}"))
+}
+
+fn internal_err(e: opendal::Error) -> CommandError {
+    CommandError::InternalServerErr(e.to_string())
+}
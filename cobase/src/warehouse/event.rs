@@ -1,10 +1,23 @@
+use std::collections::HashMap;
+
 use parse_display::{Display, FromStr};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+    export::ExportFormat,
+    schema::Schema,
+    service::{ImportFormat, ImportMode},
+};
 
 #[derive(Display, FromStr)]
 #[display(style = "kebab-case")]
 pub enum WarehouseEvent {
+    Created,
     DataImported,
+    DataDeleted,
+    DataPatched,
+    SchemaDefined,
 }
 
 impl From<WarehouseEvent> for String {
@@ -13,7 +26,57 @@ impl From<WarehouseEvent> for String {
     }
 }
 
+#[derive(Display, FromStr)]
+#[display(style = "kebab-case")]
+pub enum ExportEvent {
+    Requested,
+}
+
+impl From<ExportEvent> for String {
+    fn from(o: ExportEvent) -> Self {
+        o.to_string()
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Created {
+    pub room_id: String,
+    pub name: String,
+    pub description: Option<String>,
+}
+
 #[derive(Default, Serialize, Deserialize)]
 pub struct DataImported {
     pub storage_path: String,
+    #[serde(default)]
+    pub format: ImportFormat,
+    #[serde(default)]
+    pub mode: ImportMode,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct DataDeleted {
+    pub key: String,
+}
+
+/// `data` is a JSON merge patch (RFC 7396) applied to the row
+#[derive(Default, Serialize, Deserialize)]
+pub struct DataPatched {
+    pub key: String,
+    pub data: HashMap<String, Value>,
+}
+
+/// Replaces the schema of the warehouse, no field means no schema
+#[derive(Default, Serialize, Deserialize)]
+pub struct SchemaDefined {
+    pub schema: Schema,
+}
+
+/// Rows of the warehouse matching `filter` are exported, see `Filter`
+#[derive(Default, Serialize, Deserialize)]
+pub struct ExportRequested {
+    pub room_id: String,
+    pub warehouse_id: String,
+    pub format: ExportFormat,
+    pub filter: Option<String>,
 }
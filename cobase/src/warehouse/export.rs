@@ -0,0 +1,366 @@
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use arrow_array::{
+    builder::{BooleanBuilder, Float64Builder, Int64Builder, StringBuilder},
+    ArrayRef, RecordBatch,
+};
+use arrow_schema::{DataType, Field, Schema as ArrowSchema};
+use futures::TryStreamExt;
+use opendal::{Operator, Writer};
+use parquet::arrow::ArrowWriter;
+use parse_display::{Display, FromStr};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{PgConnection, PgPool};
+
+use super::filter::{Filter, FilterArg};
+
+/// Number of rows written at once, it is also the size of the Parquet record batches
+const BATCH_SIZE: usize = 1000;
+
+#[derive(Default, Serialize, Deserialize, Display, FromStr, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[display(style = "lowercase")]
+pub enum ExportFormat {
+    /// One column per top-level field, objects and arrays are JSON encoded
+    #[default]
+    Csv,
+    /// Newline delimited JSON, one row per line
+    Ndjson,
+    /// Apache Parquet, one column per top-level field
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+}
+
+pub fn get_export_path(id: &str, format: ExportFormat) -> String {
+    format!("exports/{id}.{format}")
+}
+
+/// Top-level field of the exported rows
+struct Column {
+    name: String,
+    data_type: DataType,
+}
+
+/// Writes the rows of `warehouse_data_{warehouse_id}` matching `filter` to the storage
+/// at `path` in the order they were imported, returns the number of rows. The columns
+/// and the rows are read from the same snapshot so that every row matches its columns.
+pub async fn export_data(
+    db: &PgPool,
+    op: &Operator,
+    warehouse_id: &str,
+    filter: Option<&str>,
+    format: ExportFormat,
+    path: &str,
+) -> Result<i64, String> {
+    let (filter, args) = match filter {
+        Some(filter) => Filter::parse(filter)
+            .and_then(|filter| filter.to_sql("data", 1))
+            .map_err(|e| e.to_string())?,
+        None => ("true".to_owned(), Vec::new()),
+    };
+
+    let mut tx = db.begin().await.map_err(|e| e.to_string())?;
+
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let columns = export_columns(&mut tx, warehouse_id, &filter, &args)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut writer = ExportWriter::new(op, path, format, columns).await?;
+
+    let sql = format!(
+        "SELECT data FROM warehouse_data_{warehouse_id} WHERE {filter} ORDER BY created_at, key"
+    );
+
+    let mut query = sqlx::query_as::<_, (Value,)>(&sql);
+
+    for arg in args {
+        query = query.bind(arg);
+    }
+
+    let mut rows = query.fetch(&mut *tx);
+    let mut batch = Vec::new();
+    let mut len = 0;
+
+    while let Some((data,)) = rows.try_next().await.map_err(|e| e.to_string())? {
+        batch.push(data);
+
+        if batch.len() == BATCH_SIZE {
+            writer.write(&batch).await?;
+            len += batch.len();
+            batch.clear();
+        }
+    }
+
+    if !batch.is_empty() {
+        writer.write(&batch).await?;
+        len += batch.len();
+    }
+
+    drop(rows);
+    tx.commit().await.map_err(|e| e.to_string())?;
+    writer.close().await?;
+
+    Ok(len as i64)
+}
+
+/// Returns the top-level fields of the matching rows, `_id` first then ordered by name.
+/// Fields that only have numbers or booleans keep their type, others are strings.
+/// Integers of more than 18 digits may not fit an `Int64` and are exported as `Float64`.
+async fn export_columns(
+    conn: &mut PgConnection,
+    warehouse_id: &str,
+    filter: &str,
+    args: &[FilterArg],
+) -> Result<Vec<Column>, sqlx::Error> {
+    let sql = format!(
+        r#"
+        SELECT field.name,
+            array_agg(DISTINCT jsonb_typeof(field.value)) FILTER (WHERE jsonb_typeof(field.value) <> 'null'),
+            bool_and(jsonb_typeof(field.value) <> 'number' OR field.value::TEXT ~ '^-?[0-9]{1,18}$')
+        FROM warehouse_data_{warehouse_id}, jsonb_each(data) AS field(name, value)
+        WHERE {filter}
+        GROUP BY field.name
+        ORDER BY field.name <> '_id', field.name
+        "#
+    );
+
+    let mut query = sqlx::query_as::<_, (String, Option<Vec<String>>, Option<bool>)>(&sql);
+
+    for arg in args.iter().cloned() {
+        query = query.bind(arg);
+    }
+
+    let columns = query
+        .fetch_all(conn)
+        .await?
+        .into_iter()
+        .map(|(name, types, is_integer)| {
+            let data_type = match types.unwrap_or_default()[..] {
+                [ref v] if v == "number" && is_integer == Some(true) => DataType::Int64,
+                [ref v] if v == "number" => DataType::Float64,
+                [ref v] if v == "boolean" => DataType::Boolean,
+                _ => DataType::Utf8,
+            };
+
+            Column { name, data_type }
+        })
+        .collect();
+
+    Ok(columns)
+}
+
+/// Text of a value in a string column, `None` for `null`
+fn value_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(v) => Some(v.to_owned()),
+        _ => Some(value.to_string()),
+    }
+}
+
+/// Buffer that `ArrowWriter` writes to, drained to the storage after each batch
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().expect("buffer lock is not poisoned"))
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .lock()
+            .expect("buffer lock is not poisoned")
+            .extend_from_slice(buf);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Encoder {
+    Csv,
+    Ndjson,
+    Parquet(ArrowWriter<SharedBuffer>, SharedBuffer, Arc<ArrowSchema>),
+}
+
+struct ExportWriter {
+    writer: Writer,
+    columns: Vec<Column>,
+    encoder: Encoder,
+}
+
+impl ExportWriter {
+    async fn new(
+        op: &Operator,
+        path: &str,
+        format: ExportFormat,
+        columns: Vec<Column>,
+    ) -> Result<Self, String> {
+        let mut writer = op.writer(path).await.map_err(|e| e.to_string())?;
+
+        let encoder = match format {
+            ExportFormat::Csv if columns.is_empty() => Encoder::Csv,
+            ExportFormat::Csv => {
+                let mut header = csv::Writer::from_writer(Vec::new());
+                header
+                    .write_record(columns.iter().map(|column| column.name.as_str()))
+                    .map_err(|e| e.to_string())?;
+
+                writer
+                    .append(header.into_inner().map_err(|e| e.to_string())?)
+                    .await
+                    .map_err(|e| e.to_string())?;
+
+                Encoder::Csv
+            }
+            ExportFormat::Ndjson => Encoder::Ndjson,
+            ExportFormat::Parquet => {
+                let schema = Arc::new(ArrowSchema::new(
+                    columns
+                        .iter()
+                        .map(|column| Field::new(&column.name, column.data_type.clone(), true))
+                        .collect::<Vec<_>>(),
+                ));
+
+                let buffer = SharedBuffer::default();
+                let arrow_writer = ArrowWriter::try_new(buffer.clone(), schema.clone(), None)
+                    .map_err(|e| e.to_string())?;
+
+                Encoder::Parquet(arrow_writer, buffer, schema)
+            }
+        };
+
+        Ok(Self {
+            writer,
+            columns,
+            encoder,
+        })
+    }
+
+    async fn write(&mut self, rows: &[Value]) -> Result<(), String> {
+        let content = match &mut self.encoder {
+            Encoder::Csv => {
+                let mut csv_writer = csv::Writer::from_writer(Vec::new());
+
+                for row in rows {
+                    csv_writer
+                        .write_record(self.columns.iter().map(|column| {
+                            row.get(&column.name)
+                                .and_then(value_to_string)
+                                .unwrap_or_default()
+                        }))
+                        .map_err(|e| e.to_string())?;
+                }
+
+                csv_writer.into_inner().map_err(|e| e.to_string())?
+            }
+            Encoder::Ndjson => {
+                let mut content = Vec::new();
+
+                for row in rows {
+                    serde_json::to_writer(&mut content, row).map_err(|e| e.to_string())?;
+                    content.push(b'\n');
+                }
+
+                content
+            }
+            Encoder::Parquet(arrow_writer, buffer, schema) => {
+                let arrays = self
+                    .columns
+                    .iter()
+                    .map(|column| to_array(column, rows))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let batch =
+                    RecordBatch::try_new(schema.clone(), arrays).map_err(|e| e.to_string())?;
+
+                arrow_writer.write(&batch).map_err(|e| e.to_string())?;
+
+                buffer.take()
+            }
+        };
+
+        if content.is_empty() {
+            return Ok(());
+        }
+
+        self.writer.append(content).await.map_err(|e| e.to_string())
+    }
+
+    async fn close(mut self) -> Result<(), String> {
+        if let Encoder::Parquet(arrow_writer, buffer, _) = self.encoder {
+            arrow_writer.close().map_err(|e| e.to_string())?;
+
+            self.writer
+                .append(buffer.take())
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        self.writer.close().await.map_err(|e| e.to_string())
+    }
+}
+
+/// Column of a Parquet record batch, values that don't match the type are `null`.
+/// Integer columns fail instead, their values are checked by `export_columns`.
+fn to_array(column: &Column, rows: &[Value]) -> Result<ArrayRef, String> {
+    let values = rows.iter().map(|row| row.get(&column.name));
+
+    let array: ArrayRef = match column.data_type {
+        DataType::Int64 => {
+            let mut builder = Int64Builder::with_capacity(rows.len());
+
+            for value in values {
+                match value {
+                    None | Some(Value::Null) => builder.append_null(),
+                    Some(value) => builder.append_value(value.as_i64().ok_or_else(|| {
+                        format!("{value} of field {} is not a 64-bit integer", column.name)
+                    })?),
+                }
+            }
+
+            Arc::new(builder.finish())
+        }
+        DataType::Float64 => {
+            let mut builder = Float64Builder::with_capacity(rows.len());
+            values.for_each(|v| builder.append_option(v.and_then(|v| v.as_f64())));
+            Arc::new(builder.finish())
+        }
+        DataType::Boolean => {
+            let mut builder = BooleanBuilder::with_capacity(rows.len());
+            values.for_each(|v| builder.append_option(v.and_then(|v| v.as_bool())));
+            Arc::new(builder.finish())
+        }
+        _ => {
+            let mut builder = StringBuilder::new();
+            values.for_each(|v| builder.append_option(v.and_then(value_to_string)));
+            Arc::new(builder.finish())
+        }
+    };
+
+    Ok(array)
+}
@@ -0,0 +1,653 @@
+use chrono::{DateTime, Utc};
+use evento::CommandError;
+use serde_json::{Number, Value};
+use sqlx::{
+    encode::IsNull,
+    postgres::{PgArgumentBuffer, PgTypeInfo},
+    Encode, Postgres, Type,
+};
+
+/// Filter expression on the JSON data of warehouse rows, for example
+/// `status = "active" AND (price > 10 OR tags contains "sale") AND address.city exists`.
+///
+/// Operators are `=`, `!=`, `>`, `>=`, `<`, `<=`, `in (..)`, `contains` and `exists`,
+/// combined with `and`, `or`, `not` and parentheses. Paths are dot separated field names.
+/// Chains of `and` and `or` are flat lists of operands, only parentheses and `not`
+/// nest filters.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+    Compare(Vec<String>, Operator, Value),
+    In(Vec<String>, Vec<Value>),
+    Contains(Vec<String>, Value),
+    Exists(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// Maximum nesting of parentheses and `not` in a filter, deeper filters are rejected
+/// before they exhaust the stack of the parser
+const MAX_DEPTH: usize = 32;
+
+/// Value bound to a placeholder of the sql generated by `Filter::to_sql`, `Sort::fetch`
+/// or `SearchQuery::to_sql`
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterArg {
+    Path(Vec<String>),
+    Json(Value),
+    Number(f64),
+    Text(String),
+    Date(DateTime<Utc>),
+    Bool(bool),
+    Words(Vec<String>),
+}
+
+/// Binds the inner value with its own type, the type of `FilterArg` is only a default
+/// that is never used
+impl Type<Postgres> for FilterArg {
+    fn type_info() -> PgTypeInfo {
+        <Value as Type<Postgres>>::type_info()
+    }
+}
+
+impl Encode<'_, Postgres> for FilterArg {
+    fn produces(&self) -> Option<PgTypeInfo> {
+        let type_info = match self {
+            FilterArg::Path(_) | FilterArg::Words(_) => {
+                <Vec<String> as Type<Postgres>>::type_info()
+            }
+            FilterArg::Json(_) => <Value as Type<Postgres>>::type_info(),
+            FilterArg::Number(_) => <f64 as Type<Postgres>>::type_info(),
+            FilterArg::Text(_) => <String as Type<Postgres>>::type_info(),
+            FilterArg::Date(_) => <DateTime<Utc> as Type<Postgres>>::type_info(),
+            FilterArg::Bool(_) => <bool as Type<Postgres>>::type_info(),
+        };
+
+        Some(type_info)
+    }
+
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> IsNull {
+        match self {
+            FilterArg::Path(v) | FilterArg::Words(v) => {
+                <Vec<String> as Encode<Postgres>>::encode_by_ref(v, buf)
+            }
+            FilterArg::Json(v) => <Value as Encode<Postgres>>::encode_by_ref(v, buf),
+            FilterArg::Number(v) => <f64 as Encode<Postgres>>::encode_by_ref(v, buf),
+            FilterArg::Text(v) => <String as Encode<Postgres>>::encode_by_ref(v, buf),
+            FilterArg::Date(v) => <DateTime<Utc> as Encode<Postgres>>::encode_by_ref(v, buf),
+            FilterArg::Bool(v) => <bool as Encode<Postgres>>::encode_by_ref(v, buf),
+        }
+    }
+}
+
+impl Filter {
+    pub fn parse(input: &str) -> Result<Self, CommandError> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            depth: 0,
+        };
+        let filter = parser.parse_or()?;
+
+        match parser.tokens.get(parser.pos) {
+            Some(token) => Err(filter_error(format!("unexpected {token:?}"))),
+            None => Ok(filter),
+        }
+    }
+
+    /// Returns a sql condition on the JSONB `column` with placeholders starting
+    /// at `$offset`, along with the values to bind in order.
+    pub fn to_sql(
+        &self,
+        column: &str,
+        offset: usize,
+    ) -> Result<(String, Vec<FilterArg>), CommandError> {
+        let mut builder = SqlBuilder {
+            column,
+            offset,
+            args: Vec::new(),
+        };
+
+        let sql = builder.build(self)?;
+
+        Ok((sql, builder.args))
+    }
+}
+
+fn filter_error(message: String) -> CommandError {
+    CommandError::BadRequest(format!("Invalid filter: {message}"))
+}
+
+struct SqlBuilder<'a> {
+    column: &'a str,
+    offset: usize,
+    args: Vec<FilterArg>,
+}
+
+impl SqlBuilder<'_> {
+    fn arg(&mut self, arg: FilterArg) -> String {
+        self.args.push(arg);
+
+        format!("${}", self.offset + self.args.len() - 1)
+    }
+
+    fn build(&mut self, filter: &Filter) -> Result<String, CommandError> {
+        let column = self.column;
+
+        let sql = match filter {
+            Filter::And(filters) => self.build_all(filters, " AND ")?,
+            Filter::Or(filters) => self.build_all(filters, " OR ")?,
+            Filter::Not(filter) => format!("NOT ({})", self.build(filter)?),
+            Filter::Exists(path) => {
+                let path = self.arg(FilterArg::Path(path.clone()));
+                format!("({column} #> {path}) IS NOT NULL")
+            }
+            Filter::Compare(path, Operator::Eq, value)
+                if !value.is_array() && !value.is_object() && !has_index(path) =>
+            {
+                // Containment of the whole document can use the GIN index on the column
+                let value = self.arg(FilterArg::Json(containment(path, value.clone())));
+                format!("{column} @> {value}")
+            }
+            Filter::Compare(path, op, value) => {
+                let path = self.arg(FilterArg::Path(path.clone()));
+
+                match (op, value) {
+                    (Operator::Eq, value) => {
+                        let value = self.arg(FilterArg::Json(value.clone()));
+                        format!("({column} #> {path}) = {value}")
+                    }
+                    (Operator::Ne, value) => {
+                        let value = self.arg(FilterArg::Json(value.clone()));
+                        format!("({column} #> {path}) <> {value}")
+                    }
+                    (op, Value::Number(number)) => {
+                        let number = number.as_f64().unwrap_or_default();
+                        let value = self.arg(FilterArg::Number(number));
+
+                        // Non numeric values are null and never match
+                        format!(
+                            "(CASE WHEN jsonb_typeof({column} #> {path}) = 'number' THEN ({column} #>> {path})::FLOAT8 END) {} {value}",
+                            op.to_sql()
+                        )
+                    }
+                    (op, Value::String(text)) => {
+                        let value = self.arg(FilterArg::Text(text.clone()));
+                        format!("({column} #>> {path}) {} {value}", op.to_sql())
+                    }
+                    (op, _) => {
+                        return Err(filter_error(format!(
+                            "operator {} requires a number or a string",
+                            op.to_sql()
+                        )))
+                    }
+                }
+            }
+            Filter::In(path, values) => {
+                let path = self.arg(FilterArg::Path(path.clone()));
+                let values = values
+                    .iter()
+                    .map(|value| self.arg(FilterArg::Json(value.clone())))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                format!("({column} #> {path}) IN ({values})")
+            }
+            Filter::Contains(path, value) if !value.is_string() && !has_index(path) => {
+                let value = self.arg(FilterArg::Json(containment(
+                    path,
+                    Value::Array(vec![value.clone()]),
+                )));
+                format!("{column} @> {value}")
+            }
+            Filter::Contains(path, value) => {
+                let path = self.arg(FilterArg::Path(path.clone()));
+                let array = self.arg(FilterArg::Json(Value::Array(vec![value.clone()])));
+                let string = match value.as_str() {
+                    Some(text) => {
+                        let text = self.arg(FilterArg::Text(text.to_owned()));
+                        format!("strpos({column} #>> {path}, {text}) > 0")
+                    }
+                    None => "false".to_owned(),
+                };
+
+                format!(
+                    "(CASE jsonb_typeof({column} #> {path}) WHEN 'array' THEN ({column} #> {path}) @> {array} WHEN 'string' THEN {string} ELSE false END)"
+                )
+            }
+        };
+
+        Ok(sql)
+    }
+
+    fn build_all(&mut self, filters: &[Filter], separator: &str) -> Result<String, CommandError> {
+        let sql = filters
+            .iter()
+            .map(|filter| self.build(filter))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(format!("({})", sql.join(separator)))
+    }
+}
+
+/// Returns whether a field of `path` can be an array index, containment would only
+/// match objects with that key while `#>` also looks up arrays
+fn has_index(path: &[String]) -> bool {
+    path.iter().any(|field| field.parse::<usize>().is_ok())
+}
+
+/// Nests `value` under `path`, `a.b = 1` becomes `{"a": {"b": 1}}`
+fn containment(path: &[String], value: Value) -> Value {
+    path.iter().rev().fold(value, |value, field| {
+        Value::Object([(field.to_owned(), value)].into_iter().collect())
+    })
+}
+
+impl Operator {
+    fn to_sql(self) -> &'static str {
+        match self {
+            Operator::Eq => "=",
+            Operator::Ne => "!=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Value(Value),
+    Operator(Operator),
+    And,
+    Or,
+    Not,
+    In,
+    Contains,
+    Exists,
+    OpenParen,
+    CloseParen,
+    Comma,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, CommandError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            ',' => Token::Comma,
+            '=' => Token::Operator(Operator::Eq),
+            '!' | '<' | '>' => {
+                let eq = chars.next_if_eq(&'=').is_some();
+
+                match (c, eq) {
+                    ('!', true) => Token::Operator(Operator::Ne),
+                    ('<', true) => Token::Operator(Operator::Le),
+                    ('>', true) => Token::Operator(Operator::Ge),
+                    ('<', false) if chars.next_if_eq(&'>').is_some() => {
+                        Token::Operator(Operator::Ne)
+                    }
+                    ('<', false) => Token::Operator(Operator::Lt),
+                    ('>', false) => Token::Operator(Operator::Gt),
+                    _ => return Err(filter_error("unexpected !".to_owned())),
+                }
+            }
+            '"' | '\'' => {
+                let mut text = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some('\\') => match chars.next() {
+                            Some(c) => text.push(c),
+                            None => return Err(filter_error("unterminated string".to_owned())),
+                        },
+                        Some(end) if end == c => break,
+                        Some(c) => text.push(c),
+                        None => return Err(filter_error("unterminated string".to_owned())),
+                    }
+                }
+
+                Token::Value(Value::String(text))
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut text = c.to_string();
+
+                while let Some(c) = chars
+                    .next_if(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'))
+                {
+                    text.push(c);
+                }
+
+                let number = match text.parse::<i64>() {
+                    Ok(v) => Number::from(v),
+                    _ => text
+                        .parse::<f64>()
+                        .ok()
+                        .and_then(Number::from_f64)
+                        .ok_or_else(|| filter_error(format!("invalid number {text}")))?,
+                };
+
+                Token::Value(Value::Number(number))
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut text = c.to_string();
+
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || matches!(c, '_' | '.'))
+                {
+                    text.push(c);
+                }
+
+                match text.to_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "in" => Token::In,
+                    "contains" => Token::Contains,
+                    "exists" => Token::Exists,
+                    "true" => Token::Value(Value::Bool(true)),
+                    "false" => Token::Value(Value::Bool(false)),
+                    "null" => Token::Value(Value::Null),
+                    _ => Token::Ident(text),
+                }
+            }
+            c => return Err(filter_error(format!("unexpected {c}"))),
+        };
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn next_if(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.pos) == Some(token) {
+            self.pos += 1;
+            return true;
+        }
+
+        false
+    }
+
+    fn parse_or(&mut self) -> Result<Filter, CommandError> {
+        let mut filters = vec![self.parse_and()?];
+
+        while self.next_if(&Token::Or) {
+            filters.push(self.parse_and()?);
+        }
+
+        Ok(match filters.len() {
+            1 => filters.remove(0),
+            _ => Filter::Or(filters),
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Filter, CommandError> {
+        let mut filters = vec![self.parse_unary()?];
+
+        while self.next_if(&Token::And) {
+            filters.push(self.parse_unary()?);
+        }
+
+        Ok(match filters.len() {
+            1 => filters.remove(0),
+            _ => Filter::And(filters),
+        })
+    }
+
+    fn enter(&mut self) -> Result<(), CommandError> {
+        self.depth += 1;
+
+        if self.depth > MAX_DEPTH {
+            return Err(filter_error(format!(
+                "nesting is limited to {MAX_DEPTH} levels"
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn parse_unary(&mut self) -> Result<Filter, CommandError> {
+        if self.next_if(&Token::Not) {
+            self.enter()?;
+            let filter = Filter::Not(Box::new(self.parse_unary()?));
+            self.depth -= 1;
+
+            return Ok(filter);
+        }
+
+        if self.next_if(&Token::OpenParen) {
+            self.enter()?;
+            let filter = self.parse_or()?;
+
+            if !self.next_if(&Token::CloseParen) {
+                return Err(filter_error("missing )".to_owned()));
+            }
+
+            self.depth -= 1;
+
+            return Ok(filter);
+        }
+
+        let path = match self.next() {
+            Some(Token::Ident(path)) => parse_path(&path)?,
+            token => return Err(filter_error(format!("expected a field, got {token:?}"))),
+        };
+
+        match self.next() {
+            Some(Token::Operator(op)) => Ok(Filter::Compare(path, op, self.parse_value()?)),
+            Some(Token::Contains) => Ok(Filter::Contains(path, self.parse_value()?)),
+            Some(Token::Exists) => Ok(Filter::Exists(path)),
+            Some(Token::In) => {
+                if !self.next_if(&Token::OpenParen) {
+                    return Err(filter_error("expected ( after in".to_owned()));
+                }
+
+                let mut values = vec![self.parse_value()?];
+
+                while self.next_if(&Token::Comma) {
+                    values.push(self.parse_value()?);
+                }
+
+                if !self.next_if(&Token::CloseParen) {
+                    return Err(filter_error("missing )".to_owned()));
+                }
+
+                Ok(Filter::In(path, values))
+            }
+            token => Err(filter_error(format!("expected an operator, got {token:?}"))),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, CommandError> {
+        match self.next() {
+            Some(Token::Value(value)) => Ok(value),
+            token => Err(filter_error(format!("expected a value, got {token:?}"))),
+        }
+    }
+}
+
+fn parse_path(path: &str) -> Result<Vec<String>, CommandError> {
+    let path = path.split('.').map(|v| v.to_owned()).collect::<Vec<_>>();
+
+    if path.iter().any(|v| v.is_empty()) {
+        return Err(filter_error(format!("invalid field {}", path.join("."))));
+    }
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn path(path: &str) -> Vec<String> {
+        path.split('.').map(|v| v.to_owned()).collect()
+    }
+
+    #[test]
+    fn parse_precedence() {
+        let filter =
+            Filter::parse("status = 'active' and not (price > 10 or tags contains \"sale\")")
+                .unwrap();
+
+        assert_eq!(
+            filter,
+            Filter::And(vec![
+                Filter::Compare(path("status"), Operator::Eq, json!("active")),
+                Filter::Not(Box::new(Filter::Or(vec![
+                    Filter::Compare(path("price"), Operator::Gt, json!(10)),
+                    Filter::Contains(path("tags"), json!("sale"))
+                ])))
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_operators() {
+        assert_eq!(
+            Filter::parse("a.b <> -1.5").unwrap(),
+            Filter::Compare(path("a.b"), Operator::Ne, json!(-1.5))
+        );
+        assert_eq!(
+            Filter::parse("a <= \"x\\\"y\"").unwrap(),
+            Filter::Compare(path("a"), Operator::Le, json!("x\"y"))
+        );
+        assert_eq!(
+            Filter::parse("a IN (1, 'b', null)").unwrap(),
+            Filter::In(path("a"), vec![json!(1), json!("b"), Value::Null])
+        );
+        assert_eq!(
+            Filter::parse("address.city exists").unwrap(),
+            Filter::Exists(path("address.city"))
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (input, message) in [
+            ("a =", "expected a value, got None"),
+            ("(a = 1", "missing )"),
+            ("a = 1)", "unexpected CloseParen"),
+            ("a ! 1", "unexpected !"),
+            ("a = 'b", "unterminated string"),
+            ("a..b = 1", "invalid field a..b"),
+            ("a in 1", "expected ( after in"),
+        ] {
+            assert_eq!(
+                Filter::parse(input).unwrap_err(),
+                CommandError::BadRequest(format!("Invalid filter: {message}")),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_nesting_limit() {
+        let nested = |depth: usize| {
+            format!(
+                "{}a = 1{}",
+                "not (".repeat(depth / 2),
+                ")".repeat(depth / 2)
+            )
+        };
+
+        assert!(Filter::parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            Filter::parse(&nested(MAX_DEPTH + 2)).unwrap_err(),
+            CommandError::BadRequest(format!(
+                "Invalid filter: nesting is limited to {MAX_DEPTH} levels"
+            ))
+        );
+        assert!(Filter::parse(&"(".repeat(100_000)).is_err());
+    }
+
+    #[test]
+    fn parse_long_chain() {
+        let input = vec!["a = 1"; 100_000].join(" and ");
+        let filter = Filter::parse(&input).unwrap();
+
+        assert!(matches!(&filter, Filter::And(filters) if filters.len() == 100_000));
+
+        let (sql, args) = filter.to_sql("data", 1).unwrap();
+
+        assert!(sql.starts_with("(data @> $1 AND data @> $2 AND "));
+        assert_eq!(args.len(), 100_000);
+
+        let input = vec!["a = 1 or b = 2"; 50_000].join(" and ");
+
+        assert!(Filter::parse(&input).unwrap().to_sql("data", 1).is_ok());
+    }
+
+    #[test]
+    fn to_sql_array_index() {
+        let (sql, args) = Filter::parse("items.0 = 'x' and items.1 contains 2")
+            .unwrap()
+            .to_sql("data", 1)
+            .unwrap();
+
+        assert_eq!(
+            sql,
+            "((data #> $1) = $2 AND (CASE jsonb_typeof(data #> $3) WHEN 'array' THEN (data #> $3) @> $4 WHEN 'string' THEN false ELSE false END))"
+        );
+        assert_eq!(
+            args[..2],
+            [
+                FilterArg::Path(path("items.0")),
+                FilterArg::Json(json!("x"))
+            ]
+        );
+    }
+
+    #[test]
+    fn to_sql_placeholders() {
+        let (sql, args) = Filter::parse("a = 1 and b > 2")
+            .unwrap()
+            .to_sql("data", 3)
+            .unwrap();
+
+        assert_eq!(
+            sql,
+            "(data @> $3 AND (CASE WHEN jsonb_typeof(data #> $4) = 'number' THEN (data #>> $4)::FLOAT8 END) > $5)"
+        );
+        assert_eq!(
+            args,
+            vec![
+                FilterArg::Json(json!({ "a": 1 })),
+                FilterArg::Path(path("b")),
+                FilterArg::Number(2.0)
+            ]
+        );
+    }
+}
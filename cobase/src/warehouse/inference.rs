@@ -0,0 +1,111 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use serde_json::Value;
+
+use super::schema::FieldType;
+
+/// Distinct values counted per field, fields with more values report this cardinality
+const MAX_CARDINALITY: usize = 1000;
+
+#[derive(Default)]
+struct FieldStats {
+    types: BTreeSet<String>,
+    count: usize,
+    null_count: usize,
+    values: HashSet<String>,
+}
+
+/// Field detected while scanning rows, nested objects are flattened into dot separated names
+#[derive(Debug, Clone, PartialEq)]
+pub struct InferredField {
+    pub name: String,
+    pub types: Vec<String>,
+    pub nullable: bool,
+    pub cardinality: i64,
+}
+
+/// Scans rows to detect the type, nullability and cardinality of their fields
+#[derive(Default)]
+pub struct Inference {
+    rows: usize,
+    fields: BTreeMap<String, FieldStats>,
+}
+
+impl Inference {
+    pub fn scan(&mut self, row: &HashMap<String, Value>) {
+        self.rows += 1;
+
+        for (key, value) in row {
+            self.scan_value(key.to_owned(), value);
+        }
+    }
+
+    fn scan_value(&mut self, name: String, value: &Value) {
+        if let Value::Object(object) = value {
+            for (key, value) in object {
+                self.scan_value(format!("{name}.{key}"), value);
+            }
+        }
+
+        let stats = self.fields.entry(name).or_default();
+
+        let field_type = match detect(value) {
+            Some(field_type) => field_type,
+            None => {
+                stats.null_count += 1;
+                return;
+            }
+        };
+
+        stats.count += 1;
+        stats.types.insert(field_type.to_string());
+
+        if stats.values.len() < MAX_CARDINALITY && !value.is_object() && !value.is_array() {
+            stats.values.insert(value.to_string());
+        }
+    }
+
+    pub fn fields(self) -> Vec<InferredField> {
+        let rows = self.rows;
+
+        self.fields
+            .into_iter()
+            .map(|(name, stats)| InferredField {
+                name,
+                nullable: stats.null_count > 0 || stats.count + stats.null_count < rows,
+                cardinality: stats.values.len() as i64,
+                types: stats.types.into_iter().collect(),
+            })
+            .collect()
+    }
+}
+
+/// Type of a value, `None` for `null`
+pub fn detect(value: &Value) -> Option<FieldType> {
+    let field_type = match value {
+        Value::Null => return None,
+        Value::Bool(_) => FieldType::Boolean,
+        Value::Number(v) if v.is_i64() || v.is_u64() => FieldType::Integer,
+        Value::Number(_) => FieldType::Number,
+        Value::String(_) if FieldType::Date.matches(value) => FieldType::Date,
+        Value::String(_) => FieldType::String,
+        Value::Array(_) => FieldType::Array,
+        Value::Object(_) => FieldType::Object,
+    };
+
+    Some(field_type)
+}
+
+/// Type that every value of `types` matches, `None` when they are incompatible
+pub fn common_type(types: &[String]) -> Option<String> {
+    let types = types.iter().map(|v| v.as_str()).collect::<BTreeSet<_>>();
+
+    let field_type = match types.into_iter().collect::<Vec<_>>()[..] {
+        [field_type] => field_type,
+        ["integer", "number"] => "number",
+        ["date", "string"] => "string",
+        _ => return None,
+    };
+
+    Some(field_type.to_owned())
+}
@@ -1,34 +1,104 @@
 mod aggregate;
 mod command;
+mod csv_import;
 mod event;
+mod export;
+mod filter;
+mod inference;
 mod query;
+mod schema;
+mod search;
 mod service;
+mod sort;
 
 pub mod projection;
 
 pub use command::*;
-pub use projection::{Warehouse, WarehouseData};
+pub use csv_import::CsvOptions;
+pub use export::ExportFormat;
+pub use projection::{
+    Warehouse, WarehouseData, WarehouseDataMatch, WarehouseExport, WarehouseField, WarehouseImport,
+};
 pub use query::*;
+pub use schema::{FieldType, RowError, Schema, SchemaField};
+pub use service::{
+    check_import_path, get_import_csv_path, get_import_file_path, ImportDataReader,
+    ImportDataWriter, ImportFormat, ImportMode,
+};
 
 #[cfg(test)]
 mod tests {
     use actix::Addr;
-    use evento::query::QueryArgs;
+    use evento::query::{Edge, QueryArgs};
     use evento::{CommandError, PgEvento};
     use opendal::Operator;
-    use serde_json::json;
+    use serde_json::{json, Value};
+    use sqlx::PgPool;
+    use std::collections::HashMap;
     use tokio::time::{sleep, Duration};
     use uuid::Uuid;
 
     use crate::query::Query;
     use crate::{
-        command::Command,
+        command::{Command, CommandInput, CommandMetadata},
+        room::CreateCommand,
         tests::create_context,
-        warehouse::{projection, ImportDataCommand, ListWarehouseDataQuery},
+        warehouse::{
+            projection, AuthorizeImportCommand, CreateWarehouseCommand, DefineSchemaCommand,
+            DeleteDataCommand, ExportDataCommand, ExportFormat, FieldType, GetWarehouseDataQuery,
+            GetWarehouseExportQuery, GetWarehouseImportQuery, ImportCsvCommand, ImportDataCommand,
+            ImportDataFileCommand, ImportDataReader, ImportDataWriter, ImportDryRun,
+            ListWarehouseDataQuery, ListWarehouseFieldsQuery, ListWarehousesQuery,
+            PatchDataCommand, RowError, Schema, SchemaField, SearchWarehouseDataQuery,
+        },
     };
 
     use super::aggregate::Warehouse;
-    use super::service::read_import_data;
+    use super::csv_import::CsvOptions;
+    use super::event::DataImported;
+    use super::service::{
+        get_import_csv_path, get_import_data_path, get_import_file_path, read_import_data,
+        ImportFormat, ImportMode,
+    };
+
+    async fn read_ndjson(op: &Operator, path: &str) -> Vec<HashMap<String, Value>> {
+        let mut reader = ImportDataReader::new(op, path, ImportFormat::Ndjson, 1000)
+            .await
+            .unwrap();
+        let mut rows = Vec::new();
+
+        while let Some(data) = reader.next().await.unwrap() {
+            rows.extend(data);
+        }
+
+        rows
+    }
+
+    async fn create_room(cmd: &Addr<Command>, user_id: Uuid) -> String {
+        cmd.send(CommandInput {
+            user_id: user_id.to_string(),
+            input: CreateCommand {
+                name: "Central park".to_owned(),
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap()
+    }
+
+    async fn create_warehouse(cmd: &Addr<Command>, user_id: Uuid, room_id: &str) -> String {
+        cmd.send(CommandInput {
+            user_id: user_id.to_string(),
+            input: CreateWarehouseCommand {
+                room_id: room_id.to_owned(),
+                name: "Customers".to_owned(),
+                description: None,
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap()
+    }
 
     #[actix::test]
     async fn fail_missing_id_import_data_to_warehouse() {
@@ -40,6 +110,8 @@ mod tests {
             .send(crate::command::CommandInput {
                 user_id: user_1.to_string(),
                 input: ImportDataCommand {
+                    room_id: "unknown".to_owned(),
+                    warehouse_id: "unknown".to_owned(),
                     data: vec![
                         serde_json::from_value(json!({
                             "_id": 1,
@@ -62,6 +134,8 @@ mod tests {
                         }))
                         .unwrap(),
                     ],
+                    mode: ImportMode::Upsert,
+                    dry_run: false,
                 },
             })
             .await
@@ -74,6 +148,382 @@ mod tests {
                 "Missing field _id or not (string | number) at index 1".to_owned()
             )
         );
+
+        let err = cmd
+            .send(crate::command::CommandInput {
+                user_id: user_1.to_string(),
+                input: ImportDataCommand {
+                    room_id: "unknown".to_owned(),
+                    warehouse_id: "unknown".to_owned(),
+                    data: vec![serde_json::from_value(json!({ "_id": "a".repeat(51) })).unwrap()],
+                    mode: ImportMode::Upsert,
+                    dry_run: false,
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::BadRequest(
+                "Field _id is longer than 50 characters at index 0".to_owned()
+            )
+        );
+
+        let err = cmd
+            .send(crate::command::CommandInput {
+                user_id: user_1.to_string(),
+                input: ImportDataCommand {
+                    room_id: "unknown".to_owned(),
+                    warehouse_id: "unknown".to_owned(),
+                    data: vec![],
+                    mode: ImportMode::Upsert,
+                    dry_run: false,
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::BadRequest("Import data must contain at least one row".to_owned())
+        );
+    }
+
+    #[actix::test]
+    async fn success_import_data_file_to_warehouse() {
+        let ctx = create_context("success_import_data_file_to_warehouse").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let evento = ctx.extract::<PgEvento>();
+        let op = ctx.extract::<Operator>();
+        let user_1 = Uuid::new_v4();
+
+        let data = vec![
+            serde_json::from_value(json!({
+                "_id": 1,
+                "email": "john.doe@timada.co",
+            }))
+            .unwrap(),
+            serde_json::from_value(json!({
+                "_id": 2,
+                "email": "albert.dupont@timada.co",
+            }))
+            .unwrap(),
+            serde_json::from_value(json!({
+                "_id": 3,
+                "email": "lennie.rice@timada.co",
+            }))
+            .unwrap(),
+        ];
+
+        let mut writer = ImportDataWriter::new(op, &user_1.to_string())
+            .await
+            .unwrap();
+        writer.append(&data[..2]).await.unwrap();
+        writer.append(&data[2..]).await.unwrap();
+
+        let storage_path = writer.close().await.unwrap();
+        let room_id = create_room(cmd, user_1).await;
+        let id = create_warehouse(cmd, user_1, &room_id).await;
+
+        let authorize = |user_id: Uuid| crate::command::CommandInput {
+            user_id: user_id.to_string(),
+            input: AuthorizeImportCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+            },
+        };
+
+        assert_eq!(
+            cmd.send(authorize(Uuid::new_v4()))
+                .await
+                .unwrap()
+                .unwrap_err(),
+            CommandError::NotFound(format!("room {room_id} not found"))
+        );
+        assert_eq!(cmd.send(authorize(user_1)).await.unwrap().unwrap(), id);
+
+        let import_file = |user_id: Uuid, storage_path: String| crate::command::CommandInput {
+            user_id: user_id.to_string(),
+            input: ImportDataFileCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                storage_path,
+                format: ImportFormat::Ndjson,
+                mode: ImportMode::Replace,
+            },
+        };
+
+        let user_2 = Uuid::new_v4();
+
+        assert_eq!(
+            cmd.send(import_file(user_2, storage_path.to_owned()))
+                .await
+                .unwrap()
+                .unwrap_err(),
+            CommandError::BadRequest(format!("Invalid import data path {storage_path}"))
+        );
+
+        let other_path = format!("import-data/{user_2}/../{}", &storage_path[12..]);
+
+        assert_eq!(
+            cmd.send(import_file(user_2, other_path.to_owned()))
+                .await
+                .unwrap()
+                .unwrap_err(),
+            CommandError::BadRequest(format!("Invalid import data path {other_path}"))
+        );
+
+        let user_2_path = get_import_data_path(&user_2.to_string());
+
+        assert_eq!(
+            cmd.send(import_file(user_2, user_2_path))
+                .await
+                .unwrap()
+                .unwrap_err(),
+            CommandError::NotFound(format!("room {room_id} not found"))
+        );
+        assert!(op.is_exist(&storage_path).await.unwrap());
+
+        let request_id = cmd
+            .send(crate::command::CommandInput {
+                user_id: user_1.to_string(),
+                input: ImportDataFileCommand {
+                    room_id: room_id.to_owned(),
+                    warehouse_id: id.to_owned(),
+                    storage_path: storage_path.to_owned(),
+                    format: ImportFormat::Ndjson,
+                    mode: ImportMode::Replace,
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (warehouse, _) = evento.load::<Warehouse, _>(&id).await.unwrap().unwrap();
+
+        assert_eq!(warehouse.room_id, room_id);
+        assert_eq!(warehouse.storage_paths, vec![storage_path.to_owned()]);
+        assert_eq!(read_ndjson(op, &storage_path).await, data);
+
+        sleep(Duration::from_millis(300)).await;
+
+        let import = query
+            .send(GetWarehouseImportQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                request_id,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(import.mode, "replace");
+        assert_eq!(import.state, "completed");
+    }
+
+    #[actix::test]
+    async fn success_import_ndjson_file_to_warehouse() {
+        let ctx = create_context("success_import_ndjson_file_to_warehouse").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let op = ctx.extract::<Operator>();
+        let user_1 = Uuid::new_v4();
+        let room_id = create_room(cmd, user_1).await;
+        let id = create_warehouse(cmd, user_1, &room_id).await;
+
+        let storage_path = get_import_file_path(&user_1.to_string(), ImportFormat::Ndjson);
+        op.write(
+            &storage_path,
+            "{\"_id\": 1, \"email\": \"john.doe@timada.co\"}\n\
+             \n\
+             {\"_id\": 2, \"email\": \"albert.dupont@timada.co\"}\n",
+        )
+        .await
+        .unwrap();
+
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: ImportDataFileCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                storage_path: storage_path.to_owned(),
+                format: ImportFormat::Ndjson,
+                mode: ImportMode::Upsert,
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let warehouse_data = query
+            .send(ListWarehouseDataQuery {
+                user_id: user_1.to_owned(),
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                query_args: QueryArgs::default(),
+                filter: None,
+                sort: Some("_id:number".to_owned()),
+            })
+            .await
+            .unwrap()
+            .unwrap()
+            .edges
+            .into_iter()
+            .map(|edge| edge.node.data)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            warehouse_data,
+            vec![
+                json!({"_id": 1, "email": "john.doe@timada.co"}),
+                json!({"_id": 2, "email": "albert.dupont@timada.co"}),
+            ]
+        );
+
+        let storage_path = get_import_file_path(&user_1.to_string(), ImportFormat::Ndjson);
+        op.write(&storage_path, "{\"_id\": 3}\n{\"_id\": 4,\n")
+            .await
+            .unwrap();
+
+        let err = cmd
+            .send(CommandInput {
+                user_id: user_1.to_string(),
+                input: ImportDataFileCommand {
+                    room_id: room_id.to_owned(),
+                    warehouse_id: id.to_owned(),
+                    storage_path: storage_path.to_owned(),
+                    format: ImportFormat::Ndjson,
+                    mode: ImportMode::Upsert,
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert!(
+            matches!(err, CommandError::BadRequest(e) if e.starts_with("Invalid NDJSON at line 2"))
+        );
+        assert!(!op.is_exist(&storage_path).await.unwrap());
+    }
+
+    #[actix::test]
+    async fn success_import_csv_to_warehouse() {
+        let ctx = create_context("success_import_csv_to_warehouse").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let evento = ctx.extract::<PgEvento>();
+        let op = ctx.extract::<Operator>();
+        let user_1 = Uuid::new_v4();
+        let room_id = create_room(cmd, user_1).await;
+        let id = create_warehouse(cmd, user_1, &room_id).await;
+
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: DefineSchemaCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                schema: Schema {
+                    fields: vec![SchemaField {
+                        name: "zip".to_owned(),
+                        field_type: FieldType::String,
+                        required: true,
+                        nullable: false,
+                    }],
+                    strict: false,
+                },
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        let csv_path = get_import_csv_path(&user_1.to_string());
+        op.write(
+            &csv_path,
+            "sku;name;price;active;zip;address.city\n\
+             a1;\"Doe; John\";12.5;true;01234;Paris\n\
+             a2;Albert;7;FALSE;75001;\n",
+        )
+        .await
+        .unwrap();
+
+        let options = CsvOptions {
+            delimiter: ';',
+            id_column: "sku".to_owned(),
+            ..CsvOptions::default()
+        };
+
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: ImportCsvCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                storage_path: csv_path.to_owned(),
+                options: options.clone(),
+                mode: ImportMode::Upsert,
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        let (warehouse, _) = evento.load::<Warehouse, _>(&id).await.unwrap().unwrap();
+
+        assert_eq!(warehouse.storage_paths.len(), 1);
+        assert!(!op.is_exist(&csv_path).await.unwrap());
+        assert_eq!(
+            read_ndjson(op, &warehouse.storage_paths[0]).await,
+            vec![
+                serde_json::from_value(json!({
+                    "_id": "a1",
+                    "sku": "a1",
+                    "name": "Doe; John",
+                    "price": 12.5,
+                    "active": true,
+                    "zip": "01234",
+                    "address": { "city": "Paris" }
+                }))
+                .unwrap(),
+                serde_json::from_value(json!({
+                    "_id": "a2",
+                    "sku": "a2",
+                    "name": "Albert",
+                    "price": 7,
+                    "active": false,
+                    "zip": "75001",
+                    "address": { "city": null }
+                }))
+                .unwrap(),
+            ]
+        );
+
+        let csv_path = get_import_csv_path(&user_1.to_string());
+        op.write(&csv_path, "sku;zip\na3;\n").await.unwrap();
+
+        let err = cmd
+            .send(CommandInput {
+                user_id: user_1.to_string(),
+                input: ImportCsvCommand {
+                    room_id: room_id.to_owned(),
+                    warehouse_id: id.to_owned(),
+                    storage_path: csv_path.to_owned(),
+                    options,
+                    mode: ImportMode::Upsert,
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert!(
+            matches!(err, CommandError::BadRequest(e) if e.contains("Data does not match the warehouse schema"))
+        );
+        assert!(!op.is_exist(&csv_path).await.unwrap());
     }
 
     #[actix::test]
@@ -85,6 +535,10 @@ mod tests {
         let op = ctx.extract::<Operator>();
         let user_1 = Uuid::new_v4();
         let user_2 = Uuid::new_v4();
+        let room_1 = create_room(cmd, user_1).await;
+        let room_2 = create_room(cmd, user_2).await;
+        let id_1 = create_warehouse(cmd, user_1, &room_1).await;
+        let id_2 = create_warehouse(cmd, user_2, &room_2).await;
 
         let data_0 = vec![
             serde_json::from_value(json!({
@@ -106,18 +560,38 @@ mod tests {
         cmd.send(crate::command::CommandInput {
             user_id: user_1.to_string(),
             input: ImportDataCommand {
+                room_id: room_1.to_owned(),
+                warehouse_id: id_1.to_owned(),
                 data: data_0.clone(),
+                mode: ImportMode::Upsert,
+                dry_run: false,
             },
         })
         .await
         .unwrap()
         .unwrap();
 
-        let (warehouse, _) = evento
-            .load::<Warehouse, _>(&user_1.to_string())
+        let err = cmd
+            .send(crate::command::CommandInput {
+                user_id: user_2.to_string(),
+                input: ImportDataCommand {
+                    room_id: room_1.to_owned(),
+                    warehouse_id: id_1.to_owned(),
+                    data: data_0.clone(),
+                    mode: ImportMode::Upsert,
+                    dry_run: false,
+                },
+            })
             .await
             .unwrap()
-            .unwrap();
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::NotFound(format!("room {room_1} not found"))
+        );
+
+        let (warehouse, _) = evento.load::<Warehouse, _>(&id_1).await.unwrap().unwrap();
 
         assert_eq!(
             read_import_data(op, &warehouse.storage_paths[0])
@@ -131,7 +605,11 @@ mod tests {
         let warehouse_data = query
             .send(ListWarehouseDataQuery {
                 user_id: user_1.to_owned(),
+                room_id: room_1.to_owned(),
+                warehouse_id: id_1.to_owned(),
                 query_args: QueryArgs::default(),
+                filter: None,
+                sort: None,
             })
             .await
             .unwrap()
@@ -184,18 +662,18 @@ mod tests {
         cmd.send(crate::command::CommandInput {
             user_id: user_1.to_string(),
             input: ImportDataCommand {
+                room_id: room_1.to_owned(),
+                warehouse_id: id_1.to_owned(),
                 data: data_1.clone(),
+                mode: ImportMode::Upsert,
+                dry_run: false,
             },
         })
         .await
         .unwrap()
         .unwrap();
 
-        let (warehouse, _) = evento
-            .load::<Warehouse, _>(&user_1.to_string())
-            .await
-            .unwrap()
-            .unwrap();
+        let (warehouse, _) = evento.load::<Warehouse, _>(&id_1).await.unwrap().unwrap();
 
         assert_eq!(
             read_import_data(op, &warehouse.storage_paths[0])
@@ -216,7 +694,11 @@ mod tests {
         let warehouse_data = query
             .send(ListWarehouseDataQuery {
                 user_id: user_1.to_owned(),
+                room_id: room_1.to_owned(),
+                warehouse_id: id_1.to_owned(),
                 query_args: QueryArgs::default(),
+                filter: None,
+                sort: None,
             })
             .await
             .unwrap()
@@ -274,21 +756,41 @@ mod tests {
             .unwrap(),
         ];
 
+        let err = cmd
+            .send(crate::command::CommandInput {
+                user_id: user_2.to_string(),
+                input: ImportDataCommand {
+                    room_id: room_2.to_owned(),
+                    warehouse_id: id_1.to_owned(),
+                    data: data_0.clone(),
+                    mode: ImportMode::Upsert,
+                    dry_run: false,
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::NotFound(format!("warehouse {id_1} not found"))
+        );
+
         cmd.send(crate::command::CommandInput {
             user_id: user_2.to_string(),
             input: ImportDataCommand {
+                room_id: room_2.to_owned(),
+                warehouse_id: id_2.to_owned(),
                 data: data_0.clone(),
+                mode: ImportMode::Upsert,
+                dry_run: false,
             },
         })
         .await
         .unwrap()
         .unwrap();
 
-        let (warehouse, _) = evento
-            .load::<Warehouse, _>(&user_2.to_string())
-            .await
-            .unwrap()
-            .unwrap();
+        let (warehouse, _) = evento.load::<Warehouse, _>(&id_2).await.unwrap().unwrap();
 
         assert_eq!(
             read_import_data(op, &warehouse.storage_paths[0])
@@ -302,7 +804,11 @@ mod tests {
         let warehouse_data = query
             .send(ListWarehouseDataQuery {
                 user_id: user_2.to_owned(),
+                room_id: room_2.to_owned(),
+                warehouse_id: id_2.to_owned(),
                 query_args: QueryArgs::default(),
+                filter: None,
+                sort: None,
             })
             .await
             .unwrap()
@@ -331,5 +837,1771 @@ mod tests {
                 },
             ]
         );
+
+        let warehouses = query
+            .send(ListWarehousesQuery {
+                user_id: user_2,
+                room_id: Some(room_2.to_owned()),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            warehouses.iter().map(|w| &w.id).collect::<Vec<_>>(),
+            vec![&id_2]
+        );
+
+        let err = query
+            .send(ListWarehousesQuery {
+                user_id: user_2,
+                room_id: Some(room_1.to_owned()),
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::NotFound(format!("room {room_1} not found"))
+        );
+    }
+
+    #[actix::test]
+    async fn success_create_warehouses() {
+        let ctx = create_context("success_create_warehouses").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let user_1 = Uuid::new_v4();
+        let room_1 = create_room(cmd, user_1).await;
+        let room_2 = create_room(cmd, user_1).await;
+
+        let products_id = cmd
+            .send(CommandInput {
+                user_id: user_1.to_string(),
+                input: CreateWarehouseCommand {
+                    room_id: room_1.to_owned(),
+                    name: "Products".to_owned(),
+                    description: Some("Product catalog".to_owned()),
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let customers_id = create_warehouse(cmd, user_1, &room_1).await;
+        let orders_id = create_warehouse(cmd, user_1, &room_2).await;
+
+        let err = cmd
+            .send(CommandInput {
+                user_id: Uuid::new_v4().to_string(),
+                input: CreateWarehouseCommand {
+                    room_id: room_1.to_owned(),
+                    name: "Products".to_owned(),
+                    description: None,
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::NotFound(format!("room {room_1} not found"))
+        );
+
+        let err = cmd
+            .send(CommandInput {
+                user_id: user_1.to_string(),
+                input: CreateWarehouseCommand {
+                    room_id: room_1.to_owned(),
+                    name: "P".repeat(51),
+                    description: None,
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert!(matches!(err, CommandError::BadRequest(e) if e.contains("name")));
+
+        sleep(Duration::from_millis(300)).await;
+
+        let warehouses = query
+            .send(ListWarehousesQuery {
+                user_id: user_1,
+                room_id: Some(room_1.to_owned()),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            warehouses
+                .iter()
+                .map(|w| (&w.id, w.name.as_str(), w.description.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![
+                (&products_id, "Products", Some("Product catalog")),
+                (&customers_id, "Customers", None)
+            ]
+        );
+
+        let warehouses = query
+            .send(ListWarehousesQuery {
+                user_id: user_1,
+                room_id: None,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            warehouses.iter().map(|w| &w.id).collect::<Vec<_>>(),
+            vec![&products_id, &customers_id, &orders_id]
+        );
+    }
+
+    #[actix::test]
+    async fn success_delete_and_patch_warehouse_data() {
+        let ctx = create_context("success_delete_and_patch_warehouse_data").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let user_1 = Uuid::new_v4();
+        let room_id = create_room(cmd, user_1).await;
+        let id = create_warehouse(cmd, user_1, &room_id).await;
+
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: ImportDataCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                data: vec![
+                    serde_json::from_value(json!({
+                        "_id": 1,
+                        "email": "john.doe@timada.co",
+                        "address": { "city": "Paris", "zip": "75001" }
+                    }))
+                    .unwrap(),
+                    serde_json::from_value(json!({
+                        "_id": 2,
+                        "email": "albert.dupont@timada.co",
+                    }))
+                    .unwrap(),
+                ],
+                mode: ImportMode::Upsert,
+                dry_run: false,
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        let err = cmd
+            .send(CommandInput {
+                user_id: user_1.to_string(),
+                input: PatchDataCommand {
+                    room_id: room_id.to_owned(),
+                    warehouse_id: id.to_owned(),
+                    key: "1".to_owned(),
+                    data: serde_json::from_value(json!({ "_id": 3 })).unwrap(),
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::BadRequest("Field _id cannot be patched".to_owned())
+        );
+
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: PatchDataCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                key: "1".to_owned(),
+                data: serde_json::from_value(json!({
+                    "email": null,
+                    "address": { "zip": "75002" }
+                }))
+                .unwrap(),
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: DeleteDataCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                key: "2".to_owned(),
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        // Missing keys are ignored so that deletes and patches can be retried
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: DeleteDataCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                key: "2".to_owned(),
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: PatchDataCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                key: "3".to_owned(),
+                data: serde_json::from_value(json!({ "email": "jane.doe@timada.co" })).unwrap(),
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let warehouse_data = query
+            .send(ListWarehouseDataQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                query_args: QueryArgs::default(),
+                filter: None,
+                sort: None,
+            })
+            .await
+            .unwrap()
+            .unwrap()
+            .edges
+            .into_iter()
+            .map(|edge| edge.node)
+            .collect::<Vec<_>>();
+
+        assert_eq!(warehouse_data.len(), 1);
+        assert_eq!(warehouse_data[0].key, "1");
+        assert_eq!(
+            warehouse_data[0].data,
+            json!({
+                "_id": 1,
+                "address": { "city": "Paris", "zip": "75002" }
+            })
+        );
+        assert!(warehouse_data[0].updated_at.is_some());
+
+        for key in ["1".to_owned(), warehouse_data[0].id.to_owned()] {
+            let data = query
+                .send(GetWarehouseDataQuery {
+                    user_id: user_1,
+                    room_id: room_id.to_owned(),
+                    warehouse_id: id.to_owned(),
+                    key,
+                })
+                .await
+                .unwrap()
+                .unwrap();
+
+            assert_eq!(data, warehouse_data[0]);
+        }
+
+        let err = query
+            .send(GetWarehouseDataQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                key: "2".to_owned(),
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::NotFound(format!("data 2 not found in warehouse {id}"))
+        );
+    }
+
+    #[actix::test]
+    async fn success_filter_warehouse_data() {
+        let ctx = create_context("success_filter_warehouse_data").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let user_1 = Uuid::new_v4();
+        let room_id = create_room(cmd, user_1).await;
+        let id = create_warehouse(cmd, user_1, &room_id).await;
+
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: ImportDataCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                data: vec![
+                    serde_json::from_value(json!({
+                        "_id": 1,
+                        "status": "active",
+                        "price": 5,
+                        "tags": ["sale"],
+                        "address": { "city": "Paris" }
+                    }))
+                    .unwrap(),
+                    serde_json::from_value(json!({
+                        "_id": 2,
+                        "status": "active",
+                        "price": 15.5,
+                        "name": "Red shoes"
+                    }))
+                    .unwrap(),
+                    serde_json::from_value(json!({
+                        "_id": 3,
+                        "status": "archived",
+                        "price": "unknown",
+                        "address": { "city": "Lyon" }
+                    }))
+                    .unwrap(),
+                ],
+                mode: ImportMode::Upsert,
+                dry_run: false,
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let list_keys = |filter: &str| {
+            query.send(ListWarehouseDataQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                query_args: QueryArgs::default(),
+                filter: Some(filter.to_owned()),
+                sort: None,
+            })
+        };
+
+        for (filter, keys) in [
+            (r#"status = "active" AND price > 10"#, vec!["2"]),
+            ("price <= 15.5", vec!["1", "2"]),
+            (
+                r#"status != "active" OR tags contains "sale""#,
+                vec!["1", "3"],
+            ),
+            ("_id in (1, 3)", vec!["1", "3"]),
+            (r#"tags.0 = "sale""#, vec!["1"]),
+            (r#"address.city = 'Lyon'"#, vec!["3"]),
+            ("not address exists", vec!["2"]),
+            (r#"name contains "shoes""#, vec!["2"]),
+        ] {
+            let res = list_keys(filter).await.unwrap().unwrap();
+
+            assert_eq!(
+                res.edges
+                    .iter()
+                    .map(|e| e.node.key.as_str())
+                    .collect::<Vec<_>>(),
+                keys,
+                "{filter}"
+            );
+        }
+
+        let err = list_keys("status = ").await.unwrap().unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::BadRequest("Invalid filter: expected a value, got None".to_owned())
+        );
+    }
+
+    #[actix::test]
+    async fn success_sort_warehouse_data() {
+        let ctx = create_context("success_sort_warehouse_data").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let user_1 = Uuid::new_v4();
+        let room_id = create_room(cmd, user_1).await;
+        let id = create_warehouse(cmd, user_1, &room_id).await;
+
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: ImportDataCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                data: vec![
+                    serde_json::from_value(json!({
+                        "_id": 1,
+                        "price": 10,
+                        "city": "Paris",
+                        "released_at": "2023-03-01"
+                    }))
+                    .unwrap(),
+                    serde_json::from_value(json!({
+                        "_id": 2,
+                        "price": 9.5,
+                        "city": "Lyon",
+                        "released_at": "2023-01-15T10:00:00Z"
+                    }))
+                    .unwrap(),
+                    serde_json::from_value(json!({
+                        "_id": 3,
+                        "price": 100,
+                        "city": "Paris",
+                        "released_at": "2023-02-30"
+                    }))
+                    .unwrap(),
+                    serde_json::from_value(json!({
+                        "_id": 4,
+                        "price": "unknown",
+                        "city": "Nice",
+                        "released_at": "2022-12-31"
+                    }))
+                    .unwrap(),
+                ],
+                mode: ImportMode::Upsert,
+                dry_run: false,
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let list = |sort: &str, query_args: QueryArgs| {
+            query.send(ListWarehouseDataQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                query_args,
+                filter: None,
+                sort: Some(sort.to_owned()),
+            })
+        };
+
+        for (sort, keys) in [
+            ("price:number", vec!["2", "1", "3", "4"]),
+            ("-price:number", vec!["3", "1", "2", "4"]),
+            ("city,-_id:number", vec!["2", "4", "3", "1"]),
+            ("released_at:date", vec!["4", "2", "1", "3"]),
+        ] {
+            let res = list(sort, QueryArgs::default()).await.unwrap().unwrap();
+
+            assert_eq!(
+                res.edges
+                    .iter()
+                    .map(|e| e.node.key.as_str())
+                    .collect::<Vec<_>>(),
+                keys,
+                "{sort}"
+            );
+        }
+
+        let page_1 = list(
+            "-price:number",
+            QueryArgs {
+                first: Some(2),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            page_1
+                .edges
+                .iter()
+                .map(|e| e.node.key.as_str())
+                .collect::<Vec<_>>(),
+            vec!["3", "1"]
+        );
+        assert!(page_1.page_info.has_next_page);
+
+        let page_2 = list(
+            "-price:number",
+            QueryArgs {
+                first: Some(2),
+                after: page_1.page_info.end_cursor.to_owned(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            page_2
+                .edges
+                .iter()
+                .map(|e| e.node.key.as_str())
+                .collect::<Vec<_>>(),
+            vec!["2", "4"]
+        );
+        assert!(!page_2.page_info.has_next_page);
+        assert!(page_2.page_info.has_previous_page);
+
+        let page_1_back = list(
+            "-price:number",
+            QueryArgs {
+                last: Some(2),
+                before: page_2.page_info.start_cursor.to_owned(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            page_1_back
+                .edges
+                .iter()
+                .map(|e| e.cursor.as_str())
+                .collect::<Vec<_>>(),
+            page_1
+                .edges
+                .iter()
+                .map(|e| e.cursor.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        let err = list(
+            "price:number",
+            QueryArgs {
+                after: page_1.page_info.end_cursor,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::BadRequest("Cursor does not match the sort".to_owned())
+        );
+
+        let err = list("price:boolean", QueryArgs::default())
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::BadRequest("Invalid sort: unknown type boolean".to_owned())
+        );
+    }
+
+    #[actix::test]
+    async fn success_search_warehouse_data() {
+        let ctx = create_context("success_search_warehouse_data").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let user_1 = Uuid::new_v4();
+        let room_id = create_room(cmd, user_1).await;
+        let id = create_warehouse(cmd, user_1, &room_id).await;
+
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: ImportDataCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                data: vec![
+                    serde_json::from_value(json!({
+                        "_id": 1,
+                        "name": "John Doe",
+                        "city": "Paris"
+                    }))
+                    .unwrap(),
+                    serde_json::from_value(json!({
+                        "_id": 2,
+                        "name": "Jane Doe",
+                        "city": "Lyon",
+                        "notes": "Doe family, moved from Paris"
+                    }))
+                    .unwrap(),
+                    serde_json::from_value(json!({
+                        "_id": 3,
+                        "name": "Albert Martin",
+                        "city": "Nice"
+                    }))
+                    .unwrap(),
+                ],
+                mode: ImportMode::Upsert,
+                dry_run: false,
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let search = |q: &str| {
+            query.send(SearchWarehouseDataQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                q: q.to_owned(),
+                limit: None,
+                offset: None,
+            })
+        };
+
+        let res = search("doe").await.unwrap().unwrap();
+
+        assert_eq!(
+            res.iter().map(|m| m.data.key.as_str()).collect::<Vec<_>>(),
+            vec!["2", "1"]
+        );
+        assert!(res[0].rank > res[1].rank);
+        assert!(res[1]
+            .snippet
+            .as_ref()
+            .unwrap()
+            .contains("<mark>Doe</mark>"));
+
+        let res = search("paris -jane").await.unwrap().unwrap();
+
+        assert_eq!(
+            res.iter().map(|m| m.data.key.as_str()).collect::<Vec<_>>(),
+            vec!["1"]
+        );
+
+        let res = search(r#""doe family" or "doe paris""#)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            res.iter().map(|m| m.data.key.as_str()).collect::<Vec<_>>(),
+            vec!["2"]
+        );
+
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: PatchDataCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                key: "3".to_owned(),
+                data: serde_json::from_value(json!({ "city": "Paris" })).unwrap(),
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let res = search("paris -jane").await.unwrap().unwrap();
+
+        assert_eq!(
+            res.iter().map(|m| m.data.key.as_str()).collect::<Vec<_>>(),
+            vec!["1", "3"]
+        );
+
+        let err = search(" ").await.unwrap().unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::BadRequest("q must not be empty".to_owned())
+        );
+    }
+
+    #[actix::test]
+    async fn success_migrate_data_tables() {
+        let ctx = create_context("success_migrate_data_tables").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let db = ctx.extract::<PgPool>();
+        let user_1 = Uuid::new_v4();
+        let room_id = create_room(cmd, user_1).await;
+        let id = create_warehouse(cmd, user_1, &room_id).await;
+
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: ImportDataCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                data: vec![
+                    serde_json::from_value(json!({ "_id": 1, "status": "active" })).unwrap(),
+                    serde_json::from_value(json!({ "_id": 2, "status": "archived" })).unwrap(),
+                ],
+                mode: ImportMode::Upsert,
+                dry_run: false,
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let table = format!("warehouse_data_{}", id.to_lowercase());
+
+        // Tables created before data was indexed, json is already jsonb on CockroachDB
+        sqlx::query(&format!("DROP INDEX {table}_data_idx"))
+            .execute(db)
+            .await
+            .unwrap();
+
+        // Tables created before search
+        sqlx::query(&format!("DROP INDEX {table}_search_idx"))
+            .execute(db)
+            .await
+            .unwrap();
+        sqlx::query(&format!("ALTER TABLE {table} DROP COLUMN search"))
+            .execute(db)
+            .await
+            .unwrap();
+
+        let migrated = projection::migrate_data_tables(db).await.unwrap();
+
+        assert!(migrated.contains(&table));
+        assert!(!projection::migrate_data_tables(db)
+            .await
+            .unwrap()
+            .contains(&table));
+
+        let (data_type,) = sqlx::query_as::<_, (String,)>(
+            "SELECT data_type FROM information_schema.columns WHERE table_name = $1 AND column_name = 'data'",
+        )
+        .bind(&table)
+        .fetch_one(db)
+        .await
+        .unwrap();
+
+        assert_eq!(data_type, "jsonb");
+
+        let res = query
+            .send(ListWarehouseDataQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                query_args: QueryArgs::default(),
+                filter: Some(r#"status = "active""#.to_owned()),
+                sort: None,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            res.edges
+                .iter()
+                .map(|e| e.node.key.as_str())
+                .collect::<Vec<_>>(),
+            vec!["1"]
+        );
+
+        let res = query
+            .send(SearchWarehouseDataQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                q: "archived".to_owned(),
+                limit: None,
+                offset: None,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            res.iter().map(|m| m.data.key.as_str()).collect::<Vec<_>>(),
+            vec!["2"]
+        );
+    }
+
+    #[actix::test]
+    async fn success_define_warehouse_schema() {
+        let ctx = create_context("success_define_warehouse_schema").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let evento = ctx.extract::<PgEvento>();
+        let user_1 = Uuid::new_v4();
+        let room_id = create_room(cmd, user_1).await;
+        let id = create_warehouse(cmd, user_1, &room_id).await;
+
+        let field = |name: &str, field_type: FieldType, required: bool| SchemaField {
+            name: name.to_owned(),
+            field_type,
+            required,
+            nullable: false,
+        };
+
+        let schema = Schema {
+            fields: vec![
+                field("email", FieldType::String, true),
+                field("age", FieldType::Integer, false),
+                field("address.city", FieldType::String, false),
+                field("birthday", FieldType::Date, false),
+            ],
+            strict: false,
+        };
+
+        let err = cmd
+            .send(CommandInput {
+                user_id: user_1.to_string(),
+                input: DefineSchemaCommand {
+                    room_id: room_id.to_owned(),
+                    warehouse_id: id.to_owned(),
+                    schema: Schema {
+                        fields: vec![field("_id", FieldType::Boolean, true)],
+                        strict: false,
+                    },
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::BadRequest(
+                "Schema field _id must be a string, number or integer".to_owned()
+            )
+        );
+
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: DefineSchemaCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                schema: schema.clone(),
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        let (warehouse, _) = evento.load::<Warehouse, _>(&id).await.unwrap().unwrap();
+
+        assert_eq!(warehouse.schema, Some(schema.clone()));
+
+        let import = |data: serde_json::Value| {
+            cmd.send(CommandInput {
+                user_id: user_1.to_string(),
+                input: ImportDataCommand {
+                    room_id: room_id.to_owned(),
+                    warehouse_id: id.to_owned(),
+                    data: serde_json::from_value(data).unwrap(),
+                    mode: ImportMode::Upsert,
+                    dry_run: false,
+                },
+            })
+        };
+
+        let err = import(json!([
+            { "_id": 1, "email": "john.doe@timada.co", "age": 32, "birthday": "1991-02-03" },
+            { "_id": 2, "age": 40.5 },
+            { "_id": 3, "email": "albert@timada.co", "address": { "city": 75 }, "birthday": "soon" },
+        ]))
+        .await
+        .unwrap()
+        .unwrap_err();
+
+        let payload = |err: CommandError| match err {
+            CommandError::BadRequest(payload) => {
+                serde_json::from_str::<serde_json::Value>(&payload).unwrap()
+            }
+            e => panic!("unexpected error {e:?}"),
+        };
+
+        assert_eq!(
+            payload(err),
+            json!({
+                "message": "Data does not match the warehouse schema",
+                "count": 4,
+                "errors": [
+                    { "index": 1, "path": "email", "message": "required field is missing" },
+                    { "index": 1, "path": "age", "message": "expected integer, got number" },
+                    { "index": 2, "path": "address.city", "message": "expected string, got number" },
+                    { "index": 2, "path": "birthday", "message": "expected date, got string" },
+                ]
+            })
+        );
+
+        import(json!([
+            { "_id": 1, "email": "john.doe@timada.co", "age": 32, "other": true },
+        ]))
+        .await
+        .unwrap()
+        .unwrap();
+
+        let patch = |data: serde_json::Value| {
+            cmd.send(CommandInput {
+                user_id: user_1.to_string(),
+                input: PatchDataCommand {
+                    room_id: room_id.to_owned(),
+                    warehouse_id: id.to_owned(),
+                    key: "1".to_owned(),
+                    data: serde_json::from_value(data).unwrap(),
+                },
+            })
+        };
+
+        patch(json!({ "age": 33, "birthday": null }))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let err = patch(json!({ "email": null })).await.unwrap().unwrap_err();
+
+        assert_eq!(
+            payload(err),
+            json!({
+                "message": "Data does not match the warehouse schema",
+                "count": 1,
+                "errors": [
+                    { "index": 0, "path": "email", "message": "required field cannot be removed" },
+                ]
+            })
+        );
+
+        sleep(Duration::from_millis(300)).await;
+
+        let warehouses = query
+            .send(ListWarehousesQuery {
+                user_id: user_1,
+                room_id: Some(room_id.to_owned()),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            warehouses[0].schema.as_ref().map(|schema| &schema.0),
+            Some(&schema)
+        );
+    }
+
+    #[actix::test]
+    async fn success_infer_warehouse_fields() {
+        let ctx = create_context("success_infer_warehouse_fields").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let user_1 = Uuid::new_v4();
+        let room_id = create_room(cmd, user_1).await;
+        let id = create_warehouse(cmd, user_1, &room_id).await;
+
+        let import = |mode: ImportMode, data: serde_json::Value| {
+            cmd.send(CommandInput {
+                user_id: user_1.to_string(),
+                input: ImportDataCommand {
+                    room_id: room_id.to_owned(),
+                    warehouse_id: id.to_owned(),
+                    data: serde_json::from_value(data).unwrap(),
+                    mode,
+                    dry_run: false,
+                },
+            })
+        };
+
+        import(ImportMode::Upsert, json!([
+            { "_id": 1, "price": 10, "city": "Paris", "address": { "zip": "75001" }, "since": "2023-01-02" },
+            { "_id": 2, "price": 9.5, "city": "Paris", "since": null },
+            { "_id": "3", "price": 12, "city": "Lyon" },
+        ]))
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let list_fields = || {
+            query.send(ListWarehouseFieldsQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+            })
+        };
+
+        let mut fields = list_fields().await.unwrap().unwrap();
+        fields.sort_by(|a, b| a.name.cmp(&b.name));
+
+        assert_eq!(
+            fields
+                .iter()
+                .map(|f| (
+                    f.name.as_str(),
+                    f.field_type.as_deref(),
+                    f.types.join("|"),
+                    f.nullable,
+                    f.max_cardinality
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("_id", None, "integer|string".to_owned(), false, 3),
+                ("address", Some("object"), "object".to_owned(), true, 0),
+                ("address.zip", Some("string"), "string".to_owned(), true, 1),
+                ("city", Some("string"), "string".to_owned(), false, 2),
+                (
+                    "price",
+                    Some("number"),
+                    "integer|number".to_owned(),
+                    false,
+                    3
+                ),
+                ("since", Some("date"), "date".to_owned(), true, 1),
+            ]
+        );
+
+        import(ImportMode::Upsert, json!([
+            { "_id": 4, "city": 75, "price": 1, "address": { "zip": "69001" }, "since": "2023-02-01" },
+        ]))
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let fields = list_fields().await.unwrap().unwrap();
+        let city = fields.iter().find(|f| f.name == "city").unwrap();
+
+        assert_eq!(city.field_type, None);
+        assert_eq!(city.types, vec!["integer", "string"]);
+        assert_eq!(city.max_cardinality, 2);
+
+        // A failed import is rolled back with its fields
+        import(
+            ImportMode::InsertFail,
+            json!([{ "_id": 5, "rating": 4 }, { "_id": 1, "city": true }]),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        assert_eq!(list_fields().await.unwrap().unwrap(), fields);
+    }
+
+    #[actix::test]
+    async fn success_export_warehouse_data() {
+        let ctx = create_context("success_export_warehouse_data").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let op = ctx.extract::<Operator>();
+        let user_1 = Uuid::new_v4();
+        let user_2 = Uuid::new_v4();
+        let room_id = create_room(cmd, user_1).await;
+        let id = create_warehouse(cmd, user_1, &room_id).await;
+
+        let data = vec![
+            serde_json::from_value(json!({"_id": 1, "name": "john", "price": 10.5, "tags": ["a"]}))
+                .unwrap(),
+            serde_json::from_value(json!({"_id": 2, "name": "albert", "price": 3})).unwrap(),
+            serde_json::from_value(json!({"_id": 3, "name": "lennie", "active": true})).unwrap(),
+        ];
+
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: ImportDataCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                data,
+                mode: ImportMode::Upsert,
+                dry_run: false,
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let err = cmd
+            .send(CommandInput {
+                user_id: user_2.to_string(),
+                input: ExportDataCommand {
+                    room_id: room_id.to_owned(),
+                    warehouse_id: id.to_owned(),
+                    format: ExportFormat::Csv,
+                    filter: None,
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::NotFound(format!("room {room_id} not found"))
+        );
+
+        let csv_id = cmd
+            .send(CommandInput {
+                user_id: user_1.to_string(),
+                input: ExportDataCommand {
+                    room_id: room_id.to_owned(),
+                    warehouse_id: id.to_owned(),
+                    format: ExportFormat::Csv,
+                    filter: Some("price exists".to_owned()),
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let ndjson_id = cmd
+            .send(CommandInput {
+                user_id: user_1.to_string(),
+                input: ExportDataCommand {
+                    room_id: room_id.to_owned(),
+                    warehouse_id: id.to_owned(),
+                    format: ExportFormat::Ndjson,
+                    filter: None,
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let export = query
+            .send(GetWarehouseExportQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                id: csv_id,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(export.status, "completed");
+        assert_eq!(export.row_count, Some(2));
+
+        let content = op.read(&export.storage_path.unwrap()).await.unwrap();
+
+        assert_eq!(
+            String::from_utf8(content).unwrap(),
+            "_id,name,price,tags\n1,john,10.5,\"[\"\"a\"\"]\"\n2,albert,3,\n"
+        );
+
+        let export = query
+            .send(GetWarehouseExportQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                id: ndjson_id,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(export.status, "completed");
+        assert_eq!(export.row_count, Some(3));
+
+        let content = op.read(&export.storage_path.unwrap()).await.unwrap();
+        let rows = String::from_utf8(content)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            rows,
+            vec![
+                json!({"_id": 1, "name": "john", "price": 10.5, "tags": ["a"]}),
+                json!({"_id": 2, "name": "albert", "price": 3}),
+                json!({"_id": 3, "name": "lennie", "active": true}),
+            ]
+        );
+    }
+
+    #[actix::test]
+    async fn success_track_warehouse_import() {
+        let ctx = create_context("success_track_warehouse_import").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let user_1 = Uuid::new_v4();
+        let user_2 = Uuid::new_v4();
+        let room_id = create_room(cmd, user_1).await;
+        let id = create_warehouse(cmd, user_1, &room_id).await;
+
+        let request_id_1 = cmd
+            .send(CommandInput {
+                user_id: user_1.to_string(),
+                input: ImportDataCommand {
+                    room_id: room_id.to_owned(),
+                    warehouse_id: id.to_owned(),
+                    data: vec![
+                        serde_json::from_value(json!({"_id": 1, "name": "john"})).unwrap(),
+                        serde_json::from_value(json!({"_id": 2, "name": "albert"})).unwrap(),
+                    ],
+                    mode: ImportMode::Upsert,
+                    dry_run: false,
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let request_id_2 = cmd
+            .send(CommandInput {
+                user_id: user_1.to_string(),
+                input: ImportDataCommand {
+                    room_id: room_id.to_owned(),
+                    warehouse_id: id.to_owned(),
+                    data: vec![
+                        serde_json::from_value(json!({"_id": 2, "name": "albert dupont"})).unwrap(),
+                        serde_json::from_value(json!({"_id": 3, "name": "lennie"})).unwrap(),
+                        serde_json::from_value(json!({"_id": 4, "name": "rice"})).unwrap(),
+                    ],
+                    mode: ImportMode::Upsert,
+                    dry_run: false,
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let import = query
+            .send(GetWarehouseImportQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                request_id: request_id_1,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(import.warehouse_id, id);
+        assert_eq!(import.state, "completed");
+        assert_eq!(import.row_count, 2);
+        assert_eq!(import.rows_inserted, 2);
+        assert_eq!(import.rows_updated, 0);
+
+        let import = query
+            .send(GetWarehouseImportQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                request_id: request_id_2.to_owned(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(import.state, "completed");
+        assert_eq!(import.row_count, 3);
+        assert_eq!(import.rows_inserted, 2);
+        assert_eq!(import.rows_updated, 1);
+        assert_eq!(import.error, None);
+
+        let err = query
+            .send(GetWarehouseImportQuery {
+                user_id: user_2,
+                room_id: room_id.to_owned(),
+                request_id: request_id_2,
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::NotFound(format!("room {room_id} not found"))
+        );
+    }
+
+    #[actix::test]
+    async fn success_dry_run_import_data() {
+        let ctx = create_context("success_dry_run_import_data").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let user_1 = Uuid::new_v4();
+        let room_id = create_room(cmd, user_1).await;
+        let id = create_warehouse(cmd, user_1, &room_id).await;
+
+        let row = |value: serde_json::Value| serde_json::from_value(value).unwrap();
+
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: ImportDataCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                data: vec![
+                    row(json!({"_id": 1, "name": "john", "price": 10})),
+                    row(json!({"_id": "2", "name": "albert", "price": 12.5})),
+                ],
+                mode: ImportMode::Upsert,
+                dry_run: false,
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: DefineSchemaCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                schema: Schema {
+                    fields: vec![SchemaField {
+                        name: "price".to_owned(),
+                        field_type: FieldType::Number,
+                        required: false,
+                        nullable: false,
+                    }],
+                    strict: false,
+                },
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let dry_run = |data| ImportDataCommand {
+            room_id: room_id.to_owned(),
+            warehouse_id: id.to_owned(),
+            data,
+            mode: ImportMode::Upsert,
+            dry_run: true,
+        };
+
+        let err = cmd
+            .send(CommandInput {
+                user_id: user_1.to_string(),
+                input: dry_run(vec![row(json!({"_id": 3, "name": "lennie"}))]),
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::BadRequest("Dry runs are answered by DryRunImportDataQuery".to_owned())
+        );
+
+        let report = query
+            .send(
+                dry_run(vec![
+                    row(json!({"_id": 1, "name": "john", "price": 10})),
+                    row(json!({"_id": 2, "name": "albert dupont", "price": 12.5})),
+                    row(json!({"_id": 3, "name": "lennie"})),
+                    row(json!({"name": "rice"})),
+                    row(json!({"_id": 4, "name": "nash", "price": "free"})),
+                    row(json!({"_id": 3, "name": "lennie"})),
+                ])
+                .into_dry_run(user_1),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            report,
+            ImportDryRun {
+                inserts: 1,
+                updates: 1,
+                unchanged: 2,
+                skipped: 0,
+                deletes: 0,
+                error_count: 2,
+                errors: vec![
+                    RowError {
+                        index: 3,
+                        path: "_id".to_owned(),
+                        message: "missing field or not (string | number)".to_owned(),
+                    },
+                    RowError {
+                        index: 4,
+                        path: "price".to_owned(),
+                        message: "expected number, got string".to_owned(),
+                    },
+                ],
+            }
+        );
+
+        sleep(Duration::from_millis(300)).await;
+
+        let err = query
+            .send(GetWarehouseDataQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                key: "3".to_owned(),
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            CommandError::NotFound(format!("data 3 not found in warehouse {id}"))
+        );
+
+        let data = query
+            .send(GetWarehouseDataQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                key: "2".to_owned(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            data.data,
+            json!({"_id": "2", "name": "albert", "price": 12.5})
+        );
+    }
+
+    #[actix::test]
+    async fn success_import_data_modes() {
+        let ctx = create_context("success_import_data_modes").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let user_1 = Uuid::new_v4();
+        let room_id = create_room(cmd, user_1).await;
+        let id = create_warehouse(cmd, user_1, &room_id).await;
+
+        let import = |mode: ImportMode, data: serde_json::Value| {
+            cmd.send(CommandInput {
+                user_id: user_1.to_string(),
+                input: ImportDataCommand {
+                    room_id: room_id.to_owned(),
+                    warehouse_id: id.to_owned(),
+                    data: serde_json::from_value(data).unwrap(),
+                    mode,
+                    dry_run: false,
+                },
+            })
+        };
+
+        import(
+            ImportMode::Upsert,
+            json!([
+                { "_id": 1, "name": "john", "tags": { "a": 1 } },
+                { "_id": 2, "name": "albert" },
+            ]),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        let skip_id = import(
+            ImportMode::InsertSkip,
+            json!([
+                { "_id": 1, "name": "john doe" },
+                { "_id": 3, "name": "lennie" },
+            ]),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        let fail_id = import(
+            ImportMode::InsertFail,
+            json!([
+                { "_id": 4, "name": "rice" },
+                { "_id": 2, "name": "albert dupont" },
+            ]),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        let merge_id = import(
+            ImportMode::Merge,
+            json!([
+                { "_id": 1, "name": null, "tags": { "b": 2 } },
+                { "_id": 1, "tags": { "a": null } },
+            ]),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let get_import = |request_id: String| {
+            query.send(GetWarehouseImportQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                request_id,
+            })
+        };
+
+        let get_data = |key: &str| {
+            query.send(GetWarehouseDataQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                key: key.to_owned(),
+            })
+        };
+
+        let import_skip = get_import(skip_id).await.unwrap().unwrap();
+        assert_eq!(import_skip.state, "completed");
+        assert_eq!(import_skip.mode, "insert-skip");
+        assert_eq!(import_skip.rows_inserted, 1);
+        assert_eq!(import_skip.rows_updated, 0);
+
+        let import_fail = get_import(fail_id).await.unwrap().unwrap();
+        assert_eq!(import_fail.state, "failed");
+        assert!(import_fail
+            .error
+            .unwrap()
+            .contains(&format!("data 2 already exists in warehouse {id}")));
+
+        let err = get_data("4").await.unwrap().unwrap_err();
+        assert_eq!(
+            err,
+            CommandError::NotFound(format!("data 4 not found in warehouse {id}"))
+        );
+
+        let import_merge = get_import(merge_id).await.unwrap().unwrap();
+        assert_eq!(import_merge.state, "completed");
+        assert_eq!(import_merge.row_count, 2);
+        assert_eq!(import_merge.rows_updated, 1);
+
+        let data = get_data("1").await.unwrap().unwrap();
+        assert_eq!(data.data, json!({ "_id": 1, "tags": { "b": 2 } }));
+
+        let data = get_data("3").await.unwrap().unwrap();
+        assert_eq!(data.data, json!({ "_id": 3, "name": "lennie" }));
+
+        let replace_id = import(
+            ImportMode::Replace,
+            json!([
+                { "_id": 1, "name": "john" },
+                { "_id": 5, "name": "nash" },
+            ]),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let import_replace = get_import(replace_id).await.unwrap().unwrap();
+        assert_eq!(import_replace.state, "completed");
+        assert_eq!(import_replace.rows_inserted, 1);
+        assert_eq!(import_replace.rows_updated, 1);
+        assert_eq!(import_replace.rows_deleted, 2);
+
+        let data = query
+            .send(ListWarehouseDataQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                query_args: QueryArgs::default(),
+                filter: None,
+                sort: Some("_id:number".to_owned()),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let keys = data
+            .edges
+            .into_iter()
+            .map(|edge| edge.node.key)
+            .collect::<Vec<_>>();
+
+        assert_eq!(keys, vec!["1".to_owned(), "5".to_owned()]);
+    }
+
+    #[actix::test]
+    async fn success_rollback_failed_import() {
+        let ctx = create_context("success_rollback_failed_import").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let user_1 = Uuid::new_v4();
+        let room_id = create_room(cmd, user_1).await;
+        let id = create_warehouse(cmd, user_1, &room_id).await;
+
+        let import = |mode: ImportMode, data: Vec<serde_json::Value>| {
+            cmd.send(CommandInput {
+                user_id: user_1.to_string(),
+                input: ImportDataCommand {
+                    room_id: room_id.to_owned(),
+                    warehouse_id: id.to_owned(),
+                    data: data
+                        .into_iter()
+                        .map(|row| serde_json::from_value(row).unwrap())
+                        .collect(),
+                    mode,
+                    dry_run: false,
+                },
+            })
+        };
+
+        import(
+            ImportMode::Upsert,
+            vec![json!({ "_id": 2000, "name": "john" })],
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        // The existing row is in the third chunk, after 2000 rows were written
+        let request_id = import(
+            ImportMode::InsertFail,
+            (0..2500)
+                .map(|i| json!({ "_id": i, "name": format!("row {i}") }))
+                .collect(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(1000)).await;
+
+        let import_fail = query
+            .send(GetWarehouseImportQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                request_id,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(import_fail.state, "failed");
+        assert_eq!(import_fail.row_count, 0);
+        assert_eq!(import_fail.rows_inserted, 0);
+        assert_eq!(import_fail.rows_updated, 0);
+        assert_eq!(import_fail.rows_deleted, 0);
+
+        let data = query
+            .send(ListWarehouseDataQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                query_args: QueryArgs::default(),
+                filter: None,
+                sort: None,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(data.edges.len(), 1);
+        assert_eq!(
+            data.edges[0].node.data,
+            json!({ "_id": 2000, "name": "john" })
+        );
+    }
+
+    #[actix::test]
+    async fn success_replay_completed_import() {
+        let ctx = create_context("success_replay_completed_import").await;
+        let cmd = ctx.extract::<Addr<Command>>();
+        let query = ctx.extract::<Addr<Query>>();
+        let db = ctx.extract::<PgPool>();
+        let op = ctx.extract::<Operator>();
+        let pikav = ctx.extract::<pikav_client::Client>();
+        let user_1 = Uuid::new_v4();
+        let room_id = create_room(cmd, user_1).await;
+        let id = create_warehouse(cmd, user_1, &room_id).await;
+
+        let request_id = cmd
+            .send(CommandInput {
+                user_id: user_1.to_string(),
+                input: ImportDataCommand {
+                    room_id: room_id.to_owned(),
+                    warehouse_id: id.to_owned(),
+                    data: vec![
+                        serde_json::from_value(json!({ "_id": 1, "name": "john" })).unwrap(),
+                        serde_json::from_value(json!({ "_id": 2, "name": "albert" })).unwrap(),
+                    ],
+                    mode: ImportMode::Upsert,
+                    dry_run: false,
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        // The row differs from the import file, a replay would overwrite it
+        cmd.send(CommandInput {
+            user_id: user_1.to_string(),
+            input: PatchDataCommand {
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                key: "1".to_owned(),
+                data: serde_json::from_value(json!({ "name": "john doe" })).unwrap(),
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        sleep(Duration::from_millis(300)).await;
+
+        let get_import = || {
+            query.send(GetWarehouseImportQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                request_id: request_id.to_owned(),
+            })
+        };
+
+        let list_data = || {
+            query.send(ListWarehouseDataQuery {
+                user_id: user_1,
+                room_id: room_id.to_owned(),
+                warehouse_id: id.to_owned(),
+                query_args: QueryArgs::default(),
+                filter: None,
+                sort: None,
+            })
+        };
+
+        let nodes = |edges: Vec<Edge<projection::WarehouseData>>| {
+            edges.into_iter().map(|edge| edge.node).collect::<Vec<_>>()
+        };
+
+        let import = get_import().await.unwrap().unwrap();
+        let data = nodes(list_data().await.unwrap().unwrap().edges);
+
+        assert_eq!(import.state, "completed");
+        assert_eq!(import.rows_inserted, 2);
+        assert_eq!(data[0].data, json!({ "_id": 1, "name": "john doe" }));
+
+        projection::import_data(
+            db,
+            op,
+            pikav,
+            &id,
+            &CommandMetadata {
+                request_by: user_1.to_string(),
+                request_id: request_id.to_owned(),
+            },
+            &DataImported {
+                storage_path: import.storage_path.to_owned(),
+                format: ImportFormat::Json,
+                mode: ImportMode::Upsert,
+            },
+            import.created_at,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(get_import().await.unwrap().unwrap(), import);
+        assert_eq!(nodes(list_data().await.unwrap().unwrap().edges), data);
     }
 }
@@ -1,26 +1,44 @@
+use std::collections::{HashMap, HashSet};
+
 use chrono::{DateTime, Utc};
 use evento::{
-    query::{Cursor, Query as QueryAs},
-    SubscirberHandlerError, Subscriber,
+    query::{Cursor, Edge, Query as QueryAs},
+    CommandError, SubscirberHandlerError, Subscriber,
 };
 use futures::FutureExt;
 use nanoid::nanoid;
 use opendal::Operator;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use sqlx::{FromRow, PgPool, Postgres, QueryBuilder};
+use sqlx::{types::Json, FromRow, PgPool, Postgres, QueryBuilder, Transaction};
 use uuid::Uuid;
 
 use crate::{command::CommandMetadata, warehouse::event::WarehouseEvent};
 
-use super::{event::DataImported, service::read_import_data};
+use super::{
+    aggregate,
+    event::{
+        Created, DataDeleted, DataImported, DataPatched, ExportEvent, ExportRequested,
+        SchemaDefined,
+    },
+    export::{export_data, get_export_path},
+    inference::{common_type, Inference},
+    schema::Schema,
+    search::search_words,
+    service::{ImportDataReader, ImportMode},
+};
 
 #[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, FromRow)]
 pub struct Warehouse {
     pub id: String,
+    pub room_id: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
     pub user_id: Uuid,
+    pub schema: Option<Json<Schema>>,
     pub created_at: DateTime<Utc>,
 }
+
 #[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, FromRow)]
 pub struct WarehouseData {
     pub id: String,
@@ -30,6 +48,76 @@ pub struct WarehouseData {
     pub updated_at: Option<DateTime<Utc>>,
 }
 
+/// Field detected in the rows imported to a warehouse, see `Inference`
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, FromRow)]
+pub struct WarehouseField {
+    pub warehouse_id: String,
+    /// Dot separated path of the field in the rows
+    pub name: String,
+    /// Type that every value matches, none when values have incompatible types
+    pub field_type: Option<String>,
+    pub types: Vec<String>,
+    pub nullable: bool,
+    /// Distinct values of the import or patch with the most distinct values, capped at
+    /// 1000. Values are not compared across imports, this is a lower bound of the
+    /// distinct values of the warehouse.
+    pub max_cardinality: i64,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Row matching a full-text search, ranked by the number of occurrences of the
+/// searched words
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, FromRow)]
+pub struct WarehouseDataMatch {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub data: WarehouseData,
+    pub rank: f32,
+    /// Fragments of the row values with matching words wrapped in `<mark>` tags
+    #[sqlx(default)]
+    pub snippet: Option<String>,
+}
+
+/// Import of a data file to a warehouse, `state` is `running`, `completed` or `failed`.
+/// Counts are updated after each chunk of 1000 rows.
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, FromRow)]
+pub struct WarehouseImport {
+    pub request_id: String,
+    pub warehouse_id: String,
+    pub user_id: Uuid,
+    pub storage_path: String,
+    pub format: String,
+    pub mode: String,
+    pub state: String,
+    /// Rows read from the file so far, counters are reset when the import fails
+    pub row_count: i64,
+    pub rows_inserted: i64,
+    pub rows_updated: i64,
+    pub rows_deleted: i64,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// Export of the rows of a warehouse, `status` is `running`, `completed` or `failed`
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, FromRow)]
+pub struct WarehouseExport {
+    pub id: String,
+    pub room_id: String,
+    pub warehouse_id: String,
+    pub user_id: Uuid,
+    pub format: String,
+    pub filter: Option<String>,
+    pub status: String,
+    /// Path of the exported file in the storage once completed
+    pub storage_path: Option<String>,
+    pub row_count: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
 impl Cursor for WarehouseData {
     fn keys() -> Vec<&'static str> {
         vec!["created_at", "key"]
@@ -65,6 +153,686 @@ impl Cursor for WarehouseData {
     }
 }
 
+/// Returns the users that joined the room of the warehouse
+async fn room_member_ids(db: &PgPool, warehouse_id: &str) -> Result<Vec<Uuid>, sqlx::Error> {
+    let user_ids = sqlx::query_as::<_, (Uuid,)>(
+        r#"
+        SELECT room_members.user_id FROM room_members
+        JOIN warehouses ON warehouses.room_id = room_members.room_id
+        WHERE warehouses.id = $1 AND room_members.joined_at IS NOT NULL
+        "#,
+    )
+    .bind(warehouse_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(user_ids.into_iter().map(|(user_id,)| user_id).collect())
+}
+
+/// Publishes an event on `warehouses/{id}` to every member of the warehouse room
+fn publish_to_members(
+    pikav: &pikav_client::Client,
+    user_ids: &[Uuid],
+    warehouse_id: &str,
+    name: &str,
+    data: Value,
+) {
+    pikav.publish(
+        user_ids
+            .iter()
+            .map(|user_id| pikav_client::Event {
+                user_id: user_id.to_string(),
+                topic: format!("warehouses/{warehouse_id}"),
+                name: name.to_owned(),
+                data: Some(data.clone().into()),
+                metadata: None,
+            })
+            .collect(),
+    );
+}
+
+/// Applies a JSON merge patch (RFC 7396), `null` values remove fields
+pub(super) fn merge_patch(target: &mut Value, patch: &Value) {
+    let patch = match patch {
+        Value::Object(patch) => patch,
+        _ => {
+            *target = patch.clone();
+            return;
+        }
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(target.entry(key).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+/// Converts the `data` column of the tables created before it was `jsonb` and indexes it,
+/// each table is converted in its own transaction so that it can be resumed on failure.
+/// Then adds and fills the `search` column of the tables created before search.
+///
+/// `json` is an alias of `jsonb` on CockroachDB, the tables created before only miss
+/// the index of the column and are not converted.
+pub async fn migrate_data_tables(db: &PgPool) -> Result<Vec<String>, sqlx::Error> {
+    let tables = sqlx::query_as::<_, (String, String)>(
+        r#"
+        SELECT columns.table_name, columns.data_type FROM information_schema.columns
+        JOIN warehouses ON columns.table_name = 'warehouse_data_' || lower(warehouses.id)
+        WHERE columns.column_name = 'data' AND (
+            columns.data_type = 'json' OR NOT EXISTS (
+                SELECT 1 FROM pg_indexes
+                WHERE tablename = columns.table_name
+                    AND indexname = columns.table_name || '_data_idx'
+            )
+        )
+        "#,
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut migrated = Vec::new();
+
+    for (table, data_type) in tables {
+        let mut tx = db.begin().await?;
+
+        if data_type == "json" {
+            sqlx::query::<_>(&format!(
+                "ALTER TABLE {table} ALTER COLUMN data TYPE jsonb USING data::JSONB"
+            ))
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query::<_>(&format!(
+            "CREATE INDEX IF NOT EXISTS {table}_data_idx ON {table} USING GIN (data)"
+        ))
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        migrated.push(table);
+    }
+
+    // The search index is created last so that an interrupted backfill is resumed
+    let tables = sqlx::query_as::<_, (String,)>(
+        r#"
+        SELECT 'warehouse_data_' || lower(warehouses.id) FROM warehouses
+        WHERE NOT EXISTS (
+            SELECT 1 FROM pg_indexes
+            WHERE tablename = 'warehouse_data_' || lower(warehouses.id)
+                AND indexname = 'warehouse_data_' || lower(warehouses.id) || '_search_idx'
+        )
+        "#,
+    )
+    .fetch_all(db)
+    .await?;
+
+    for (table,) in tables {
+        migrate_search_column(db, &table).await?;
+
+        if !migrated.contains(&table) {
+            migrated.push(table);
+        }
+    }
+
+    Ok(migrated)
+}
+
+/// Adds the `search` words column of the tables created before search, see `search_words`
+async fn migrate_search_column(db: &PgPool, table: &str) -> Result<(), sqlx::Error> {
+    let data_type = sqlx::query_as::<_, (String,)>(
+        "SELECT data_type FROM information_schema.columns WHERE table_name = $1 AND column_name = 'search'",
+    )
+    .bind(table)
+    .fetch_optional(db)
+    .await?;
+
+    // Schema changes are not in a transaction, CockroachDB does not allow writing to a
+    // column added by the same transaction
+    if !matches!(data_type, Some((data_type,)) if data_type == "ARRAY") {
+        sqlx::query::<_>(&format!("ALTER TABLE {table} DROP COLUMN IF EXISTS search"))
+            .execute(db)
+            .await?;
+
+        sqlx::query::<_>(&format!(
+            "ALTER TABLE {table} ADD COLUMN search TEXT[] NULL"
+        ))
+        .execute(db)
+        .await?;
+    }
+
+    loop {
+        let rows = sqlx::query_as::<_, (String, Value)>(&format!(
+            "SELECT key, data FROM {table} WHERE search IS NULL LIMIT 1000"
+        ))
+        .fetch_all(db)
+        .await?;
+
+        if rows.is_empty() {
+            break;
+        }
+
+        let mut query_builder: QueryBuilder<Postgres> =
+            QueryBuilder::new(format!("UPDATE {table} SET search = words.search FROM ("));
+
+        query_builder.push_values(&rows, |mut b, (key, data)| {
+            b.push_bind(key.to_owned()).push_bind(search_words(data));
+        });
+
+        query_builder.push(format!(
+            ") AS words (key, search) WHERE {table}.key = words.key"
+        ));
+        query_builder.build().execute(db).await?;
+    }
+
+    sqlx::query::<_>(&format!(
+        "CREATE INDEX IF NOT EXISTS {table}_search_idx ON {table} USING GIN (search)"
+    ))
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Error of an import, only invalid data fails the import, other errors are returned
+/// to the subscriber so that the event is retried
+enum ImportError {
+    /// The data can not be imported, the import is reported as failed
+    Data(String),
+    Handler(SubscirberHandlerError),
+}
+
+impl From<SubscirberHandlerError> for ImportError {
+    fn from(e: SubscirberHandlerError) -> Self {
+        Self::Handler(e)
+    }
+}
+
+/// Values too long for their column (22001) and integrity constraint violations (23xxx)
+/// come from the data and would fail again on retry
+impl From<sqlx::Error> for ImportError {
+    fn from(e: sqlx::Error) -> Self {
+        let code = e
+            .as_database_error()
+            .and_then(|e| e.code())
+            .map(|code| code.into_owned());
+
+        match code {
+            Some(code) if code == "22001" || code.starts_with("23") => Self::Data(e.to_string()),
+            _ => Self::Handler(e.into()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Handler(e.into())
+    }
+}
+
+/// The reader returns a bad request for a file that can not be parsed
+fn read_import_data_err(e: CommandError) -> ImportError {
+    match e {
+        CommandError::BadRequest(message) => ImportError::Data(message),
+        e => ImportError::Handler(SubscirberHandlerError::new(
+            "warehouse-data.read_import_data",
+            e.to_string(),
+        )),
+    }
+}
+
+/// Rows written by a committed import
+struct ImportedRows {
+    import: WarehouseImport,
+    /// Keys of the inserted or updated rows
+    keys: Vec<String>,
+    removed: Vec<Edge<WarehouseData>>,
+    /// Fields updated with the inference of the imported rows
+    fields: Vec<WarehouseField>,
+}
+
+/// Writes the rows of an import data file according to its mode in a single
+/// transaction that also marks the import as completed, so that the import is either
+/// fully applied or not at all. The progress of the import is recorded and published
+/// after each chunk outside of the transaction, otherwise it would only be visible once
+/// the import is completed. The counters of the written rows are set again by the
+/// transaction so that they only count committed rows, and reset by `import_data`
+/// when the transaction is rolled back. The fields inferred from the rows are updated
+/// by the same transaction.
+#[allow(clippy::too_many_arguments)]
+async fn import_data_file(
+    db: &PgPool,
+    op: &Operator,
+    pikav: &pikav_client::Client,
+    user_ids: &[Uuid],
+    warehouse_id: &str,
+    request_id: &str,
+    data: &DataImported,
+    created_at: DateTime<Utc>,
+) -> Result<ImportedRows, ImportError> {
+    let mut reader = ImportDataReader::new(op, &data.storage_path, data.format, 1000)
+        .await
+        .map_err(read_import_data_err)?;
+
+    let mut tx = db.begin().await?;
+    let mut inference = Inference::default();
+    let mut keys = Vec::new();
+    let mut keys_seen = HashSet::new();
+    let mut row_count = 0;
+    let mut rows_inserted = 0;
+    let mut rows_updated = 0;
+
+    while let Some(rows) = reader.next().await.map_err(read_import_data_err)? {
+        for row in rows.iter() {
+            inference.scan(row);
+        }
+
+        let (written, inserted) =
+            import_chunk(&mut tx, warehouse_id, data.mode, &rows, created_at).await?;
+
+        // Rows that are not in the import are removed at the end of a replace
+        if data.mode == ImportMode::Replace && !written.is_empty() {
+            let mut query_builder: QueryBuilder<Postgres> =
+                QueryBuilder::new("INSERT INTO warehouse_import_keys (request_id, key) ");
+
+            query_builder.push_values(&written, |mut b, key| {
+                b.push_bind(request_id).push_bind(key.to_owned());
+            });
+
+            query_builder.push(" ON CONFLICT DO NOTHING");
+            query_builder.build().execute(&mut *tx).await?;
+        }
+
+        row_count += rows.len() as i64;
+        rows_inserted += inserted;
+        rows_updated += written.len() as i64 - inserted;
+
+        let import = sqlx::query_as::<_, WarehouseImport>(
+            r#"
+            UPDATE warehouse_imports SET
+                row_count = $2, rows_inserted = $3, rows_updated = $4, updated_at = $5
+            WHERE request_id = $1 RETURNING *
+            "#,
+        )
+        .bind(request_id)
+        .bind(row_count)
+        .bind(rows_inserted)
+        .bind(rows_updated)
+        .bind(Utc::now())
+        .fetch_one(db)
+        .await?;
+
+        publish_to_members(
+            pikav,
+            user_ids,
+            warehouse_id,
+            "import-progress",
+            serde_json::to_value(&import)?,
+        );
+
+        keys.extend(
+            written
+                .into_iter()
+                .filter(|key| keys_seen.insert(key.to_owned())),
+        );
+    }
+
+    let removed = match data.mode {
+        ImportMode::Replace => remove_missing_rows(&mut tx, warehouse_id, request_id).await?,
+        _ => Vec::new(),
+    };
+
+    let fields = update_fields(&mut tx, warehouse_id, inference, true, created_at).await?;
+
+    let import = sqlx::query_as::<_, WarehouseImport>(
+        r#"
+        UPDATE warehouse_imports SET
+            state = 'completed', row_count = $2, rows_inserted = $3, rows_updated = $4,
+            rows_deleted = $5, completed_at = $6
+        WHERE request_id = $1 RETURNING *
+        "#,
+    )
+    .bind(request_id)
+    .bind(row_count)
+    .bind(rows_inserted)
+    .bind(rows_updated)
+    .bind(removed.len() as i64)
+    .bind(Utc::now())
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(ImportedRows {
+        import,
+        keys,
+        removed,
+        fields,
+    })
+}
+
+/// Publishes the rows written and removed by a committed import
+async fn publish_imported_rows(
+    db: &PgPool,
+    pikav: &pikav_client::Client,
+    user_ids: &[Uuid],
+    warehouse_id: &str,
+    rows: &ImportedRows,
+) -> Result<(), SubscirberHandlerError> {
+    for keys in rows.keys.chunks(1000) {
+        let res = QueryAs::<WarehouseData>::new(&format!(
+            "SELECT * FROM warehouse_data_{warehouse_id} WHERE key = ANY($1)"
+        ))
+        .bind(keys)
+        .forward(1000, None::<String>)
+        .fetch_all(db)
+        .await?;
+
+        publish_to_members(
+            pikav,
+            user_ids,
+            warehouse_id,
+            "updated",
+            serde_json::to_value(res.edges)?,
+        );
+    }
+
+    for edges in rows.removed.chunks(1000) {
+        publish_to_members(
+            pikav,
+            user_ids,
+            warehouse_id,
+            "removed",
+            serde_json::to_value(edges)?,
+        );
+    }
+
+    if !rows.fields.is_empty() {
+        publish_to_members(
+            pikav,
+            user_ids,
+            warehouse_id,
+            "fields-updated",
+            serde_json::to_value(&rows.fields)?,
+        );
+    }
+
+    Ok(())
+}
+
+/// Writes a chunk of rows according to the import mode, rows sharing an `_id` are
+/// written as if they were imported one after the other. Returns the keys of the
+/// written rows with the number of inserted rows.
+async fn import_chunk(
+    tx: &mut Transaction<'_, Postgres>,
+    warehouse_id: &str,
+    mode: ImportMode,
+    rows: &[HashMap<String, Value>],
+    created_at: DateTime<Utc>,
+) -> Result<(Vec<String>, i64), ImportError> {
+    let mut rows_by_key = Vec::new();
+
+    for row in rows {
+        let key = match row.get("_id") {
+            Some(Value::Number(v)) => v.to_string(),
+            Some(Value::String(v)) => v.to_owned(),
+            _ => return Err(ImportError::Data("missing field _id".to_owned())),
+        };
+
+        rows_by_key.push((key, serde_json::to_value(row)?));
+    }
+
+    let row_keys = rows_by_key
+        .iter()
+        .map(|(key, _)| key.to_owned())
+        .collect::<Vec<_>>();
+
+    // Rows that already exist, to count the inserted rows, with their data when it is
+    // merged with the imported rows
+    let data = match mode {
+        ImportMode::Merge => "data",
+        _ => "NULL::JSONB AS data",
+    };
+
+    let mut existing = sqlx::query_as::<_, (String, Option<Value>)>(&format!(
+        "SELECT key, {data} FROM warehouse_data_{warehouse_id} WHERE key = ANY($1) FOR UPDATE"
+    ))
+    .bind(&row_keys[..])
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .collect::<HashMap<_, _>>();
+
+    let mut keys = Vec::new();
+    let mut values = HashMap::new();
+
+    for (key, value) in rows_by_key {
+        if let Some(current) = values.get_mut(&key) {
+            match mode {
+                ImportMode::InsertSkip => {}
+                ImportMode::InsertFail => {
+                    return Err(ImportError::Data(format!("data {key} is imported twice")))
+                }
+                ImportMode::Merge => merge_patch(current, &value),
+                ImportMode::Upsert | ImportMode::Replace => *current = value,
+            };
+
+            continue;
+        }
+
+        let value = match existing.get_mut(&key).and_then(Option::take) {
+            Some(mut data) => {
+                merge_patch(&mut data, &value);
+                data
+            }
+            None => value,
+        };
+
+        keys.push(key.to_owned());
+        values.insert(key, value);
+    }
+
+    let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+        "INSERT INTO warehouse_data_{warehouse_id} (id, key, data, search, created_at) "
+    ));
+
+    query_builder.push_values(&keys, |mut b, key| {
+        b.push_bind(nanoid!())
+            .push_bind(key.to_owned())
+            .push_bind(values[key].clone())
+            .push_bind(search_words(&values[key]))
+            .push_bind(created_at);
+    });
+
+    query_builder.push(match mode {
+        ImportMode::InsertSkip | ImportMode::InsertFail => {
+            " ON CONFLICT (key) DO NOTHING RETURNING key"
+        }
+        _ => {
+            r#"
+            ON CONFLICT (key)
+            DO UPDATE SET data = EXCLUDED.data, search = EXCLUDED.search, updated_at = EXCLUDED.created_at
+            RETURNING key
+            "#
+        }
+    });
+
+    let written = query_builder
+        .build_query_as::<(String,)>()
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|(key,)| key)
+        .collect::<Vec<_>>();
+
+    let inserted = written
+        .iter()
+        .filter(|key| !existing.contains_key(*key))
+        .count() as i64;
+
+    if mode == ImportMode::InsertFail && written.len() < keys.len() {
+        let written = written.iter().collect::<HashSet<_>>();
+        let key = keys
+            .iter()
+            .find(|key| !written.contains(key))
+            .expect("a key is not written");
+
+        return Err(ImportError::Data(format!(
+            "data {key} already exists in warehouse {warehouse_id}"
+        )));
+    }
+
+    Ok((written, inserted))
+}
+
+/// Deletes the rows whose key was not recorded in `warehouse_import_keys` by the
+/// import, returns them so that subscribers know which edges to remove
+async fn remove_missing_rows(
+    tx: &mut Transaction<'_, Postgres>,
+    warehouse_id: &str,
+    request_id: &str,
+) -> Result<Vec<Edge<WarehouseData>>, SubscirberHandlerError> {
+    let removed = sqlx::query_as::<_, WarehouseData>(&format!(
+        r#"
+        DELETE FROM warehouse_data_{warehouse_id} WHERE NOT EXISTS (
+            SELECT 1 FROM warehouse_import_keys
+            WHERE warehouse_import_keys.request_id = $1
+                AND warehouse_import_keys.key = warehouse_data_{warehouse_id}.key
+        )
+        RETURNING *
+        "#
+    ))
+    .bind(request_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    sqlx::query::<_>("DELETE FROM warehouse_import_keys WHERE request_id = $1")
+        .bind(request_id)
+        .execute(&mut *tx)
+        .await?;
+
+    Ok(removed
+        .into_iter()
+        .map(|node| Edge {
+            cursor: node.to_cursor(),
+            node,
+        })
+        .collect())
+}
+
+/// Imports the data of a `DataImported` event, a redelivered event is skipped unless its
+/// import is still running
+pub(super) async fn import_data(
+    db: &PgPool,
+    op: &Operator,
+    pikav: &pikav_client::Client,
+    warehouse_id: &str,
+    metadata: &CommandMetadata,
+    data: &DataImported,
+    created_at: DateTime<Utc>,
+) -> Result<(), SubscirberHandlerError> {
+    let user_ids = room_member_ids(db, warehouse_id).await?;
+
+    // A redelivered import restarts unless it was committed or reported as failed
+    let started = sqlx::query_as::<_, (String,)>(
+        r#"
+        INSERT INTO warehouse_imports (request_id, warehouse_id, user_id, storage_path, format, mode, state, row_count, rows_inserted, rows_updated, rows_deleted, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, 'running', 0, 0, 0, 0, $7)
+        ON CONFLICT (request_id) DO UPDATE SET
+            row_count = 0, rows_inserted = 0, rows_updated = 0, rows_deleted = 0
+        WHERE warehouse_imports.state = 'running'
+        RETURNING request_id
+        "#,
+    )
+    .bind(&metadata.request_id)
+    .bind(warehouse_id)
+    .bind(Uuid::parse_str(&metadata.request_by)?)
+    .bind(&data.storage_path)
+    .bind(data.format.to_string())
+    .bind(data.mode.to_string())
+    .bind(created_at)
+    .fetch_optional(db)
+    .await?;
+
+    if started.is_none() {
+        return Ok(());
+    }
+
+    let res = import_data_file(
+        db,
+        op,
+        pikav,
+        &user_ids,
+        warehouse_id,
+        &metadata.request_id,
+        data,
+        created_at,
+    )
+    .await;
+
+    // An import of invalid data is rolled back and reported to the user instead of being
+    // retried, its counters are reset as none of its rows were committed
+    let (name, import) = match res {
+        Ok(rows) => {
+            publish_imported_rows(db, pikav, &user_ids, warehouse_id, &rows).await?;
+
+            ("import-completed", rows.import)
+        }
+        Err(ImportError::Data(error)) => {
+            let import = sqlx::query_as::<_, WarehouseImport>(
+                r#"
+                UPDATE warehouse_imports SET
+                    state = 'failed', error = $2, completed_at = $3,
+                    row_count = 0, rows_inserted = 0, rows_updated = 0, rows_deleted = 0
+                WHERE request_id = $1 RETURNING *
+                "#,
+            )
+            .bind(&metadata.request_id)
+            .bind(error)
+            .bind(Utc::now())
+            .fetch_one(db)
+            .await?;
+
+            ("import-failed", import)
+        }
+        Err(ImportError::Handler(e)) => {
+            // The import is retried, its progress restarts from the first row
+            sqlx::query::<_>(
+                r#"
+                UPDATE warehouse_imports SET
+                    row_count = 0, rows_inserted = 0, rows_updated = 0, rows_deleted = 0
+                WHERE request_id = $1 AND state = 'running'
+                "#,
+            )
+            .bind(&metadata.request_id)
+            .execute(db)
+            .await?;
+
+            return Err(e);
+        }
+    };
+
+    publish_to_members(
+        pikav,
+        &user_ids,
+        warehouse_id,
+        name,
+        serde_json::to_value(&import)?,
+    );
+
+    Ok(())
+}
+
 pub fn warehouse_data() -> Subscriber {
     Subscriber::new("warehouse-data")
         .filter("warehouse/#")
@@ -76,152 +844,448 @@ pub fn warehouse_data() -> Subscriber {
             async move {
                 let warehouse_event: WarehouseEvent = event.name.parse()?;
                 let metadata = event.to_metadata::<CommandMetadata>()?;
+                let id = aggregate::Warehouse::to_id(event.aggregate_id.to_owned());
 
                 match warehouse_event {
+                    WarehouseEvent::Created => {
+                        let data: Created = event.to_data()?;
+
+                        let warehouse = Warehouse {
+                            id,
+                            room_id: Some(data.room_id),
+                            name: data.name,
+                            description: data.description,
+                            user_id: Uuid::parse_str(&metadata.request_by)?,
+                            schema: None,
+                            created_at: event.created_at,
+                        };
+
+                        let mut tx = db.begin().await?;
+
+                        let res = sqlx::query::<_>(
+                            "INSERT INTO warehouses (id, room_id, name, description, user_id, created_at) VALUES ($1, $2, $3, $4, $5, $6)",
+                        )
+                        .bind(&warehouse.id)
+                        .bind(&warehouse.room_id)
+                        .bind(&warehouse.name)
+                        .bind(&warehouse.description)
+                        .bind(warehouse.user_id)
+                        .bind(warehouse.created_at)
+                        .execute(&mut *tx)
+                        .await;
+
+                        if let Err(e) = res {
+                            tx.rollback().await?;
+                            return Err(e.into());
+                        }
+
+                        let res = sqlx::query::<_>(
+                            &format!(r#"
+                            CREATE TABLE warehouse_data_{}
+                            (
+                                id VARCHAR(21) NOT NULL PRIMARY KEY,
+                                key VARCHAR(50) NOT NULL,
+                                data jsonb NOT NULL,
+                                search TEXT[] NULL,
+                                created_at timestamptz NOT NULL,
+                                updated_at timestamptz NULL
+                            )
+                            "#, warehouse.id),
+                        )
+                        .execute(&mut *tx)
+                        .await;
+
+                        if let Err(e) = res {
+                            tx.rollback().await?;
+                            return Err(e.into());
+                        }
+
+                        let res = sqlx::query::<_>(
+                            &format!("CREATE UNIQUE INDEX ON warehouse_data_{} (key)", warehouse.id),
+                        )
+                        .execute(&mut *tx)
+                        .await;
+
+                        if let Err(e) = res {
+                            tx.rollback().await?;
+                            return Err(e.into());
+                        }
+
+                        let res = sqlx::query::<_>(
+                            &format!("CREATE INDEX warehouse_data_{0}_search_idx ON warehouse_data_{0} USING GIN (search)", warehouse.id),
+                        )
+                        .execute(&mut *tx)
+                        .await;
+
+                        if let Err(e) = res {
+                            tx.rollback().await?;
+                            return Err(e.into());
+                        }
+
+                        let res = sqlx::query::<_>(
+                            &format!("CREATE INDEX warehouse_data_{0}_data_idx ON warehouse_data_{0} USING GIN (data)", warehouse.id),
+                        )
+                        .execute(&mut *tx)
+                        .await;
+
+                        if let Err(e) = res {
+                            tx.rollback().await?;
+                            return Err(e.into());
+                        }
+
+                        tx.commit().await?;
+
+                        let user_ids = room_member_ids(&db, &warehouse.id).await?;
+
+                        publish_to_members(
+                            &pikav,
+                            &user_ids,
+                            &warehouse.id,
+                            "created",
+                            serde_json::to_value(&warehouse)?,
+                        );
+                    }
                     WarehouseEvent::DataImported => {
                         let data: DataImported = event.to_data()?;
 
-                        let import_data =
-                            read_import_data(&op, &data.storage_path)
-                                .await
-                                .map_err(|e| SubscirberHandlerError::new("warehouse-data.read_import_data", e.to_string()))?;
-
+                        import_data(&db, &op, &pikav, &id, &metadata, &data, event.created_at)
+                            .await?;
+                    }
+                    WarehouseEvent::SchemaDefined => {
+                        let data: SchemaDefined = event.to_data()?;
+                        let schema = Some(Json(data.schema)).filter(|schema| !schema.fields.is_empty());
 
-                        let warehouse_id = sqlx::query_as::<_, (String,)>(
-                            "SELECT id FROM warehouses WHERE user_id = $1",
+                        let warehouse = sqlx::query_as::<_, Warehouse>(
+                            "UPDATE warehouses SET schema = $2 WHERE id = $1 RETURNING *",
                         )
-                        .bind(&metadata.request_by)
-                        .fetch_optional(&db)
+                        .bind(&id)
+                        .bind(&schema)
+                        .fetch_one(&db)
                         .await?;
 
-                        let warehouse_id = match warehouse_id {
-                            Some((id,)) => id,
-                            _ => {
-                                let id = nanoid!().replace('-', "").replace('_', "");
-                                let mut tx = db.begin().await?;
+                        let user_ids = room_member_ids(&db, &id).await?;
 
-                                let res = sqlx::query::<_>(
-                                    "INSERT INTO warehouses (id, user_id, created_at) VALUES ($1, $2, $3)",
-                                )
-                                .bind(&id)
-                                .bind(&metadata.request_by)
-                                .bind(event.created_at)
-                                .execute(&mut *tx)
-                                .await;
-
-                                if let Err(e) = res {
-                                    tx.rollback().await?;
-                                    return Err(e.into());
-                                }
-
-                                let res = sqlx::query::<_>(
-                                    &format!(r#"
-                                    CREATE TABLE warehouse_data_{id}
-                                    (
-                                        id VARCHAR(21) NOT NULL PRIMARY KEY,
-                                        key VARCHAR(50) NOT NULL,
-                                        data json NOT NULL,
-                                        created_at timestamptz NOT NULL,
-                                        updated_at timestamptz NULL
-                                    )
-                                    "#),
-                                )
-                                .execute(&mut *tx)
-                                .await;
+                        publish_to_members(
+                            &pikav,
+                            &user_ids,
+                            &id,
+                            "schema-defined",
+                            serde_json::to_value(&warehouse)?,
+                        );
+                    }
+                    WarehouseEvent::DataDeleted => {
+                        let data: DataDeleted = event.to_data()?;
 
-                                if let Err(e) = res {
-                                    tx.rollback().await?;
-                                    return Err(e.into());
-                                }
+                        // Read the row before deleting it so that subscribers know which edge to remove
+                        let res = QueryAs::<WarehouseData>::new(&format!(
+                            "SELECT * FROM warehouse_data_{id} WHERE key = $1"
+                        ))
+                        .bind(&data.key)
+                        .forward(1, None::<String>)
+                        .fetch_all(&db)
+                        .await?;
 
-                                let res = sqlx::query::<_>(
-                                    &format!("CREATE UNIQUE INDEX ON warehouse_data_{id} (key)"),
-                                )
-                                .execute(&mut *tx)
-                                .await;
+                        if res.edges.is_empty() {
+                            return Ok(());
+                        }
 
-                                if let Err(e) = res {
-                                    tx.rollback().await?;
-                                    return Err(e.into());
-                                }
+                        sqlx::query::<_>(&format!("DELETE FROM warehouse_data_{id} WHERE key = $1"))
+                            .bind(&data.key)
+                            .execute(&db)
+                            .await?;
 
-                                tx.commit().await?;
+                        let user_ids = room_member_ids(&db, &id).await?;
 
-                                id
-                            }
+                        publish_to_members(
+                            &pikav,
+                            &user_ids,
+                            &id,
+                            "removed",
+                            serde_json::to_value(res.edges)?,
+                        );
+                    }
+                    WarehouseEvent::DataPatched => {
+                        let data: DataPatched = event.to_data()?;
+
+                        let row = sqlx::query_as::<_, WarehouseData>(&format!(
+                            "SELECT * FROM warehouse_data_{id} WHERE key = $1"
+                        ))
+                        .bind(&data.key)
+                        .fetch_optional(&db)
+                        .await?;
+
+                        let mut row = match row {
+                            Some(row) => row,
+                            _ => return Ok(()),
                         };
 
-                        for import_data in import_data.chunks(1000).collect::<Vec<&[_]>>() {
-                            let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
-                                format!("INSERT INTO warehouse_data_{warehouse_id} (id, key, data, created_at) ")
-                            );
-
-                            let mut errors = Vec::new();
-                            let mut data_keys = Vec::new();
-
-                            query_builder.push_values(import_data, |mut b, data| {
-                                let key = match data.get("_id").cloned() {
-                                    Some(key) => match key {
-                                        Value::Number(v) => Some(v.to_string()),
-                                        Value::String(v) => Some(v),
-                                        _ => None,
-                                    },
-                                    _ => {
-                                        None
-                                    },
-                                };
-
-                                let key = match key {
-                                    Some(key) => key,
-                                    None => {
-                                        errors.push(SubscirberHandlerError::new("warehouse-data.query_builder.push_values.id", "missing field _id"));
-                                        return
-                                    },
-                                };
-
-                                let data = match serde_json::to_value(data) {
-                                    Ok(data) => data,
-                                    Err(e) => {
-                                        errors.push(SubscirberHandlerError::new("warehouse-data.query_builder.push_values.serde", e.to_string()));
-                                        return
-                                    },
-                                };
-
-                                data_keys.push(key.to_owned());
-
-                                b.push_bind(nanoid!())
-                                .push_bind(key)
-                                .push_bind(data)
-                                .push_bind(event.created_at);
-                            });
-
-                            if let Some(e) = errors.first() {
-                                return Err(e.clone())
-                            }
+                        merge_patch(&mut row.data, &serde_json::to_value(data.data)?);
 
-                            query_builder.push(r#"
-                                ON CONFLICT (key)
-                                DO UPDATE SET data = EXCLUDED.data, updated_at = EXCLUDED.created_at
-                            "#);
+                        sqlx::query::<_>(&format!(
+                            "UPDATE warehouse_data_{id} SET data = $2, search = $3, updated_at = $4 WHERE key = $1"
+                        ))
+                        .bind(&data.key)
+                        .bind(&row.data)
+                        .bind(search_words(&row.data))
+                        .bind(event.created_at)
+                        .execute(&db)
+                        .await?;
 
-                            query_builder.build().execute(&db).await?;
+                        let res = QueryAs::<WarehouseData>::new(&format!(
+                            "SELECT * FROM warehouse_data_{id} WHERE key = $1"
+                        ))
+                        .bind(&data.key)
+                        .forward(1, None::<String>)
+                        .fetch_all(&db)
+                        .await?;
 
-                            let res = QueryAs::<WarehouseData>::new(&format!(
-                                "SELECT * FROM warehouse_data_{warehouse_id} WHERE key = ANY($1)"
-                            ))
-                            .bind(&data_keys[..])
-                            .forward(1000, None::<String>)
-                            .fetch_all(&db)
-                            .await?;
+                        let user_ids = room_member_ids(&db, &id).await?;
 
-                            pikav.publish(vec![pikav_client::Event {
-                                user_id: metadata.request_by.to_owned(),
-                                topic: format!("warehouses/{}", warehouse_id),
-                                name: "updated".to_owned(),
-                                data: Some(serde_json::to_value(res.edges)?.into()),
-                                metadata: None,
-                            }]);
-                        }
+                        publish_to_members(
+                            &pikav,
+                            &user_ids,
+                            &id,
+                            "updated",
+                            serde_json::to_value(res.edges)?,
+                        );
+                    }
+                };
+
+                Ok(())
+            }
+            .boxed()
+        })
+}
+
+/// Merges the fields inferred from imported or patched rows with the fields of the
+/// warehouse, returns the fields that changed. The fields are locked so that imports
+/// and patches of the same warehouse are merged one after the other.
+async fn update_fields(
+    tx: &mut Transaction<'_, Postgres>,
+    warehouse_id: &str,
+    inference: Inference,
+    is_import: bool,
+    updated_at: DateTime<Utc>,
+) -> Result<Vec<WarehouseField>, sqlx::Error> {
+    let mut fields = sqlx::query_as::<_, WarehouseField>(
+        "SELECT * FROM warehouse_fields WHERE warehouse_id = $1 FOR UPDATE",
+    )
+    .bind(warehouse_id)
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|field| (field.name.to_owned(), field))
+    .collect::<HashMap<_, _>>();
+
+    let has_fields = !fields.is_empty();
+    let mut updated = Vec::new();
+
+    for inferred in inference.fields() {
+        let field = match fields.remove(&inferred.name) {
+            Some(mut field) => {
+                field.types.extend(inferred.types);
+                field.types.sort();
+                field.types.dedup();
+                field.nullable |= inferred.nullable;
+                field.max_cardinality = field.max_cardinality.max(inferred.cardinality);
+                field
+            }
+            // Rows imported before do not have the new field
+            None => WarehouseField {
+                warehouse_id: warehouse_id.to_owned(),
+                name: inferred.name,
+                types: inferred.types,
+                nullable: inferred.nullable || (is_import && has_fields),
+                max_cardinality: inferred.cardinality,
+                ..Default::default()
+            },
+        };
+
+        updated.push(field);
+    }
+
+    // Imported rows do not have the fields that are missing from the import
+    if is_import {
+        updated.extend(
+            fields
+                .into_values()
+                .filter(|field| !field.nullable)
+                .map(|mut field| {
+                    field.nullable = true;
+                    field
+                }),
+        );
+    }
+
+    for field in updated.iter_mut() {
+        field.field_type = common_type(&field.types);
+        field.updated_at = updated_at;
+
+        sqlx::query::<_>(
+            r#"
+            INSERT INTO warehouse_fields (warehouse_id, name, field_type, types, nullable, max_cardinality, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (warehouse_id, name) DO UPDATE SET
+                field_type = EXCLUDED.field_type,
+                types = EXCLUDED.types,
+                nullable = EXCLUDED.nullable,
+                max_cardinality = EXCLUDED.max_cardinality,
+                updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind(&field.warehouse_id)
+        .bind(&field.name)
+        .bind(&field.field_type)
+        .bind(&field.types)
+        .bind(field.nullable)
+        .bind(field.max_cardinality)
+        .bind(field.updated_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    Ok(updated)
+}
+
+pub fn warehouse_fields() -> Subscriber {
+    Subscriber::new("warehouse-fields")
+        .filter("warehouse/#")
+        .handler(|event, ctx| {
+            let db = ctx.0.read().extract::<PgPool>().clone();
+            let pikav = ctx.0.read().extract::<pikav_client::Client>().clone();
+
+            async move {
+                let warehouse_event: WarehouseEvent = event.name.parse()?;
+                let id = aggregate::Warehouse::to_id(event.aggregate_id.to_owned());
+
+                // Fields of imported rows are updated by the import once its rows are
+                // committed, see `import_data_file`
+                if !matches!(warehouse_event, WarehouseEvent::DataPatched) {
+                    return Ok(());
+                }
+
+                let data: DataPatched = event.to_data()?;
+                let mut inference = Inference::default();
+                inference.scan(&data.data);
+
+                let mut tx = db.begin().await?;
+                let updated =
+                    update_fields(&mut tx, &id, inference, false, event.created_at).await?;
+                tx.commit().await?;
+
+                if updated.is_empty() {
+                    return Ok(());
+                }
+
+                let user_ids = room_member_ids(&db, &id).await?;
+
+                publish_to_members(
+                    &pikav,
+                    &user_ids,
+                    &id,
+                    "fields-updated",
+                    serde_json::to_value(updated)?,
+                );
+
+                Ok(())
+            }
+            .boxed()
+        })
+}
+
+pub fn warehouse_exports() -> Subscriber {
+    Subscriber::new("warehouse-exports")
+        .filter("warehouse-export/#")
+        .handler(|event, ctx| {
+            let db = ctx.0.read().extract::<PgPool>().clone();
+            let pikav = ctx.0.read().extract::<pikav_client::Client>().clone();
+            let op = ctx.0.read().extract::<Operator>().clone();
+
+            async move {
+                let export_event: ExportEvent = event.name.parse()?;
+                let metadata = event.to_metadata::<CommandMetadata>()?;
+                let id = aggregate::Export::to_id(event.aggregate_id.to_owned());
+
+                match export_event {
+                    ExportEvent::Requested => {
+                        let data: ExportRequested = event.to_data()?;
+
+                        sqlx::query::<_>(
+                            r#"
+                            INSERT INTO warehouse_exports (id, room_id, warehouse_id, user_id, format, filter, status, created_at)
+                            VALUES ($1, $2, $3, $4, $5, $6, 'running', $7)
+                            ON CONFLICT (id) DO UPDATE SET status = 'running'
+                            "#,
+                        )
+                        .bind(&id)
+                        .bind(&data.room_id)
+                        .bind(&data.warehouse_id)
+                        .bind(Uuid::parse_str(&metadata.request_by)?)
+                        .bind(data.format.to_string())
+                        .bind(&data.filter)
+                        .bind(event.created_at)
+                        .execute(&db)
+                        .await?;
+
+                        let storage_path = get_export_path(&id, data.format);
+                        let res = export_data(
+                            &db,
+                            &op,
+                            &data.warehouse_id,
+                            data.filter.as_deref(),
+                            data.format,
+                            &storage_path,
+                        )
+                        .await;
+
+                        // A failed export is reported to the user instead of being retried
+                        let (name, export) = match res {
+                            Ok(row_count) => (
+                                "export-completed",
+                                sqlx::query_as::<_, WarehouseExport>(
+                                    r#"
+                                    UPDATE warehouse_exports
+                                    SET status = 'completed', storage_path = $2, row_count = $3, completed_at = $4
+                                    WHERE id = $1 RETURNING *
+                                    "#,
+                                )
+                                .bind(&id)
+                                .bind(&storage_path)
+                                .bind(row_count)
+                                .bind(Utc::now())
+                                .fetch_one(&db)
+                                .await?,
+                            ),
+                            Err(e) => {
+                                let _ = op.remove(vec![storage_path]).await;
+
+                                (
+                                    "export-failed",
+                                    sqlx::query_as::<_, WarehouseExport>(
+                                        r#"
+                                        UPDATE warehouse_exports
+                                        SET status = 'failed', error = $2, completed_at = $3
+                                        WHERE id = $1 RETURNING *
+                                        "#,
+                                    )
+                                    .bind(&id)
+                                    .bind(e)
+                                    .bind(Utc::now())
+                                    .fetch_one(&db)
+                                    .await?,
+                                )
+                            }
+                        };
+
+                        let user_ids = room_member_ids(&db, &data.warehouse_id).await?;
+
+                        publish_to_members(
+                            &pikav,
+                            &user_ids,
+                            &data.warehouse_id,
+                            name,
+                            serde_json::to_value(&export)?,
+                        );
                     }
                 };
 
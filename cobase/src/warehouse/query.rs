@@ -1,21 +1,132 @@
+use std::collections::HashMap;
+
 use actix::{ActorFutureExt, Context, Handler, Message, ResponseActFuture, WrapFuture};
 use evento::{
     query::{Query as QueryAs, QueryArgs, QueryResult},
     CommandError,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{types::Json, PgPool};
 use uuid::Uuid;
 
-use crate::query::Query;
+use crate::query::{Query, DEFAULT_LIMIT};
+
+use super::{
+    filter::Filter,
+    projection::{
+        merge_patch, Warehouse, WarehouseData, WarehouseDataMatch, WarehouseExport, WarehouseField,
+        WarehouseImport,
+    },
+    schema::{RowError, Schema, MAX_ERRORS},
+    search::SearchQuery,
+    service::ImportMode,
+    sort::Sort,
+};
+
+/// Returns an error unless `user_id` joined the room
+async fn check_room_member(db: &PgPool, room_id: &str, user_id: Uuid) -> Result<(), CommandError> {
+    let member = sqlx::query_as::<_, (String,)>(
+        r#"
+        SELECT rooms.id FROM rooms
+        JOIN room_members ON room_members.room_id = rooms.id
+        WHERE rooms.id = $1
+            AND room_members.user_id = $2
+            AND room_members.joined_at IS NOT NULL
+            AND rooms.deleted_at IS NULL
+        "#,
+    )
+    .bind(room_id)
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+
+    match member {
+        Some(_) => Ok(()),
+        None => Err(CommandError::NotFound(format!("room {room_id} not found"))),
+    }
+}
+
+/// Returns the id of a warehouse of the room, the user must have joined the room
+async fn check_warehouse(
+    db: &PgPool,
+    room_id: &str,
+    warehouse_id: &str,
+    user_id: Uuid,
+) -> Result<String, CommandError> {
+    check_room_member(db, room_id, user_id).await?;
+
+    let warehouse =
+        sqlx::query_as::<_, (String,)>("SELECT id FROM warehouses WHERE id = $1 AND room_id = $2")
+            .bind(warehouse_id)
+            .bind(room_id)
+            .fetch_optional(db)
+            .await?;
+
+    match warehouse {
+        Some((id,)) => Ok(id),
+        None => Err(CommandError::NotFound(format!(
+            "warehouse {warehouse_id} not found"
+        ))),
+    }
+}
+
+#[derive(Message, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[rtype(result = "Result<Vec<Warehouse>, CommandError>")]
+pub struct ListWarehousesQuery {
+    pub user_id: Uuid,
+    /// Lists the warehouses of every room the user joined when not set
+    pub room_id: Option<String>,
+}
+
+impl Handler<ListWarehousesQuery> for Query {
+    type Result = ResponseActFuture<Self, Result<Vec<Warehouse>, CommandError>>;
+
+    fn handle(&mut self, msg: ListWarehousesQuery, _ctx: &mut Context<Self>) -> Self::Result {
+        let db = self.pool.clone();
+
+        async move {
+            if let Some(room_id) = &msg.room_id {
+                check_room_member(&db, room_id, msg.user_id).await?;
+            }
+
+            let warehouses = sqlx::query_as::<_, Warehouse>(
+                r#"
+                SELECT warehouses.* FROM warehouses
+                JOIN room_members ON room_members.room_id = warehouses.room_id
+                JOIN rooms ON rooms.id = warehouses.room_id
+                WHERE room_members.user_id = $1
+                    AND room_members.joined_at IS NOT NULL
+                    AND rooms.deleted_at IS NULL
+                    AND ($2::VARCHAR IS NULL OR warehouses.room_id = $2)
+                ORDER BY warehouses.created_at
+                "#,
+            )
+            .bind(msg.user_id)
+            .bind(&msg.room_id)
+            .fetch_all(&db)
+            .await?;
 
-use super::projection::WarehouseData;
+            Ok(warehouses)
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
 
 #[derive(Message, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[rtype(result = "Result<QueryResult<WarehouseData>, CommandError>")]
 pub struct ListWarehouseDataQuery {
     pub user_id: Uuid,
+    pub room_id: String,
+    pub warehouse_id: String,
     pub query_args: QueryArgs,
+    /// Filter expression on the row data, see `Filter`
+    pub filter: Option<String>,
+    /// Sort on the row data, see `Sort`, rows are ordered by creation when not set
+    pub sort: Option<String>,
 }
 
 impl Handler<ListWarehouseDataQuery> for Query {
@@ -26,24 +137,432 @@ impl Handler<ListWarehouseDataQuery> for Query {
 
         async move {
             let warehouse_id =
-                sqlx::query_as::<_, (String,)>("SELECT id FROM warehouses WHERE user_id = $1")
-                    .bind(&msg.user_id.to_string())
-                    .fetch_optional(&db)
-                    .await?;
-
-            let warehouse_id = match warehouse_id {
-                Some((warehouse_id,)) => warehouse_id,
-                None => return Ok(QueryResult::default()),
+                check_warehouse(&db, &msg.room_id, &msg.warehouse_id, msg.user_id).await?;
+
+            let (filter, args) = match msg.filter {
+                Some(filter) => Filter::parse(&filter)?.to_sql("data", 1)?,
+                None => ("true".to_owned(), Vec::new()),
             };
 
-            let res = QueryAs::<WarehouseData>::new(&format!(
-                "SELECT * FROM warehouse_data_{warehouse_id}"
+            // Filtering is done in a subquery so that the cursor conditions apply on its result
+            let sql = format!(
+                "SELECT * FROM (SELECT * FROM warehouse_data_{warehouse_id} WHERE {filter}) AS warehouse_data"
+            );
+
+            if let Some(sort) = msg.sort {
+                return Sort::parse(&sort)?
+                    .fetch(&db, &sql, args, msg.query_args)
+                    .await;
+            }
+
+            let mut query = QueryAs::<WarehouseData>::new(&sql);
+
+            for arg in args {
+                query = query.bind(arg);
+            }
+
+            let res = query.build(msg.query_args).fetch_all(&db).await?;
+
+            Ok(res)
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+#[derive(Message, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[rtype(result = "Result<WarehouseData, CommandError>")]
+pub struct GetWarehouseDataQuery {
+    pub user_id: Uuid,
+    pub room_id: String,
+    pub warehouse_id: String,
+    /// The `_id` provided at import, or the internal id of the row
+    pub key: String,
+}
+
+impl Handler<GetWarehouseDataQuery> for Query {
+    type Result = ResponseActFuture<Self, Result<WarehouseData, CommandError>>;
+
+    fn handle(&mut self, msg: GetWarehouseDataQuery, _ctx: &mut Context<Self>) -> Self::Result {
+        let db = self.pool.clone();
+
+        async move {
+            let warehouse_id =
+                check_warehouse(&db, &msg.room_id, &msg.warehouse_id, msg.user_id).await?;
+
+            let data = sqlx::query_as::<_, WarehouseData>(&format!(
+                "SELECT * FROM warehouse_data_{warehouse_id} WHERE key = $1"
             ))
-            .build(msg.query_args)
+            .bind(&msg.key)
+            .fetch_optional(&db)
+            .await?;
+
+            if let Some(data) = data {
+                return Ok(data);
+            }
+
+            let data = sqlx::query_as::<_, WarehouseData>(&format!(
+                "SELECT * FROM warehouse_data_{warehouse_id} WHERE id = $1"
+            ))
+            .bind(&msg.key)
+            .fetch_optional(&db)
+            .await?;
+
+            data.ok_or_else(|| {
+                CommandError::NotFound(format!(
+                    "data {} not found in warehouse {warehouse_id}",
+                    msg.key
+                ))
+            })
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+#[derive(Message, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[rtype(result = "Result<Vec<WarehouseDataMatch>, CommandError>")]
+pub struct SearchWarehouseDataQuery {
+    pub user_id: Uuid,
+    pub room_id: String,
+    pub warehouse_id: String,
+    /// Words to search in the string and numeric values of the rows, supports quoted
+    /// phrases, `or` and `-` to exclude words
+    pub q: String,
+    pub limit: Option<u16>,
+    pub offset: Option<u32>,
+}
+
+impl Handler<SearchWarehouseDataQuery> for Query {
+    type Result = ResponseActFuture<Self, Result<Vec<WarehouseDataMatch>, CommandError>>;
+
+    fn handle(&mut self, msg: SearchWarehouseDataQuery, _ctx: &mut Context<Self>) -> Self::Result {
+        let db = self.pool.clone();
+
+        async move {
+            let warehouse_id =
+                check_warehouse(&db, &msg.room_id, &msg.warehouse_id, msg.user_id).await?;
+
+            let search = SearchQuery::parse(&msg.q)?;
+            let (condition, args) = search.to_sql("search", 2);
+
+            let sql = format!(
+                r#"
+                SELECT
+                    warehouse_data.*,
+                    (SELECT count(*) FROM unnest(search) AS words (word) WHERE word = ANY($1))::FLOAT4 AS rank
+                FROM warehouse_data_{warehouse_id} AS warehouse_data
+                WHERE {condition}
+                ORDER BY rank DESC, key
+                LIMIT ${} OFFSET ${}
+                "#,
+                args.len() + 2,
+                args.len() + 3
+            );
+
+            let mut query = sqlx::query_as::<_, WarehouseDataMatch>(&sql).bind(search.words());
+
+            for arg in args {
+                query = query.bind(arg);
+            }
+
+            let mut rows = query
+                .bind(i64::from(msg.limit.unwrap_or(DEFAULT_LIMIT)))
+                .bind(i64::from(msg.offset.unwrap_or_default()))
+                .fetch_all(&db)
+                .await?;
+
+            for row in rows.iter_mut() {
+                row.snippet = search.snippet(&row.data.data);
+            }
+
+            Ok(rows)
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+/// Lists the fields detected in the rows imported to a warehouse
+#[derive(Message, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[rtype(result = "Result<Vec<WarehouseField>, CommandError>")]
+pub struct ListWarehouseFieldsQuery {
+    pub user_id: Uuid,
+    pub room_id: String,
+    pub warehouse_id: String,
+}
+
+impl Handler<ListWarehouseFieldsQuery> for Query {
+    type Result = ResponseActFuture<Self, Result<Vec<WarehouseField>, CommandError>>;
+
+    fn handle(&mut self, msg: ListWarehouseFieldsQuery, _ctx: &mut Context<Self>) -> Self::Result {
+        let db = self.pool.clone();
+
+        async move {
+            let warehouse_id =
+                check_warehouse(&db, &msg.room_id, &msg.warehouse_id, msg.user_id).await?;
+
+            let fields = sqlx::query_as::<_, WarehouseField>(
+                "SELECT * FROM warehouse_fields WHERE warehouse_id = $1 ORDER BY name",
+            )
+            .bind(&warehouse_id)
             .fetch_all(&db)
             .await?;
 
-            Ok(res)
+            Ok(fields)
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+#[derive(Message, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[rtype(result = "Result<WarehouseExport, CommandError>")]
+pub struct GetWarehouseExportQuery {
+    pub user_id: Uuid,
+    pub room_id: String,
+    pub id: String,
+}
+
+impl Handler<GetWarehouseExportQuery> for Query {
+    type Result = ResponseActFuture<Self, Result<WarehouseExport, CommandError>>;
+
+    fn handle(&mut self, msg: GetWarehouseExportQuery, _ctx: &mut Context<Self>) -> Self::Result {
+        let db = self.pool.clone();
+
+        async move {
+            check_room_member(&db, &msg.room_id, msg.user_id).await?;
+
+            let export = sqlx::query_as::<_, WarehouseExport>(
+                "SELECT * FROM warehouse_exports WHERE id = $1 AND room_id = $2",
+            )
+            .bind(&msg.id)
+            .bind(&msg.room_id)
+            .fetch_optional(&db)
+            .await?;
+
+            match export {
+                Some(export) => Ok(export),
+                None => Err(CommandError::NotFound(format!(
+                    "export {} not found",
+                    msg.id
+                ))),
+            }
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+#[derive(Message, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[rtype(result = "Result<WarehouseImport, CommandError>")]
+pub struct GetWarehouseImportQuery {
+    pub user_id: Uuid,
+    pub room_id: String,
+    /// Returned by the import commands
+    pub request_id: String,
+}
+
+impl Handler<GetWarehouseImportQuery> for Query {
+    type Result = ResponseActFuture<Self, Result<WarehouseImport, CommandError>>;
+
+    fn handle(&mut self, msg: GetWarehouseImportQuery, _ctx: &mut Context<Self>) -> Self::Result {
+        let db = self.pool.clone();
+
+        async move {
+            check_room_member(&db, &msg.room_id, msg.user_id).await?;
+
+            let import = sqlx::query_as::<_, WarehouseImport>(
+                r#"
+                SELECT warehouse_imports.* FROM warehouse_imports
+                JOIN warehouses ON warehouses.id = warehouse_imports.warehouse_id
+                WHERE warehouse_imports.request_id = $1 AND warehouses.room_id = $2
+                "#,
+            )
+            .bind(&msg.request_id)
+            .bind(&msg.room_id)
+            .fetch_optional(&db)
+            .await?;
+
+            match import {
+                Some(import) => Ok(import),
+                None => Err(CommandError::NotFound(format!(
+                    "import {} not found",
+                    msg.request_id
+                ))),
+            }
+        }
+        .into_actor(self)
+        .boxed_local()
+    }
+}
+
+/// What an import would do to the rows of a warehouse, rows with errors are not counted
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ImportDryRun {
+    pub inserts: usize,
+    pub updates: usize,
+    pub unchanged: usize,
+    /// Existing rows kept with `ImportMode::InsertSkip`
+    pub skipped: usize,
+    /// Rows that are not in the import with `ImportMode::Replace`
+    pub deletes: usize,
+    pub error_count: usize,
+    /// First errors of the rows, the import would fail if any
+    pub errors: Vec<RowError>,
+}
+
+/// Validates the rows of an import and compares them to the existing rows of the
+/// warehouse as written with `mode`, rows sharing an `_id` are compared to the
+/// previous one.
+async fn dry_run_import_data(
+    db: &PgPool,
+    warehouse_id: &str,
+    schema: Option<&Schema>,
+    mode: ImportMode,
+    data: &[HashMap<String, Value>],
+) -> Result<ImportDryRun, CommandError> {
+    let mut report = ImportDryRun::default();
+    let mut errors = Vec::new();
+    let mut rows = Vec::new();
+
+    for (index, row) in data.iter().enumerate() {
+        let key = match row.get("_id") {
+            Some(Value::Number(v)) => Some(v.to_string()),
+            Some(Value::String(v)) => Some(v.to_owned()),
+            _ => None,
+        };
+
+        let mut row_errors = schema
+            .map(|schema| schema.validate(index, row))
+            .unwrap_or_default();
+
+        if key.is_none() && !row_errors.iter().any(|error| error.path == "_id") {
+            row_errors.insert(
+                0,
+                RowError {
+                    index,
+                    path: "_id".to_owned(),
+                    message: "missing field or not (string | number)".to_owned(),
+                },
+            );
+        }
+
+        match (key, row_errors.is_empty()) {
+            (Some(key), true) => rows.push((index, key, serde_json::to_value(row)?)),
+            _ => errors.extend(row_errors),
+        }
+    }
+
+    let keys = rows
+        .iter()
+        .map(|(_, key, _)| key.to_owned())
+        .collect::<Vec<_>>();
+
+    let mut existing = sqlx::query_as::<_, (String, Value)>(&format!(
+        "SELECT key, data FROM warehouse_data_{warehouse_id} WHERE key = ANY($1)"
+    ))
+    .bind(&keys[..])
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .collect::<HashMap<_, _>>();
+
+    if mode == ImportMode::Replace {
+        let (deletes,) = sqlx::query_as::<_, (i64,)>(&format!(
+            "SELECT COUNT(*) FROM warehouse_data_{warehouse_id} WHERE NOT (key = ANY($1))"
+        ))
+        .bind(&keys[..])
+        .fetch_one(db)
+        .await?;
+
+        report.deletes = deletes as usize;
+    }
+
+    for (index, key, mut row) in rows {
+        let current = match existing.get(&key) {
+            Some(current) => current,
+            None => {
+                report.inserts += 1;
+                existing.insert(key, row);
+                continue;
+            }
+        };
+
+        match mode {
+            ImportMode::InsertSkip => {
+                report.skipped += 1;
+                continue;
+            }
+            ImportMode::InsertFail => {
+                errors.push(RowError {
+                    index,
+                    path: "_id".to_owned(),
+                    message: format!("data {key} already exists"),
+                });
+                continue;
+            }
+            ImportMode::Merge => {
+                let patch = row;
+                row = current.clone();
+                merge_patch(&mut row, &patch);
+            }
+            ImportMode::Upsert | ImportMode::Replace => {}
+        };
+
+        if current == &row {
+            report.unchanged += 1;
+        } else {
+            report.updates += 1;
+        }
+
+        existing.insert(key, row);
+    }
+
+    errors.sort_by_key(|error| error.index);
+
+    report.error_count = errors.len();
+    report.errors = errors.into_iter().take(MAX_ERRORS).collect();
+
+    Ok(report)
+}
+
+/// Validates the rows of an import and compares them to the existing rows of the
+/// warehouse without importing them
+#[derive(Message, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[rtype(result = "Result<ImportDryRun, CommandError>")]
+pub struct DryRunImportDataQuery {
+    pub user_id: Uuid,
+    pub room_id: String,
+    pub warehouse_id: String,
+    pub data: Vec<HashMap<String, Value>>,
+    pub mode: ImportMode,
+}
+
+impl Handler<DryRunImportDataQuery> for Query {
+    type Result = ResponseActFuture<Self, Result<ImportDryRun, CommandError>>;
+
+    fn handle(&mut self, msg: DryRunImportDataQuery, _ctx: &mut Context<Self>) -> Self::Result {
+        let db = self.pool.clone();
+
+        async move {
+            let warehouse_id =
+                check_warehouse(&db, &msg.room_id, &msg.warehouse_id, msg.user_id).await?;
+
+            let (schema,) = sqlx::query_as::<_, (Option<Json<Schema>>,)>(
+                "SELECT schema FROM warehouses WHERE id = $1",
+            )
+            .bind(&warehouse_id)
+            .fetch_one(&db)
+            .await?;
+
+            dry_run_import_data(&db, &warehouse_id, schema.as_deref(), msg.mode, &msg.data).await
         }
         .into_actor(self)
         .boxed_local()
@@ -0,0 +1,335 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, NaiveDate};
+use evento::CommandError;
+use parse_display::Display;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Maximum number of row errors reported by `Schema::check_import_data`
+pub(super) const MAX_ERRORS: usize = 100;
+
+const MISSING_FIELD: &str = "required field is missing";
+
+#[derive(Serialize, Deserialize, Display, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[display(style = "lowercase")]
+pub enum FieldType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    /// RFC 3339 date time or `YYYY-MM-DD` string
+    Date,
+    Object,
+    Array,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaField {
+    /// Dot separated path of the field in the row
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub nullable: bool,
+}
+
+/// Typed fields that imported rows must match, fields that are not declared are
+/// accepted unless the schema is strict.
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Schema {
+    pub fields: Vec<SchemaField>,
+    #[serde(default)]
+    pub strict: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RowError {
+    pub index: usize,
+    pub path: String,
+    pub message: String,
+}
+
+impl FieldType {
+    pub(super) fn matches(&self, value: &Value) -> bool {
+        match (self, value) {
+            (FieldType::String, Value::String(_)) => true,
+            (FieldType::Number, Value::Number(_)) => true,
+            (FieldType::Integer, Value::Number(v)) => v.is_i64() || v.is_u64(),
+            (FieldType::Boolean, Value::Bool(_)) => true,
+            (FieldType::Date, Value::String(v)) => {
+                DateTime::parse_from_rfc3339(v).is_ok()
+                    || NaiveDate::parse_from_str(v, "%Y-%m-%d").is_ok()
+            }
+            (FieldType::Object, Value::Object(_)) => true,
+            (FieldType::Array, Value::Array(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+fn value_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn get_path<'a>(row: &'a HashMap<String, Value>, path: &str) -> Option<&'a Value> {
+    let mut fields = path.split('.');
+    let value = row.get(fields.next()?)?;
+
+    fields.try_fold(value, |value, field| value.get(field))
+}
+
+/// Returns whether a JSON merge patch removes the field at `path`, either with a `null`
+/// value or by setting a parent to `null` or to a value that is not an object
+fn removes_path(patch: &HashMap<String, Value>, path: &str) -> bool {
+    let mut fields = path.split('.');
+    let mut value = match fields.next().and_then(|field| patch.get(field)) {
+        Some(value) => value,
+        None => return false,
+    };
+
+    loop {
+        match (value, fields.next()) {
+            (Value::Null, _) => return true,
+            (_, None) => return false,
+            (Value::Object(object), Some(field)) => match object.get(field) {
+                Some(field_value) => value = field_value,
+                None => return false,
+            },
+            (_, Some(_)) => return true,
+        }
+    }
+}
+
+/// Removes the `null` values of a JSON merge patch, at any depth
+fn without_nulls(value: &Value) -> Option<Value> {
+    match value {
+        Value::Null => None,
+        Value::Object(object) => Some(Value::Object(
+            object
+                .iter()
+                .filter_map(|(key, value)| Some((key.to_owned(), without_nulls(value)?)))
+                .collect(),
+        )),
+        value => Some(value.clone()),
+    }
+}
+
+impl Schema {
+    /// Checks that field names are valid and unique, and that `_id` keeps a valid key type
+    pub fn check(&self) -> Result<(), CommandError> {
+        let mut names = HashSet::new();
+
+        for field in self.fields.iter() {
+            if field.name.split('.').any(|v| v.is_empty()) {
+                return Err(CommandError::BadRequest(format!(
+                    "Invalid schema field {}",
+                    field.name
+                )));
+            }
+
+            if !names.insert(field.name.as_str()) {
+                return Err(CommandError::BadRequest(format!(
+                    "Schema field {} is declared twice",
+                    field.name
+                )));
+            }
+
+            if field.name == "_id"
+                && (field.nullable
+                    || !matches!(
+                        field.field_type,
+                        FieldType::String | FieldType::Number | FieldType::Integer
+                    ))
+            {
+                return Err(CommandError::BadRequest(
+                    "Schema field _id must be a string, number or integer".to_owned(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the errors of a row, `index` is its position in the import
+    pub fn validate(&self, index: usize, row: &HashMap<String, Value>) -> Vec<RowError> {
+        let mut errors = Vec::new();
+
+        for field in self.fields.iter() {
+            let message = match get_path(row, &field.name) {
+                None if field.required => MISSING_FIELD.to_owned(),
+                None => continue,
+                Some(Value::Null) if field.nullable => continue,
+                Some(value) if field.field_type.matches(value) => continue,
+                Some(value) => format!("expected {}, got {}", field.field_type, value_type(value)),
+            };
+
+            errors.push(RowError {
+                index,
+                path: field.name.to_owned(),
+                message,
+            });
+        }
+
+        if self.strict {
+            let mut keys = row
+                .keys()
+                .filter(|key| {
+                    key.as_str() != "_id"
+                        && !self
+                            .fields
+                            .iter()
+                            .any(|field| field.name.split('.').next() == Some(key.as_str()))
+                })
+                .collect::<Vec<_>>();
+
+            keys.sort();
+
+            errors.extend(keys.into_iter().map(|key| RowError {
+                index,
+                path: key.to_owned(),
+                message: "field is not declared in the schema".to_owned(),
+            }));
+        }
+
+        errors
+    }
+
+    /// Validates rows of an import, `offset` is added to the reported index when rows
+    /// are sent in batches. The error is a JSON object with the errors of each row.
+    pub fn check_import_data(
+        &self,
+        data: &[HashMap<String, Value>],
+        offset: usize,
+    ) -> Result<(), CommandError> {
+        let errors = data
+            .iter()
+            .enumerate()
+            .flat_map(|(index, row)| self.validate(offset + index, row))
+            .collect::<Vec<_>>();
+
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        Err(schema_error(errors))
+    }
+
+    /// Validates the fields of a JSON merge patch, `null` removes a field and its
+    /// descendants
+    pub fn check_patch(&self, data: &HashMap<String, Value>) -> Result<(), CommandError> {
+        let fields = data
+            .iter()
+            .filter_map(|(key, value)| Some((key.to_owned(), without_nulls(value)?)))
+            .collect::<HashMap<_, _>>();
+
+        // A patch only contains the changed fields
+        let mut errors = self
+            .validate(0, &fields)
+            .into_iter()
+            .filter(|error| error.message != MISSING_FIELD)
+            .collect::<Vec<_>>();
+
+        errors.extend(
+            self.fields
+                .iter()
+                .filter(|field| field.required && removes_path(data, &field.name))
+                .map(|field| RowError {
+                    index: 0,
+                    path: field.name.to_owned(),
+                    message: "required field cannot be removed".to_owned(),
+                }),
+        );
+
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        Err(schema_error(errors))
+    }
+}
+
+fn schema_error(errors: Vec<RowError>) -> CommandError {
+    let count = errors.len();
+    let errors = errors.into_iter().take(MAX_ERRORS).collect::<Vec<_>>();
+
+    let payload = serde_json::json!({
+        "message": "Data does not match the warehouse schema",
+        "count": count,
+        "errors": errors,
+    });
+
+    CommandError::BadRequest(payload.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn patch_errors(schema: &Schema, patch: Value) -> Vec<String> {
+        match schema.check_patch(&serde_json::from_value(patch).unwrap()) {
+            Ok(_) => Vec::new(),
+            Err(CommandError::BadRequest(payload)) => serde_json::from_str::<Value>(&payload)
+                .unwrap()["errors"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|error| {
+                    format!(
+                        "{}: {}",
+                        error["path"].as_str().unwrap(),
+                        error["message"].as_str().unwrap()
+                    )
+                })
+                .collect(),
+            Err(e) => panic!("unexpected error {e:?}"),
+        }
+    }
+
+    #[test]
+    fn check_patch_removes_descendants() {
+        let field = |name: &str, required: bool| SchemaField {
+            name: name.to_owned(),
+            field_type: FieldType::String,
+            required,
+            nullable: false,
+        };
+
+        let schema = Schema {
+            fields: vec![field("address.city", true), field("address.zip", false)],
+            strict: false,
+        };
+
+        let removed = vec!["address.city: required field cannot be removed".to_owned()];
+
+        assert_eq!(patch_errors(&schema, json!({ "address": null })), removed);
+        assert_eq!(
+            patch_errors(&schema, json!({ "address": "Paris" })),
+            removed
+        );
+        assert_eq!(
+            patch_errors(&schema, json!({ "address": { "city": null } })),
+            removed
+        );
+        assert!(patch_errors(&schema, json!({ "address": { "zip": null } })).is_empty());
+        assert!(patch_errors(&schema, json!({ "address": { "city": "Lyon" } })).is_empty());
+        assert_eq!(
+            patch_errors(&schema, json!({ "address": { "zip": 75001 } })),
+            vec!["address.zip: expected string, got number".to_owned()]
+        );
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::HashSet;
+
+use evento::CommandError;
+use serde_json::Value;
+
+use super::filter::FilterArg;
+
+/// Maximum number of words of a snippet fragment
+const FRAGMENT_WORDS: usize = 20;
+
+/// Maximum number of fragments of a snippet
+const MAX_FRAGMENTS: usize = 3;
+
+/// Search on the string and numeric values of warehouse rows, for example
+/// `"john doe" paris or lyon -archived`.
+///
+/// Words must all match, quoted phrases match consecutive words, `or` matches either
+/// side and `-` excludes a word or a phrase. Words are compared in lower case and are
+/// separated by any character that is not alphanumeric.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchQuery {
+    groups: Vec<SearchGroup>,
+}
+
+/// Terms of a search that must all match, a term of several words is a phrase
+#[derive(Debug, Clone, Default, PartialEq)]
+struct SearchGroup {
+    include: Vec<Vec<String>>,
+    exclude: Vec<Vec<String>>,
+}
+
+impl SearchGroup {
+    fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+}
+
+impl SearchQuery {
+    pub fn parse(input: &str) -> Result<Self, CommandError> {
+        let mut groups = Vec::new();
+        let mut group = SearchGroup::default();
+        let mut chars = input.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if c.is_whitespace() {
+                continue;
+            }
+
+            let exclude = c == '-';
+            let c = match exclude {
+                true => match chars.next() {
+                    Some(c) => c,
+                    None => break,
+                },
+                false => c,
+            };
+
+            let mut term = String::new();
+
+            if c == '"' {
+                while let Some(c) = chars.next_if(|c| *c != '"') {
+                    term.push(c);
+                }
+
+                chars.next();
+            } else {
+                term.push(c);
+
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    term.push(c);
+                }
+
+                if !exclude && term.eq_ignore_ascii_case("or") {
+                    if !group.is_empty() {
+                        groups.push(std::mem::take(&mut group));
+                    }
+
+                    continue;
+                }
+            }
+
+            let words = words(&term);
+
+            match (exclude, words.is_empty()) {
+                (_, true) => {}
+                (true, false) => group.exclude.push(words),
+                (false, false) => group.include.push(words),
+            }
+        }
+
+        if !group.is_empty() {
+            groups.push(group);
+        }
+
+        if groups.is_empty() {
+            return Err(CommandError::BadRequest("q must not be empty".to_owned()));
+        }
+
+        Ok(Self { groups })
+    }
+
+    /// Distinct words that rows should contain, used to rank and highlight the rows
+    pub fn words(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+
+        self.groups
+            .iter()
+            .flat_map(|group| group.include.iter().flatten())
+            .filter(|word| seen.insert(word.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns a sql condition on the words `column`, see `search_words`, with
+    /// placeholders starting at `$offset`, along with the values to bind in order.
+    pub fn to_sql(&self, column: &str, offset: usize) -> (String, Vec<FilterArg>) {
+        let mut args = Vec::new();
+        let mut arg = |value: FilterArg| {
+            args.push(value);
+            format!("${}", offset + args.len() - 1)
+        };
+
+        // Phrases are found in the words joined by spaces, words of different values are
+        // separated by two spaces
+        let text = format!("(' ' || array_to_string({column}, ' ') || ' ')");
+
+        let groups = self
+            .groups
+            .iter()
+            .map(|group| {
+                let mut conditions = Vec::new();
+
+                let words = group.include.iter().flatten().cloned().collect::<Vec<_>>();
+
+                if !words.is_empty() {
+                    conditions.push(format!("{column} @> {}", arg(FilterArg::Words(words))));
+                }
+
+                let words = group
+                    .exclude
+                    .iter()
+                    .filter(|term| term.len() == 1)
+                    .flatten()
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                if !words.is_empty() {
+                    conditions.push(format!(
+                        "NOT ({column} && {})",
+                        arg(FilterArg::Words(words))
+                    ));
+                }
+
+                for (op, phrase) in group
+                    .include
+                    .iter()
+                    .map(|term| (">", term))
+                    .chain(group.exclude.iter().map(|term| ("=", term)))
+                    .filter(|(_, term)| term.len() > 1)
+                {
+                    let phrase = arg(FilterArg::Text(format!(" {} ", phrase.join(" "))));
+                    conditions.push(format!("strpos({text}, {phrase}) {op} 0"));
+                }
+
+                format!("({})", conditions.join(" AND "))
+            })
+            .collect::<Vec<_>>();
+
+        (format!("({})", groups.join(" OR ")), args)
+    }
+
+    /// Values of `data` that contain the searched words, HTML escaped with the words
+    /// wrapped in `<mark>` tags. Long values are cut around the first searched word.
+    pub fn snippet(&self, data: &Value) -> Option<String> {
+        let words = self.words().into_iter().collect::<HashSet<_>>();
+        let mut values = Vec::new();
+        text_values(data, &mut values);
+
+        let fragments = values
+            .iter()
+            .filter_map(|value| fragment(value, &words))
+            .take(MAX_FRAGMENTS)
+            .collect::<Vec<_>>();
+
+        match fragments.is_empty() {
+            true => None,
+            false => Some(fragments.join(" ... ")),
+        }
+    }
+}
+
+/// Words of the string and numeric values of `data` in lower case, stored in the
+/// `search` column of the warehouse data tables. Values are separated by an empty word
+/// so that phrases do not match across values.
+pub fn search_words(data: &Value) -> Vec<String> {
+    let mut values = Vec::new();
+    text_values(data, &mut values);
+
+    let mut search_words = Vec::new();
+
+    for value in values {
+        let value_words = words(&value);
+
+        if value_words.is_empty() {
+            continue;
+        }
+
+        if !search_words.is_empty() {
+            search_words.push(String::new());
+        }
+
+        search_words.extend(value_words);
+    }
+
+    search_words
+}
+
+fn text_values(data: &Value, values: &mut Vec<String>) {
+    match data {
+        Value::String(v) => values.push(v.to_owned()),
+        Value::Number(v) => values.push(v.to_string()),
+        Value::Array(v) => v.iter().for_each(|v| text_values(v, values)),
+        Value::Object(v) => v.values().for_each(|v| text_values(v, values)),
+        _ => {}
+    }
+}
+
+/// Byte ranges of the words of `text`
+fn word_ranges(text: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = None;
+
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                ranges.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(s) = start {
+        ranges.push((s, text.len()));
+    }
+
+    ranges
+}
+
+fn words(text: &str) -> Vec<String> {
+    word_ranges(text)
+        .into_iter()
+        .map(|(start, end)| text[start..end].to_lowercase())
+        .collect()
+}
+
+fn fragment(text: &str, words: &HashSet<String>) -> Option<String> {
+    let ranges = word_ranges(text);
+    let first = ranges
+        .iter()
+        .position(|(start, end)| words.contains(&text[*start..*end].to_lowercase()))?;
+
+    let start = first.saturating_sub(FRAGMENT_WORDS / 4);
+    let ranges = &ranges[start..ranges.len().min(start + FRAGMENT_WORDS)];
+
+    let mut fragment = String::new();
+    let mut pos = ranges[0].0;
+
+    for (start, end) in ranges {
+        let word = &text[*start..*end];
+        fragment.push_str(&escape_html(&text[pos..*start]));
+
+        match words.contains(&word.to_lowercase()) {
+            true => fragment.push_str(&format!("<mark>{}</mark>", escape_html(word))),
+            false => fragment.push_str(&escape_html(word)),
+        }
+
+        pos = *end;
+    }
+
+    Some(fragment)
+}
+
+/// Escapes the data of a snippet, only the `<mark>` tags are markup
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_groups() {
+        let query =
+            SearchQuery::parse(r#""John Doe" paris OR lyon -archived -"old, file""#).unwrap();
+
+        assert_eq!(
+            query.groups,
+            vec![
+                SearchGroup {
+                    include: vec![words("john doe"), words("paris")],
+                    exclude: vec![],
+                },
+                SearchGroup {
+                    include: vec![words("lyon")],
+                    exclude: vec![words("archived"), words("old file")],
+                },
+            ]
+        );
+        assert_eq!(query.words(), strings(&["john", "doe", "paris", "lyon"]));
+        assert_eq!(
+            SearchQuery::parse(" - ").unwrap_err(),
+            CommandError::BadRequest("q must not be empty".to_owned())
+        );
+    }
+
+    #[test]
+    fn to_sql_placeholders() {
+        let (sql, args) = SearchQuery::parse(r#"a "b c" -d or -"e f""#)
+            .unwrap()
+            .to_sql("search", 2);
+
+        let text = "(' ' || array_to_string(search, ' ') || ' ')";
+
+        assert_eq!(
+            sql,
+            format!(
+                "((search @> $2 AND NOT (search && $3) AND strpos({text}, $4) > 0) OR (strpos({text}, $5) = 0))"
+            )
+        );
+        assert_eq!(
+            args,
+            vec![
+                FilterArg::Words(strings(&["a", "b", "c"])),
+                FilterArg::Words(strings(&["d"])),
+                FilterArg::Text(" b c ".to_owned()),
+                FilterArg::Text(" e f ".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn search_words_and_snippet() {
+        let data = json!({
+            "_id": 2,
+            "name": "Jane Doe",
+            "notes": ["Doe family, moved from Paris"],
+            "active": true
+        });
+
+        assert_eq!(
+            search_words(&data),
+            strings(&["2", "", "jane", "doe", "", "doe", "family", "moved", "from", "paris"])
+        );
+
+        let query = SearchQuery::parse("doe paris").unwrap();
+
+        assert_eq!(
+            query.snippet(&data).unwrap(),
+            "Jane <mark>Doe</mark> ... <mark>Doe</mark> family, moved from <mark>Paris</mark>"
+        );
+        assert_eq!(SearchQuery::parse("lyon").unwrap().snippet(&data), None);
+    }
+
+    #[test]
+    fn snippet_escapes_html() {
+        let data = json!({
+            "name": "<b>Doe</b> & \"Sons\"",
+            "notes": "<script>alert('doe')</script>"
+        });
+
+        assert_eq!(
+            SearchQuery::parse("doe").unwrap().snippet(&data).unwrap(),
+            "b&gt;<mark>Doe</mark>&lt;/b&gt; &amp; &quot;Sons ... script&gt;alert(&#39;<mark>doe</mark>&#39;)&lt;/script"
+        );
+    }
+}
@@ -1,10 +1,86 @@
-use opendal::{Error, Operator, Result};
+use arrow_json::writer::record_batches_to_json_rows;
+use evento::CommandError;
+use futures::{
+    io::{BufReader, Lines},
+    AsyncBufReadExt, StreamExt,
+};
+use opendal::{Error, Operator, Reader, Result, Writer};
+use parquet::arrow::{async_reader::ParquetRecordBatchStream, ParquetRecordBatchStreamBuilder};
+use parse_display::Display;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 
-pub fn get_import_data_path() -> String {
+/// Format of an import data file, `Json` files hold a single array of rows
+#[derive(Default, Serialize, Deserialize, Display, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[display(style = "lowercase")]
+pub enum ImportFormat {
+    #[default]
+    Json,
+    /// Newline delimited JSON, one row per line
+    Ndjson,
+    /// Apache Parquet, columns are converted to the fields of the rows
+    Parquet,
+}
+
+/// How imported rows are written when their `_id` already exists in the warehouse
+#[derive(Default, Serialize, Deserialize, Display, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+#[display(style = "kebab-case")]
+pub enum ImportMode {
+    /// Existing rows are kept, their imported rows are skipped
+    InsertSkip,
+    /// The import fails when a row already exists
+    InsertFail,
+    /// Existing rows are replaced by the imported rows
+    #[default]
+    Upsert,
+    /// Imported rows are applied to the existing rows as JSON merge patches
+    Merge,
+    /// Like upsert, and rows that are not in the import are deleted
+    Replace,
+}
+
+pub fn get_import_data_path(user_id: &str) -> String {
+    get_import_file_path(user_id, ImportFormat::Json)
+}
+
+/// Path of an import data file stored in its native format, import data files are
+/// stored under the id of the user who wrote them, see `check_import_path`
+pub fn get_import_file_path(user_id: &str, format: ImportFormat) -> String {
     let id = nanoid::nanoid!();
-    format!("import-data/{id}.tid")
+
+    match format {
+        ImportFormat::Json => format!("import-data/{user_id}/{id}.tid"),
+        _ => format!("import-data/{user_id}/{id}.{format}"),
+    }
+}
+
+/// Path of a CSV file uploaded to be converted to import data
+pub fn get_import_csv_path(user_id: &str) -> String {
+    let id = nanoid::nanoid!();
+    format!("import-data/{user_id}/{id}.csv")
+}
+
+/// Checks that `path` is an import data file of `user_id`, a single file name under
+/// `import-data/{user_id}/`
+pub fn check_import_path(user_id: &str, path: &str) -> std::result::Result<(), CommandError> {
+    let name = path
+        .strip_prefix("import-data/")
+        .and_then(|path| path.strip_prefix(user_id))
+        .and_then(|path| path.strip_prefix('/'));
+
+    match name {
+        Some(name)
+            if !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\']) =>
+        {
+            Ok(())
+        }
+        _ => Err(CommandError::BadRequest(format!(
+            "Invalid import data path {path}"
+        ))),
+    }
 }
 
 pub async fn read_import_data(op: &Operator, path: &str) -> Result<Vec<HashMap<String, Value>>> {
@@ -24,3 +100,169 @@ pub async fn write_import_data(
 
     op.write(path, content).await
 }
+
+/// Writes rows to a new `Ndjson` import data file batch by batch, without holding
+/// every row in memory so that the file can also be read as a stream.
+pub struct ImportDataWriter {
+    writer: Writer,
+    path: String,
+    len: usize,
+}
+
+impl ImportDataWriter {
+    pub async fn new(op: &Operator, user_id: &str) -> Result<Self> {
+        let path = get_import_file_path(user_id, ImportFormat::Ndjson);
+        let writer = op.writer(&path).await?;
+
+        Ok(Self {
+            writer,
+            path,
+            len: 0,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub async fn append(&mut self, data: &[HashMap<String, Value>]) -> Result<()> {
+        let mut content = Vec::new();
+
+        for row in data {
+            serde_json::to_writer(&mut content, row)
+                .map_err(|e| Error::new(opendal::ErrorKind::Unexpected, &e.to_string()))?;
+            content.push(b'\n');
+
+            self.len += 1;
+        }
+
+        self.writer.append(content).await
+    }
+
+    pub async fn close(mut self) -> Result<String> {
+        self.writer.close().await?;
+
+        Ok(self.path)
+    }
+}
+
+enum ImportDataSource {
+    Json(std::vec::IntoIter<HashMap<String, Value>>),
+    Ndjson(Lines<BufReader<Reader>>, usize),
+    Parquet(ParquetRecordBatchStream<Reader>),
+}
+
+/// Reads the rows of an import data file batch by batch, `Ndjson` and `Parquet`
+/// files are decoded while they are read instead of being loaded in memory.
+///
+/// Rows that can't be decoded are reported as a bad request.
+pub struct ImportDataReader {
+    source: ImportDataSource,
+    batch_size: usize,
+}
+
+impl ImportDataReader {
+    pub async fn new(
+        op: &Operator,
+        path: &str,
+        format: ImportFormat,
+        batch_size: usize,
+    ) -> std::result::Result<Self, CommandError> {
+        let source = match format {
+            ImportFormat::Json => ImportDataSource::Json(
+                read_import_data(op, path)
+                    .await
+                    .map_err(internal_err)?
+                    .into_iter(),
+            ),
+            ImportFormat::Ndjson => {
+                let reader = op.reader(path).await.map_err(internal_err)?;
+
+                ImportDataSource::Ndjson(BufReader::new(reader).lines(), 0)
+            }
+            ImportFormat::Parquet => {
+                let reader = op.reader(path).await.map_err(internal_err)?;
+                let stream = ParquetRecordBatchStreamBuilder::new(reader)
+                    .await
+                    .map_err(|e| CommandError::BadRequest(format!("Invalid Parquet: {e}")))?
+                    .with_batch_size(batch_size)
+                    .build()
+                    .map_err(|e| CommandError::BadRequest(format!("Invalid Parquet: {e}")))?;
+
+                ImportDataSource::Parquet(stream)
+            }
+        };
+
+        Ok(Self { source, batch_size })
+    }
+
+    /// Returns the next rows, none when every row has been read
+    pub async fn next(
+        &mut self,
+    ) -> std::result::Result<Option<Vec<HashMap<String, Value>>>, CommandError> {
+        let rows = match &mut self.source {
+            ImportDataSource::Json(rows) => rows.take(self.batch_size).collect::<Vec<_>>(),
+            ImportDataSource::Ndjson(lines, line_number) => {
+                let mut rows = Vec::new();
+
+                while rows.len() < self.batch_size {
+                    let line = match lines.next().await {
+                        Some(line) => {
+                            line.map_err(|e| CommandError::InternalServerErr(e.to_string()))?
+                        }
+                        None => break,
+                    };
+
+                    *line_number += 1;
+
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+
+                    rows.push(serde_json::from_str(&line).map_err(|e| {
+                        CommandError::BadRequest(format!(
+                            "Invalid NDJSON at line {line_number}: {e}"
+                        ))
+                    })?);
+                }
+
+                rows
+            }
+            ImportDataSource::Parquet(stream) => {
+                let mut rows = Vec::new();
+
+                // Row groups may hold empty batches
+                while rows.is_empty() {
+                    let batch = match stream.next().await {
+                        Some(batch) => batch.map_err(|e| {
+                            CommandError::BadRequest(format!("Invalid Parquet: {e}"))
+                        })?,
+                        None => break,
+                    };
+
+                    rows = record_batches_to_json_rows(&[&batch])
+                        .map_err(|e| CommandError::BadRequest(format!("Invalid Parquet: {e}")))?
+                        .into_iter()
+                        .map(|row| row.into_iter().collect())
+                        .collect();
+                }
+
+                rows
+            }
+        };
+
+        Ok(Some(rows).filter(|rows| !rows.is_empty()))
+    }
+}
+
+fn internal_err(e: Error) -> CommandError {
+    CommandError::InternalServerErr(e.to_string())
+}
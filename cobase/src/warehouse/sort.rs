@@ -0,0 +1,293 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use evento::{
+    query::{Edge, PageInfo, QueryArgs, QueryResult},
+    CommandError,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{postgres::PgRow, FromRow, PgPool, Row};
+
+use crate::query::DEFAULT_LIMIT;
+
+use super::{filter::FilterArg, projection::WarehouseData};
+
+/// ISO 8601 dates that exist in the calendar with an optional time and offset, values
+/// are only cast to `TIMESTAMPTZ` when they match so that invalid dates are sorted
+/// last instead of failing the query
+const DATE_PATTERN: &str = concat!(
+    r"^(([1-9]\d{3}|0[1-9]\d{2}|00[1-9]\d|000[1-9])-",
+    r"((0[13578]|1[02])-(0[1-9]|[12]\d|3[01])|(0[469]|11)-(0[1-9]|[12]\d|30)|02-(0[1-9]|1\d|2[0-8]))",
+    // February 29 of leap years
+    r"|(\d{2}(0[48]|[2468][048]|[13579][26])|([13579][26]|[2468][048]|0[48])00)-02-29)",
+    r"([T ]([01]\d|2[0-3]):[0-5]\d(:[0-5]\d(\.\d+)?)?(Z|[+-](0\d|1[0-4])(:?[0-5]\d)?)?)?$"
+);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKind {
+    String,
+    Number,
+    Date,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortField {
+    pub path: Vec<String>,
+    pub kind: SortKind,
+    pub desc: bool,
+}
+
+/// Sort on the JSON data of warehouse rows, the spec is a comma separated list of
+/// dot separated paths, prefixed by `-` for descending order and suffixed by
+/// `:number` or `:date` to cast values, e.g. `-price:number,address.city`.
+///
+/// Rows are sorted by their key last so that the order is stable, values that are
+/// missing or can't be cast are sorted last.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sort {
+    spec: String,
+    fields: Vec<SortField>,
+}
+
+/// Position of a row in a sort, encoded as an opaque cursor
+#[derive(Serialize, Deserialize)]
+struct SortCursor {
+    spec: String,
+    values: Vec<Value>,
+    key: String,
+}
+
+impl Sort {
+    pub fn parse(spec: &str) -> Result<Self, CommandError> {
+        let fields = spec
+            .split(',')
+            .map(|field| {
+                let field = field.trim();
+                let (desc, field) = match field.strip_prefix('-') {
+                    Some(field) => (true, field),
+                    None => (false, field),
+                };
+
+                let (path, kind) = match field.split_once(':') {
+                    Some((path, "number")) => (path, SortKind::Number),
+                    Some((path, "date")) => (path, SortKind::Date),
+                    Some((path, "string")) => (path, SortKind::String),
+                    Some((_, kind)) => return Err(sort_error(format!("unknown type {kind}"))),
+                    None => (field, SortKind::String),
+                };
+
+                let path = path.split('.').map(|v| v.to_owned()).collect::<Vec<_>>();
+
+                if path.iter().any(|v| v.is_empty()) {
+                    return Err(sort_error(format!("invalid field {field}")));
+                }
+
+                Ok(SortField { path, kind, desc })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            spec: spec.to_owned(),
+            fields,
+        })
+    }
+
+    /// Fetches a page of the rows of `from`, a sql query using the placeholders of
+    /// `args`, in the order of the sort.
+    pub async fn fetch(
+        &self,
+        db: &PgPool,
+        from: &str,
+        mut args: Vec<FilterArg>,
+        query_args: QueryArgs,
+    ) -> Result<QueryResult<WarehouseData>, CommandError> {
+        let is_backward = query_args.last.is_some() || query_args.before.is_some();
+        let (limit, cursor) = match is_backward {
+            true => (query_args.last, query_args.before),
+            false => (query_args.first, query_args.after),
+        };
+        let limit = limit.unwrap_or(DEFAULT_LIMIT) as usize;
+        let has_cursor = cursor.is_some();
+
+        let mut columns = Vec::new();
+        let mut orders = Vec::new();
+
+        for (i, field) in self.fields.iter().enumerate() {
+            args.push(FilterArg::Path(field.path.to_owned()));
+
+            let path = format!("${}", args.len());
+            let (value, default) = match field.kind {
+                SortKind::String => (format!("data #>> {path}"), "''"),
+                SortKind::Number => (
+                    format!("CASE WHEN jsonb_typeof(data #> {path}) = 'number' THEN (data #>> {path})::FLOAT8 END"),
+                    "0::FLOAT8",
+                ),
+                SortKind::Date => (
+                    format!("CASE WHEN (data #>> {path}) ~ '{DATE_PATTERN}' THEN (data #>> {path})::TIMESTAMPTZ END"),
+                    "'1970-01-01T00:00:00Z'::TIMESTAMPTZ",
+                ),
+            };
+
+            columns.push(format!(
+                "({value}) IS NULL AS sort_{i}_null, COALESCE({value}, {default}) AS sort_{i}"
+            ));
+
+            // Missing values are last in both directions
+            orders.push((format!("sort_{i}_null"), is_backward));
+            orders.push((format!("sort_{i}"), field.desc != is_backward));
+        }
+
+        orders.push(("key".to_owned(), is_backward));
+
+        let mut conditions = Vec::new();
+
+        if let Some(cursor) = cursor {
+            let values = self.decode_cursor(&cursor)?;
+            let mut placeholders = Vec::new();
+
+            for value in values {
+                args.push(value);
+                placeholders.push(format!("${}", args.len()));
+            }
+
+            // Keyset condition, rows strictly after the cursor in the order of the page
+            for (i, (column, desc)) in orders.iter().enumerate() {
+                let mut condition = orders[..i]
+                    .iter()
+                    .zip(placeholders.iter())
+                    .map(|((column, _), placeholder)| format!("{column} = {placeholder}"))
+                    .collect::<Vec<_>>();
+
+                let op = if *desc { "<" } else { ">" };
+                condition.push(format!("{column} {op} {}", placeholders[i]));
+                conditions.push(format!("({})", condition.join(" AND ")));
+            }
+        }
+
+        let sql = format!(
+            "SELECT * FROM (SELECT *, {} FROM ({from}) AS warehouse_data) AS warehouse_data WHERE {} ORDER BY {} LIMIT {}",
+            columns.join(", "),
+            match conditions.is_empty() {
+                true => "true".to_owned(),
+                false => conditions.join(" OR "),
+            },
+            orders
+                .iter()
+                .map(|(column, desc)| format!("{column} {}", if *desc { "DESC" } else { "ASC" }))
+                .collect::<Vec<_>>()
+                .join(", "),
+            limit + 1
+        );
+
+        let mut query = sqlx::query(&sql);
+
+        for arg in args {
+            query = query.bind(arg);
+        }
+
+        let rows = query.fetch_all(db).await?;
+        let has_more = rows.len() > limit;
+
+        let mut edges = rows
+            .iter()
+            .take(limit)
+            .map(|row| {
+                Ok(Edge {
+                    cursor: self.encode_cursor(row)?,
+                    node: WarehouseData::from_row(row)?,
+                })
+            })
+            .collect::<Result<Vec<_>, CommandError>>()?;
+
+        if is_backward {
+            edges.reverse();
+        }
+
+        Ok(QueryResult {
+            page_info: PageInfo {
+                has_previous_page: if is_backward { has_more } else { has_cursor },
+                has_next_page: if is_backward { has_cursor } else { has_more },
+                start_cursor: edges.first().map(|edge| edge.cursor.to_owned()),
+                end_cursor: edges.last().map(|edge| edge.cursor.to_owned()),
+            },
+            edges,
+        })
+    }
+
+    fn encode_cursor(&self, row: &PgRow) -> Result<String, CommandError> {
+        let mut values = Vec::new();
+
+        for (i, field) in self.fields.iter().enumerate() {
+            let is_null: bool = row.try_get(format!("sort_{i}_null").as_str())?;
+            let column = format!("sort_{i}");
+
+            let value = match field.kind {
+                SortKind::String => Value::from(row.try_get::<String, _>(column.as_str())?),
+                SortKind::Number => Value::from(row.try_get::<f64, _>(column.as_str())?),
+                SortKind::Date => Value::from(
+                    row.try_get::<DateTime<Utc>, _>(column.as_str())?
+                        .to_rfc3339(),
+                ),
+            };
+
+            values.push(Value::Bool(is_null));
+            values.push(value);
+        }
+
+        let cursor = serde_json::to_vec(&SortCursor {
+            spec: self.spec.to_owned(),
+            values,
+            key: row.try_get("key")?,
+        })
+        .map_err(|e| CommandError::InternalServerErr(e.to_string()))?;
+
+        Ok(URL_SAFE_NO_PAD.encode(cursor))
+    }
+
+    /// Returns the values of the cursor in the order of the sort columns
+    fn decode_cursor(&self, cursor: &str) -> Result<Vec<FilterArg>, CommandError> {
+        let cursor = URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|cursor| serde_json::from_slice::<SortCursor>(&cursor).ok())
+            .ok_or_else(|| CommandError::BadRequest("Invalid cursor".to_owned()))?;
+
+        if cursor.spec != self.spec || cursor.values.len() != self.fields.len() * 2 {
+            return Err(CommandError::BadRequest(
+                "Cursor does not match the sort".to_owned(),
+            ));
+        }
+
+        let mut args = Vec::new();
+
+        for (field, values) in self.fields.iter().zip(cursor.values.chunks(2)) {
+            let value = match (field.kind, &values[1]) {
+                (SortKind::String, Value::String(v)) => FilterArg::Text(v.to_owned()),
+                (SortKind::Number, Value::Number(v)) => {
+                    FilterArg::Number(v.as_f64().unwrap_or_default())
+                }
+                (SortKind::Date, Value::String(v)) => FilterArg::Date(
+                    DateTime::parse_from_rfc3339(v)
+                        .map_err(|_| CommandError::BadRequest("Invalid cursor".to_owned()))?
+                        .with_timezone(&Utc),
+                ),
+                _ => return Err(CommandError::BadRequest("Invalid cursor".to_owned())),
+            };
+
+            match &values[0] {
+                Value::Bool(v) => args.push(FilterArg::Bool(*v)),
+                _ => return Err(CommandError::BadRequest("Invalid cursor".to_owned())),
+            }
+
+            args.push(value);
+        }
+
+        args.push(FilterArg::Text(cursor.key));
+
+        Ok(args)
+    }
+}
+
+fn sort_error(message: String) -> CommandError {
+    CommandError::BadRequest(format!("Invalid sort: {message}"))
+}
@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS rooms_user_id_idx;
+
+ALTER TABLE rooms DROP COLUMN IF EXISTS archived_at;
+ALTER TABLE rooms DROP COLUMN IF EXISTS deleted_at;
+ALTER TABLE rooms DROP COLUMN IF EXISTS updated_at;
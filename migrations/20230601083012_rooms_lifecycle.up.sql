@@ -0,0 +1,6 @@
+-- Add up migration script here
+ALTER TABLE rooms ADD COLUMN archived_at timestamptz NULL;
+ALTER TABLE rooms ADD COLUMN deleted_at timestamptz NULL;
+ALTER TABLE rooms ADD COLUMN updated_at timestamptz NULL;
+
+CREATE INDEX ON rooms (user_id);
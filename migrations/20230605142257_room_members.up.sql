@@ -0,0 +1,15 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS room_members
+(
+    room_id VARCHAR(21) NOT NULL,
+    user_id UUID NOT NULL,
+    role VARCHAR(10) NOT NULL,
+    joined_at timestamptz NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (room_id, user_id)
+);
+
+CREATE INDEX ON room_members (user_id);
+
+INSERT INTO room_members (room_id, user_id, role, joined_at, created_at)
+SELECT id, user_id, 'owner', created_at, created_at FROM rooms;
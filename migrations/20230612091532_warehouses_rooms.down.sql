@@ -0,0 +1,7 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS warehouses_room_id_idx;
+DROP INDEX IF EXISTS warehouses_user_id_idx;
+
+ALTER TABLE warehouses DROP COLUMN IF EXISTS room_id;
+
+CREATE UNIQUE INDEX ON warehouses (user_id);
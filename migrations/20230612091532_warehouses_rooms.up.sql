@@ -0,0 +1,9 @@
+-- Add up migration script here
+ALTER TABLE warehouses ADD COLUMN room_id VARCHAR(21) NULL;
+
+-- Unique index of the init migration, named `_idx` by PostgreSQL and `_key` by CockroachDB
+DROP INDEX IF EXISTS warehouses_user_id_idx CASCADE;
+DROP INDEX IF EXISTS warehouses_user_id_key CASCADE;
+
+CREATE INDEX ON warehouses (user_id);
+CREATE INDEX ON warehouses (room_id);
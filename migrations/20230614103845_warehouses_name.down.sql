@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE warehouses DROP COLUMN IF EXISTS name;
+ALTER TABLE warehouses DROP COLUMN IF EXISTS description;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE warehouses ADD COLUMN name VARCHAR(50) NOT NULL DEFAULT '';
+ALTER TABLE warehouses ADD COLUMN description VARCHAR(255) NULL;
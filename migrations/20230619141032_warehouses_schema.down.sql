@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE warehouses DROP COLUMN IF EXISTS schema;
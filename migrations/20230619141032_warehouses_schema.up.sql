@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE warehouses ADD COLUMN schema jsonb NULL;
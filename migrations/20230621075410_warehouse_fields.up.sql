@@ -0,0 +1,12 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS warehouse_fields
+(
+    warehouse_id VARCHAR(21) NOT NULL,
+    name TEXT NOT NULL,
+    field_type VARCHAR(10) NULL,
+    types VARCHAR(10)[] NOT NULL,
+    nullable BOOLEAN NOT NULL,
+    max_cardinality INT8 NOT NULL,
+    updated_at timestamptz NOT NULL,
+    PRIMARY KEY (warehouse_id, name)
+);
@@ -0,0 +1,18 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS warehouse_exports
+(
+    id VARCHAR(21) NOT NULL PRIMARY KEY,
+    room_id VARCHAR(21) NOT NULL,
+    warehouse_id VARCHAR(21) NOT NULL,
+    user_id uuid NOT NULL,
+    format VARCHAR(10) NOT NULL,
+    filter TEXT NULL,
+    status VARCHAR(10) NOT NULL,
+    storage_path VARCHAR(255) NULL,
+    row_count INT8 NULL,
+    error TEXT NULL,
+    created_at timestamptz NOT NULL,
+    completed_at timestamptz NULL
+);
+
+CREATE INDEX ON warehouse_exports (room_id);
@@ -0,0 +1,19 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS warehouse_imports
+(
+    request_id VARCHAR(36) NOT NULL PRIMARY KEY,
+    warehouse_id VARCHAR(21) NOT NULL,
+    user_id uuid NOT NULL,
+    storage_path VARCHAR(255) NOT NULL,
+    format VARCHAR(10) NOT NULL,
+    state VARCHAR(10) NOT NULL,
+    row_count INT8 NOT NULL,
+    rows_inserted INT8 NOT NULL,
+    rows_updated INT8 NOT NULL,
+    error TEXT NULL,
+    created_at timestamptz NOT NULL,
+    updated_at timestamptz NULL,
+    completed_at timestamptz NULL
+);
+
+CREATE INDEX ON warehouse_imports (warehouse_id);
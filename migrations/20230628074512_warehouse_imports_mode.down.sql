@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE warehouse_imports DROP COLUMN IF EXISTS rows_deleted;
+ALTER TABLE warehouse_imports DROP COLUMN IF EXISTS mode;
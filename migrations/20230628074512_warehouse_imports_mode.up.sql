@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE warehouse_imports ADD COLUMN mode VARCHAR(12) NOT NULL DEFAULT 'upsert';
+ALTER TABLE warehouse_imports ADD COLUMN rows_deleted INT8 NOT NULL DEFAULT 0;
@@ -0,0 +1,7 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS warehouse_import_keys
+(
+    request_id VARCHAR(36) NOT NULL,
+    key VARCHAR(50) NOT NULL,
+    PRIMARY KEY (request_id, key)
+);
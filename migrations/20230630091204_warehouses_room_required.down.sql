@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE warehouses ALTER COLUMN room_id DROP NOT NULL;
@@ -0,0 +1,10 @@
+-- Add up migration script here
+
+-- Warehouses created before rooms are keyed by their user in the event store and their
+-- events carry neither a room nor a name, the warehouse commands can't load them. They
+-- are not migrated: copy the rows of their warehouse_data_{id} table and delete them
+-- before upgrading. The cast fails the migration with the id of the first one left.
+SELECT CAST(id || ': warehouse without room, copy its data and delete it before upgrading' AS INT)
+FROM warehouses WHERE room_id IS NULL LIMIT 1;
+
+ALTER TABLE warehouses ALTER COLUMN room_id SET NOT NULL;